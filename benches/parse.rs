@@ -0,0 +1,175 @@
+//! Parsing a Bitstamp `order_book` frame of 100 levels per side, the old way
+//! (owned strings, `str::parse` and a venue name per level) against the
+//! borrowed path the clients use.
+//!
+//! Besides criterion's timings, the allocations per message are printed first:
+//!
+//! ```text
+//! cargo bench --bench parse
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, BenchmarkId, Criterion, Throughput};
+use serde::Deserialize;
+
+use algo_challenge::decimal;
+use algo_challenge::exchange::bitstamp_client;
+use algo_challenge::types::{Exchange, Level, OrderBook, Summary};
+
+const FRAME: &str = include_str!("data/bitstamp_order_book_btcusd.json");
+
+/// Counts every allocation, so that a parse can be measured in allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// The frame as it used to be parsed: every level as owned strings.
+#[derive(Deserialize)]
+struct OwnedEvent {
+    data: OwnedData,
+}
+
+#[derive(Deserialize)]
+struct OwnedData {
+    microtimestamp: String,
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
+}
+
+/// The levels of the old parse path, each tagged with its venue's name.
+struct OwnedBook {
+    _last_updated: String,
+    _bids: Vec<Level>,
+    _asks: Vec<Level>,
+}
+
+fn parse_owned(frame: &str, best_of: usize) -> Option<OwnedBook> {
+    let event = serde_json::from_str::<OwnedEvent>(frame).ok()?;
+    let levels = |side: &[(String, String)]| {
+        side.iter()
+            .take(best_of)
+            .map(|(price, amount)| {
+                Some(Level {
+                    exchange: Exchange::Bitstamp.to_string(),
+                    price: price.parse().ok()?,
+                    amount: amount.parse().ok()?,
+                    venue: Exchange::Bitstamp.id().into(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+    };
+    Some(OwnedBook {
+        _bids: levels(&event.data.bids)?,
+        _asks: levels(&event.data.asks)?,
+        _last_updated: event.data.microtimestamp,
+    })
+}
+
+fn parse_borrowed(frame: &str, best_of: usize) -> Option<OrderBook> {
+    bitstamp_client::parse_order_book(frame, best_of)
+}
+
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn report_allocations() {
+    println!("allocations per message:");
+    for best_of in [10, 100] {
+        let owned = allocations(|| parse_owned(FRAME, best_of).unwrap());
+        let borrowed = allocations(|| parse_borrowed(FRAME, best_of).unwrap());
+        println!("  best {best_of:>3}: owned {owned:>4}, borrowed {borrowed:>4}");
+    }
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bitstamp_order_book_100");
+    group.throughput(Throughput::Bytes(FRAME.len() as u64));
+    for best_of in [10, 100] {
+        group.bench_with_input(BenchmarkId::new("owned", best_of), &best_of, |b, &n| {
+            b.iter(|| parse_owned(black_box(FRAME), n))
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", best_of), &best_of, |b, &n| {
+            b.iter(|| parse_borrowed(black_box(FRAME), n))
+        });
+    }
+    group.finish();
+}
+
+fn decimals(c: &mut Criterion) {
+    let texts = serde_json::from_str::<OwnedEvent>(FRAME)
+        .unwrap()
+        .data
+        .bids
+        .into_iter()
+        .flat_map(|(price, amount)| [price, amount])
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("decimal_200");
+    group.throughput(Throughput::Elements(texts.len() as u64));
+    group.bench_function("str_parse", |b| {
+        b.iter(|| {
+            for text in &texts {
+                black_box(black_box(text).parse::<f64>().ok());
+            }
+        })
+    });
+    group.bench_function("decimal_parse", |b| {
+        b.iter(|| {
+            for text in &texts {
+                black_box(decimal::parse(black_box(text)));
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Merging the books of three venues into levels that carry venue ids only.
+fn merge(c: &mut Criterion) {
+    let book = |exchange| OrderBook {
+        exchange,
+        ..parse_borrowed(FRAME, 100).unwrap()
+    };
+    let books = [
+        book(Exchange::Bitstamp),
+        book(Exchange::Binance),
+        book(Exchange::Kraken),
+    ];
+    c.bench_function("merge_3x100_best_10", |b| {
+        b.iter(|| Summary::merge_all(black_box(books.clone()), 10))
+    });
+}
+
+criterion_group!(benches, parse, decimals, merge);
+
+fn main() {
+    report_allocations();
+    benches();
+    criterion::Criterion::default()
+        .configure_from_args()
+        .final_summary();
+}
@@ -0,0 +1,469 @@
+//! A client of the aggregator's gRPC service, for programs that consume its
+//! merged book rather than print it.
+//!
+//! [`AggregatorClient::summaries`] is a stream of [`MergedBook`]s that outlives
+//! connections: when the server goes away it reconnects with backoff and
+//! subscribes again, and the stream carries on with the next summary. Only a
+//! server refusing the client, e.g. for a wrong API key, ends it.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::pin::Pin;
+use std::time::Duration;
+use std::{fs, io};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::watch;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Code, Request, Status, Streaming};
+
+use crate::types::{
+    Empty, Level, OrderbookAggregatorClient, Quote, Summary, SummaryRequest, Venue, VenueList,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("cannot connect: {0}")]
+    Connect(String),
+    #[error("request failed: {0}")]
+    Status(String),
+    /// The server refused the client: a missing or unknown API key, a venue
+    /// the key may not see, or too many streams. Retrying does not help.
+    #[error("refused by the server: {0}")]
+    Refused(String),
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(e: tonic::transport::Error) -> Self {
+        Self::Connect(e.to_string())
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        match status.code() {
+            Code::Unauthenticated | Code::PermissionDenied | Code::ResourceExhausted => {
+                Self::Refused(status.to_string())
+            }
+            _ => Self::Status(status.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What a [`summaries`](AggregatorClient::summaries) stream does with the
+/// summaries its consumer is too slow for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Read the next summary only once the consumer asks for it, so that a
+    /// slow consumer holds back the server, which skips summaries for it once
+    /// its buffer is full.
+    #[default]
+    Wait,
+    /// Keep reading, and hand the consumer only the latest summary when it
+    /// asks, skipping those it was too slow for.
+    Latest,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// The server's URL, e.g. `http://[::1]:50051`, or `https://` for TLS.
+    pub url: String,
+    pub connect_timeout: Duration,
+    /// The wait before reconnecting, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub overflow: Overflow,
+    /// How to verify the server, and the certificate to present to it, when
+    /// connecting over TLS.
+    pub tls: Option<ClientTls>,
+    /// The key to send with every call, for servers requiring one.
+    pub api_key: Option<String>,
+}
+
+impl ClientConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            connect_timeout: Duration::from_secs(5),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            overflow: Overflow::default(),
+            tls: None,
+            api_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientTls {
+    /// The PEM encoded CA certificate to verify the server with, besides the
+    /// system's trusted roots.
+    pub ca: Option<Vec<u8>>,
+    /// The PEM encoded certificate and private key to present to servers
+    /// requiring mutual TLS.
+    pub identity: Option<(Vec<u8>, Vec<u8>)>,
+    /// The name to verify the server's certificate for, if not the URL's host.
+    pub domain: Option<String>,
+}
+
+impl ClientTls {
+    /// Reads the PEM files at the given paths.
+    pub fn load(ca: Option<&Path>, identity: Option<(&Path, &Path)>) -> io::Result<Self> {
+        Ok(Self {
+            ca: ca.map(fs::read).transpose()?,
+            identity: match identity {
+                Some((cert, key)) => Some((fs::read(cert)?, fs::read(key)?)),
+                None => None,
+            },
+            domain: None,
+        })
+    }
+
+    fn client_config(&self) -> ClientTlsConfig {
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca) = &self.ca {
+            config = config.ca_certificate(Certificate::from_pem(ca));
+        }
+        if let Some((cert, key)) = &self.identity {
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        if let Some(domain) = &self.domain {
+            config = config.domain_name(domain);
+        }
+        config
+    }
+}
+
+/// The merged book of a [`Summary`], with its venues resolved to the
+/// server's [`Venue`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedBook {
+    pub spread: f64,
+    /// The best levels over all venues, best first.
+    pub bids: Vec<(Venue, Quote)>,
+    pub asks: Vec<(Venue, Quote)>,
+    /// The names of the venues whose book is polled over REST, stale or
+    /// missing, see [`Summary::degraded`].
+    pub degraded: Vec<String>,
+}
+
+/// The levels of one venue in a [`MergedBook`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueBook {
+    pub venue: Venue,
+    pub bids: Vec<Quote>,
+    pub asks: Vec<Quote>,
+    /// Whether the venue is listed in [`MergedBook::degraded`].
+    pub degraded: bool,
+}
+
+impl MergedBook {
+    /// Converts `summary`, resolving its venue ids with `venues`. Levels of a
+    /// venue `venues` does not know are dropped.
+    pub fn from_summary(summary: Summary, venues: &VenueMap) -> MergedBook {
+        let side = |levels: Vec<Level>| {
+            levels
+                .into_iter()
+                .filter_map(|level| {
+                    let quote = Quote {
+                        price: level.price,
+                        amount: level.amount,
+                    };
+                    Some((venues.resolve(level)?, quote))
+                })
+                .collect()
+        };
+        MergedBook {
+            spread: summary.spread,
+            bids: side(summary.bids),
+            asks: side(summary.asks),
+            degraded: summary.degraded,
+        }
+    }
+
+    /// The best bid, if any.
+    pub fn best_bid(&self) -> Option<&(Venue, Quote)> {
+        self.bids.first()
+    }
+
+    /// The best ask, if any.
+    pub fn best_ask(&self) -> Option<&(Venue, Quote)> {
+        self.asks.first()
+    }
+
+    /// The merged book split back into the book of each venue, in the order
+    /// the venues first appear in, bids first.
+    pub fn venue_books(&self) -> Vec<VenueBook> {
+        let mut books: Vec<VenueBook> = Vec::new();
+        for (is_bid, side) in [(true, &self.bids), (false, &self.asks)] {
+            for (venue, quote) in side {
+                let quote = *quote;
+                let i = match books.iter().position(|book| book.venue.id == venue.id) {
+                    Some(i) => i,
+                    None => {
+                        books.push(VenueBook {
+                            venue: venue.clone(),
+                            bids: Vec::new(),
+                            asks: Vec::new(),
+                            degraded: self.degraded.contains(&venue.name),
+                        });
+                        books.len() - 1
+                    }
+                };
+                let book = &mut books[i];
+                if is_bid {
+                    book.bids.push(quote);
+                } else {
+                    book.asks.push(quote);
+                }
+            }
+        }
+        books
+    }
+}
+
+/// The names of the server's venues by id.
+///
+/// The names stay local to the map rather than being interned as
+/// [`Exchange`](crate::types::Exchange)s, as a server may list any number of
+/// venues unknown to this process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VenueMap {
+    names: HashMap<u32, String>,
+}
+
+impl VenueMap {
+    /// The name of the server's venue `id`, if it is known.
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    /// The venue of `level`, by the name the server sent if any.
+    fn resolve(&self, level: Level) -> Option<Venue> {
+        let name = if level.exchange.is_empty() {
+            self.get(level.venue)?.to_string()
+        } else {
+            level.exchange
+        };
+        Some(Venue {
+            id: level.venue,
+            name,
+        })
+    }
+
+    /// The ids of the levels of `summary` that cannot be resolved.
+    fn unknown(&self, summary: &Summary) -> HashSet<u32> {
+        summary
+            .bids
+            .iter()
+            .chain(&summary.asks)
+            .filter(|level| level.exchange.is_empty() && !self.names.contains_key(&level.venue))
+            .map(|level| level.venue)
+            .collect()
+    }
+}
+
+impl From<VenueList> for VenueMap {
+    fn from(list: VenueList) -> Self {
+        let names = list
+            .venues
+            .into_iter()
+            .map(|venue| (venue.id, venue.name))
+            .collect();
+        Self { names }
+    }
+}
+
+/// The merged books of an [`AggregatorClient`], across reconnects, ending
+/// with an [`Error::Refused`] if the server refuses the client.
+pub type MergedBookStream = Pin<Box<dyn Stream<Item = Result<MergedBook>> + Send>>;
+
+#[derive(Debug, Clone)]
+pub struct AggregatorClient {
+    config: ClientConfig,
+}
+
+impl AggregatorClient {
+    /// A client of the server at `url` with the default [`ClientConfig`].
+    /// Nothing is connected until it is used.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_config(ClientConfig::new(url))
+    }
+
+    pub fn with_config(config: ClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// The server's venues, connecting once without retrying.
+    pub async fn venues(&self) -> Result<VenueMap> {
+        let mut client = self.connect().await?;
+        venue_map(&mut client).await
+    }
+
+    /// Every summary the server sends, as [`MergedBook`]s.
+    ///
+    /// A failed connection or subscription is retried with backoff, and a
+    /// dropped one resubscribed, so the stream only ends once the server
+    /// refuses the client, after yielding the [`Error::Refused`]. How a slow
+    /// consumer is handled depends on [`ClientConfig::overflow`].
+    pub fn summaries(&self) -> MergedBookStream {
+        let books = resuming(self.clone());
+        match self.config.overflow {
+            Overflow::Wait => Box::pin(books),
+            Overflow::Latest => Box::pin(latest(books)),
+        }
+    }
+
+    async fn connect(&self) -> Result<GrpcClient> {
+        let mut endpoint = Endpoint::from_shared(self.config.url.clone())?
+            .connect_timeout(self.config.connect_timeout);
+        if let Some(tls) = &self.config.tls {
+            endpoint = endpoint.tls_config(tls.client_config())?;
+        }
+        let authorization = match &self.config.api_key {
+            Some(key) => Some(
+                format!("Bearer {key}")
+                    .parse()
+                    .map_err(|_| Error::Connect("invalid API key".to_string()))?,
+            ),
+            None => None,
+        };
+        let channel = endpoint.connect().await?;
+        Ok(OrderbookAggregatorClient::with_interceptor(
+            channel,
+            Authorization(authorization),
+        ))
+    }
+
+    /// Connects, looks up the venues and subscribes to summaries by id.
+    async fn subscribe(&self) -> Result<(GrpcClient, VenueMap, Streaming<Summary>)> {
+        let mut client = self.connect().await?;
+        let venues = venue_map(&mut client).await?;
+        let summaries = client
+            .book_summary(SummaryRequest::default())
+            .await?
+            .into_inner();
+        Ok((client, venues, summaries))
+    }
+}
+
+type GrpcClient = OrderbookAggregatorClient<InterceptedService<Channel, Authorization>>;
+
+/// Sends the API key, if any, with every call.
+#[derive(Debug, Clone)]
+struct Authorization(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Authorization {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
+
+async fn venue_map(client: &mut GrpcClient) -> Result<VenueMap> {
+    Ok(client.venues(Empty {}).await?.into_inner().into())
+}
+
+/// Subscribes, and subscribes again whenever the subscription fails or ends.
+fn resuming(aggregator: AggregatorClient) -> impl Stream<Item = Result<MergedBook>> + Send {
+    stream! {
+        let config = aggregator.config.clone();
+        let mut backoff = config.initial_backoff;
+        loop {
+            match aggregator.subscribe().await {
+                Ok((mut client, mut venues, mut summaries)) => {
+                    backoff = config.initial_backoff;
+                    // The unknown ids the venues were looked up again for.
+                    let mut looked_up = HashSet::new();
+                    loop {
+                        match summaries.message().await {
+                            Ok(Some(summary)) => {
+                                // A venue the map lacks was added since it was
+                                // fetched; look the venues up again, once per
+                                // id, as the server may not list it.
+                                let unknown = venues.unknown(&summary);
+                                if !unknown.is_subset(&looked_up) {
+                                    looked_up.extend(unknown);
+                                    match venue_map(&mut client).await {
+                                        Ok(map) => venues = map,
+                                        Err(e) => tracing::warn!("cannot look up venues: {e}"),
+                                    }
+                                }
+                                yield Ok(MergedBook::from_summary(summary, &venues));
+                            }
+                            Ok(None) => {
+                                tracing::warn!("summaries ended, resubscribing in {backoff:?}");
+                                break;
+                            }
+                            Err(status) => match Error::from(status) {
+                                e @ Error::Refused(_) => {
+                                    yield Err(e);
+                                    return;
+                                }
+                                e => {
+                                    tracing::warn!("summaries failed: {e}, resubscribing in {backoff:?}");
+                                    break;
+                                }
+                            },
+                        }
+                    }
+                }
+                Err(e @ Error::Refused(_)) => {
+                    yield Err(e);
+                    return;
+                }
+                Err(e) => tracing::warn!("{e}, retrying in {backoff:?}"),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(config.max_backoff);
+        }
+    }
+}
+
+/// Reads `books` on its own task, keeping only the latest one for the
+/// returned stream, which ends after the last one once `books` ends. The task
+/// stops once the stream is dropped.
+fn latest<T: Clone + Send + Sync + 'static>(
+    books: impl Stream<Item = T> + Send + 'static,
+) -> impl Stream<Item = T> + Send {
+    let (tx, mut rx) = watch::channel(None::<T>);
+    tokio::spawn(async move {
+        tokio::pin!(books);
+        loop {
+            tokio::select! {
+                book = books.next() => match book {
+                    Some(book) => {
+                        if tx.send(Some(book)).is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                _ = tx.closed() => break,
+            }
+        }
+    });
+    stream! {
+        while rx.changed().await.is_ok() {
+            let book = rx.borrow_and_update().clone();
+            if let Some(book) = book {
+                yield book;
+            }
+        }
+    }
+}
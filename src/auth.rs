@@ -0,0 +1,206 @@
+//! API key authentication of the gRPC service, with per-key permissions.
+//!
+//! Clients send their key as `authorization: Bearer <key>` or `x-api-key:
+//! <key>`. [`Auth::authenticate`] runs as a tonic interceptor and hands the
+//! key's [`Grant`] to the service in the request's extensions; the service
+//! then limits the venues the caller sees and how many `BookSummary` streams
+//! it holds at once.
+
+// Denials are the `Status` tonic's interceptors and handlers return.
+#![allow(clippy::result_large_err)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tonic::{Request, Status};
+
+use crate::types::{name_key, Exchange, Selection};
+
+/// What a key may do, e.g.
+///
+/// ```json
+/// {"key": "s3cr3t", "venues": ["binance", "kraken"], "instruments": ["btcusdt"], "max_streams": 2}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct KeyConfig {
+    pub key: String,
+    /// The venues the key may see, or none for all.
+    #[serde(default)]
+    pub venues: Vec<String>,
+    /// The symbols the key may see, or none for all. A venue is visible only
+    /// if the symbol it aggregates is listed, matched like venue names.
+    #[serde(default)]
+    pub instruments: Vec<String>,
+    /// The most `BookSummary` streams the key may have open at once, or none
+    /// for no limit.
+    #[serde(default)]
+    pub max_streams: Option<usize>,
+}
+
+/// The keys the server accepts, e.g. `{"keys": [{"key": "s3cr3t"}]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct AuthConfig {
+    pub keys: Vec<KeyConfig>,
+}
+
+impl AuthConfig {
+    /// Reads a JSON configuration file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// The permissions of an authenticated key, and its open streams.
+#[derive(Debug)]
+pub struct Grant {
+    /// The visible venues, or `None` for all.
+    venues: Option<Vec<Exchange>>,
+    max_streams: Option<usize>,
+    streams: AtomicUsize,
+}
+
+impl Grant {
+    pub fn allows(&self, venue: Exchange) -> bool {
+        self.venues
+            .as_ref()
+            .is_none_or(|venues| venues.contains(&venue))
+    }
+
+    /// Limits `selection` to the visible venues: all of them when it names
+    /// none, and an error if it names one that is not.
+    pub fn restrict(&self, selection: &mut Selection) -> Result<(), Status> {
+        let Some(venues) = &self.venues else {
+            return Ok(());
+        };
+        if let Some(venue) = selection.venues.iter().find(|v| !venues.contains(v)) {
+            return Err(Status::permission_denied(format!(
+                "venue not permitted: {venue}"
+            )));
+        }
+        if selection.venues.is_empty() {
+            if venues.is_empty() {
+                return Err(Status::permission_denied("no venues are permitted"));
+            }
+            selection.venues = venues.clone();
+        }
+        Ok(())
+    }
+
+    /// Counts a stream as open until the permit is dropped, or fails once
+    /// the key holds `max_streams` of them.
+    pub fn open_stream(self: &Arc<Self>) -> Result<StreamPermit, Status> {
+        let max = self.max_streams.unwrap_or(usize::MAX);
+        self.streams
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                (open < max).then_some(open + 1)
+            })
+            .map_err(|open| {
+                Status::resource_exhausted(format!("too many streams: {open} of {max} open"))
+            })?;
+        Ok(StreamPermit(self.clone()))
+    }
+}
+
+/// An open stream of a [`Grant`].
+#[derive(Debug)]
+pub struct StreamPermit(Arc<Grant>);
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.0.streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The accepted keys and their grants.
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    grants: Arc<HashMap<String, Arc<Grant>>>,
+}
+
+impl Auth {
+    /// The grants of `config`'s keys on a server aggregating `instruments`,
+    /// the symbol of each venue, or an error naming an unknown venue.
+    pub fn new(config: &AuthConfig, instruments: &[(Exchange, String)]) -> Result<Self, String> {
+        let mut grants = HashMap::new();
+        for key in &config.keys {
+            let mut venues = key
+                .venues
+                .iter()
+                .map(|name| Exchange::lookup(name).ok_or_else(|| format!("unknown venue: {name}")))
+                .collect::<Result<Vec<_>, _>>()?;
+            if !key.instruments.is_empty() {
+                let symbols = key
+                    .instruments
+                    .iter()
+                    .map(|s| name_key(s))
+                    .collect::<Vec<_>>();
+                let trading = instruments
+                    .iter()
+                    .filter(|(_, symbol)| symbols.contains(&name_key(symbol)))
+                    .map(|(exchange, _)| *exchange);
+                venues = if key.venues.is_empty() {
+                    trading.collect()
+                } else {
+                    trading
+                        .filter(|exchange| venues.contains(exchange))
+                        .collect()
+                };
+            }
+            let all = key.venues.is_empty() && key.instruments.is_empty();
+            let grant = Grant {
+                venues: (!all).then_some(venues),
+                max_streams: key.max_streams,
+                streams: AtomicUsize::new(0),
+            };
+            grants.insert(key.key.clone(), Arc::new(grant));
+        }
+        Ok(Self {
+            grants: Arc::new(grants),
+        })
+    }
+
+    pub fn grant(&self, key: &str) -> Option<Arc<Grant>> {
+        self.grants.get(key).cloned()
+    }
+
+    /// Admits requests with a known key, adding its [`Grant`] to their
+    /// extensions.
+    pub fn authenticate(&self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let metadata = request.metadata();
+        let key = key_of(
+            metadata
+                .get("authorization")
+                .and_then(|value| value.to_str().ok()),
+            metadata
+                .get("x-api-key")
+                .and_then(|value| value.to_str().ok()),
+        );
+        let grant = self.authorize(key)?;
+        request.extensions_mut().insert(grant);
+        Ok(request)
+    }
+
+    /// The grant of `key`, or an error if there is none or it is not known.
+    pub fn authorize(&self, key: Option<&str>) -> Result<Arc<Grant>, Status> {
+        let Some(key) = key else {
+            return Err(Status::unauthenticated(
+                "missing API key, send `authorization: Bearer <key>`",
+            ));
+        };
+        self.grant(key.trim())
+            .ok_or_else(|| Status::unauthenticated("invalid API key"))
+    }
+}
+
+/// The key of a request with these `authorization` and `x-api-key` headers:
+/// the bearer token if any, else the `x-api-key`.
+pub fn key_of<'a>(authorization: Option<&'a str>, api_key: Option<&'a str>) -> Option<&'a str> {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(api_key)
+}
@@ -1,24 +1,175 @@
-mod types;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
 
-use std::thread;
-use std::time::Duration;
+use futures::StreamExt;
 
-use types::{Empty, OrderbookAggregatorClient};
+use algo_challenge::aggregator_client::{AggregatorClient, ClientConfig, ClientTls, Overflow};
+use algo_challenge::output::{Format, SummaryWriter};
+use algo_challenge::tui;
+use algo_challenge::types::{Quote, Venue};
+
+const SERVER: &str = "http://[::1]:50051";
+const DEPTH: usize = 10;
+
+// cargo run --release --bin client
+// cargo run --release --bin client http://127.0.0.1:50051
+// cargo run --release --bin client -- --tui
+// cargo run --release --bin client -- --format jsonl
+// cargo run --release --bin client -- --output levels.parquet
+// cargo run --release --bin client -- --ca ca.pem https://aggregator.example.com:50051
+// AGGREGATOR_API_KEY=s3cr3t cargo run --release --bin client
+// cargo run --release --bin client -- --ca ca.pem --cert client.pem --key client.key --domain aggregator https://10.0.0.5:50051
+
+struct Args {
+    url: String,
+    tui: bool,
+    format: Option<Format>,
+    output: Option<PathBuf>,
+    ca: Option<PathBuf>,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    domain: Option<String>,
+    api_key: Option<String>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args {
+            url: SERVER.to_string(),
+            tui: false,
+            format: None,
+            output: None,
+            ca: None,
+            cert: None,
+            key: None,
+            domain: None,
+            api_key: std::env::var("AGGREGATOR_API_KEY").ok(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| args.next().ok_or(format!("{flag} needs a value"));
+            match arg.as_str() {
+                "--tui" => parsed.tui = true,
+                "--format" => parsed.format = Some(value("--format")?.parse()?),
+                "--output" => parsed.output = Some(value("--output")?.into()),
+                "--ca" => parsed.ca = Some(value("--ca")?.into()),
+                "--cert" => parsed.cert = Some(value("--cert")?.into()),
+                "--key" => parsed.key = Some(value("--key")?.into()),
+                "--domain" => parsed.domain = Some(value("--domain")?),
+                "--api-key" => parsed.api_key = Some(value("--api-key")?),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag: {flag}")),
+                _ => parsed.url = arg,
+            }
+        }
+        // Without a format, an output file's extension picks it.
+        if parsed.format.is_none() {
+            if let Some(path) = &parsed.output {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                parsed.format = Some(extension.parse()?);
+            }
+        }
+        if parsed.cert.is_some() != parsed.key.is_some() {
+            return Err("--cert and --key go together".to_string());
+        }
+        Ok(parsed)
+    }
+
+    /// The TLS options, if any were given or the URL is `https://`.
+    fn tls(&self) -> io::Result<Option<ClientTls>> {
+        let any = self.ca.is_some() || self.cert.is_some() || self.domain.is_some();
+        if !any && !self.url.starts_with("https://") {
+            return Ok(None);
+        }
+        let identity = self.cert.as_deref().zip(self.key.as_deref());
+        let mut tls = ClientTls::load(self.ca.as_deref(), identity)?;
+        tls.domain = self.domain.clone();
+        Ok(Some(tls))
+    }
+
+    fn config(&self) -> io::Result<ClientConfig> {
+        Ok(ClientConfig {
+            tls: self.tls()?,
+            api_key: self.api_key.clone(),
+            ..ClientConfig::new(self.url.clone())
+        })
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = OrderbookAggregatorClient::connect("http://[::1]:50051").await?;
+async fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!(
+                "Usage: client [--tui | --format jsonl|csv|parquet] [--output <path>] \
+                 [--ca <pem>] [--cert <pem> --key <pem>] [--domain <name>] \
+                 [--api-key <key>] [<url>]"
+            );
+            std::process::exit(2);
+        }
+    };
+    // The server refusing the client ends its summaries with the reason.
+    if let Err(e) = run(args).await {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
 
-    loop {
-        let mut stream = client
-            .book_summary(tonic::Request::new(Empty {}))
-            .await?
-            .into_inner();
+async fn run(args: Args) -> io::Result<()> {
+    if args.tui {
+        // The view only needs the latest summary, however slowly it draws.
+        let client = AggregatorClient::with_config(ClientConfig {
+            overflow: Overflow::Latest,
+            ..args.config()?
+        });
+        return tui::run(client.summaries(), DEPTH).await;
+    }
 
-        while let Some(feature) = stream.message().await? {
-            println!("Response = {:?}", feature);
+    let client = AggregatorClient::with_config(args.config()?);
+    let mut books = client.summaries();
+
+    if let Some(format) = args.format {
+        let out: Box<dyn Write + Send> = match &args.output {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        let mut writer = SummaryWriter::new(format, out)?;
+        // Stop on Ctrl-C, so that the file is finished.
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+        loop {
+            tokio::select! {
+                book = books.next() => match book {
+                    Some(Ok(book)) => writer.write(SystemTime::now(), &book)?,
+                    Some(Err(e)) => {
+                        writer.finish()?;
+                        return Err(io::Error::other(e));
+                    }
+                    None => break,
+                },
+                _ = &mut interrupted => break,
+            }
         }
+        return writer.finish();
+    }
 
-        thread::sleep(Duration::from_secs(2));
+    while let Some(book) = books.next().await {
+        let book = book.map_err(io::Error::other)?;
+        let best = |level: Option<&(Venue, Quote)>| match level {
+            Some((venue, quote)) => {
+                format!("{:.8} x {:.8} ({})", quote.price, quote.amount, venue.name)
+            }
+            None => "-".to_string(),
+        };
+        println!(
+            "spread {:.8}  bid {}  ask {}",
+            book.spread,
+            best(book.best_bid()),
+            best(book.best_ask())
+        );
     }
+    Ok(())
 }
@@ -0,0 +1,118 @@
+//! Which venues and symbols the server aggregates.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::exchange::json_adapter::AdapterConfig;
+use crate::registry::VenueRegistry;
+use crate::streaming;
+use crate::types::name_key;
+
+/// A symbol to subscribe to on a venue, by the venue's registered name.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VenueConfig {
+    pub venue: String,
+    pub symbol: String,
+}
+
+impl VenueConfig {
+    pub fn new(venue: &str, symbol: &str) -> Self {
+        Self {
+            venue: venue.to_string(),
+            symbol: symbol.to_string(),
+        }
+    }
+}
+
+/// The server configuration, e.g.
+///
+/// ```json
+/// {"venues": [{"venue": "binance", "symbol": "btcusdt"}, {"venue": "kraken", "symbol": "BTC/USD"}]}
+/// ```
+///
+/// Levels at the same price are merged in the order the venues are listed.
+/// Each venue may be listed once, as books are kept per venue.
+/// Venues without a client of their own can be declared under `adapters`, see
+/// [`AdapterConfig`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Config {
+    pub venues: Vec<VenueConfig>,
+    #[serde(default)]
+    pub adapters: BTreeMap<String, AdapterConfig>,
+}
+
+impl Default for Config {
+    /// `btcusdt` on Bitstamp and Binance.
+    fn default() -> Self {
+        Self {
+            venues: vec![
+                VenueConfig::new("bitstamp", "btcusdt"),
+                VenueConfig::new("binance", "btcusdt"),
+            ],
+            adapters: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads a JSON configuration file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let config: Self =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        config
+            .check_unique()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Parses `venue=symbol` arguments, e.g. `coinbase=BTC-USD`. Plain symbols
+    /// are taken as the Bitstamp and then the Binance symbol.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut positional = ["bitstamp", "binance"].into_iter();
+        let venues = args
+            .iter()
+            .map(|arg| match arg.split_once('=') {
+                Some((venue, symbol)) => Ok(VenueConfig::new(venue, symbol)),
+                None => positional
+                    .next()
+                    .map(|venue| VenueConfig::new(venue, arg))
+                    .ok_or_else(|| format!("missing venue for symbol: {arg}")),
+            })
+            .collect::<Result<_, _>>()?;
+        let config = Self {
+            venues,
+            adapters: BTreeMap::new(),
+        };
+        config.check_unique()?;
+        Ok(config)
+    }
+
+    /// Rejects venues listed more than once, e.g. `binance=btcusdt
+    /// binance=ethusdt`, whose books would replace each other.
+    fn check_unique(&self) -> Result<(), String> {
+        for (i, venue) in self.venues.iter().enumerate() {
+            let key = name_key(&venue.venue);
+            if let Some(first) = self.venues[..i]
+                .iter()
+                .find(|other| name_key(&other.venue) == key)
+            {
+                return Err(format!(
+                    "venue listed twice: {} ({} and {})",
+                    venue.venue, first.symbol, venue.symbol
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers the venues declared under `adapters` in `registry`.
+    pub fn register_adapters(&self, registry: &mut VenueRegistry) {
+        for (name, adapter) in &self.adapters {
+            streaming::register_json_adapter(registry, name, adapter.clone());
+        }
+    }
+}
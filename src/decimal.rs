@@ -0,0 +1,52 @@
+//! A fast path for the plain decimal strings the exchanges send prices and
+//! amounts as, e.g. `"27123.45000000"`.
+
+/// The powers of ten that are exact in an `f64`.
+const POW10: [f64; 23] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16,
+    1e17, 1e18, 1e19, 1e20, 1e21, 1e22,
+];
+
+/// The largest integer below which every integer is exact in an `f64`.
+const MAX_EXACT: u64 = 1 << 53;
+
+/// Parses `text` like `text.parse::<f64>().ok()`, with the same result.
+///
+/// Decimals whose digits form an integer below 2^53, with at most 22 of them
+/// after the point, are computed as one division of two exact `f64`s, which
+/// IEEE 754 rounds correctly. That covers what the exchanges send; anything
+/// else, such as exponents, falls back to the standard parser.
+pub fn parse(text: &str) -> Option<f64> {
+    let bytes = text.as_bytes();
+    let (negative, digits) = match bytes.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, bytes),
+    };
+
+    let mut mantissa = 0u64;
+    let mut seen_digit = false;
+    let mut fraction_digits = None::<usize>;
+    for &byte in digits {
+        match byte {
+            b'0'..=b'9' => {
+                mantissa = mantissa * 10 + u64::from(byte - b'0');
+                if mantissa >= MAX_EXACT {
+                    return text.parse().ok();
+                }
+                seen_digit = true;
+                if let Some(n) = &mut fraction_digits {
+                    *n += 1;
+                }
+            }
+            b'.' if fraction_digits.is_none() => fraction_digits = Some(0),
+            _ => return text.parse().ok(),
+        }
+    }
+    let scale = fraction_digits.unwrap_or(0);
+    if !seen_digit || scale >= POW10.len() {
+        return text.parse().ok();
+    }
+
+    let value = mantissa as f64 / POW10[scale];
+    Some(if negative { -value } else { value })
+}
@@ -1,36 +1,130 @@
 //! <https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md>
+//! <https://binance-docs.github.io/apidocs/futures/en/#partial-book-depth-streams>
+//! <https://binance-docs.github.io/apidocs/delivery/en/#partial-book-depth-streams>
 
 use crate::exchange::error::Error;
 use std::fmt::Debug;
-use std::pin::Pin;
+use std::future::Future;
+use std::time::Duration;
 
 use async_stream::stream;
-use futures::{stream::SplitSink, StreamExt};
-use futures_util::{SinkExt, Stream};
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 
-use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
 
-use crate::types::{Exchange, Level, OrderBook};
+use crate::exchange::connection::{Connection, Ping};
+use crate::exchange::levels;
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
 
 pub const SUBSCRIBE_METHOD: &str = "SUBSCRIBE";
+pub const UNSUBSCRIBE_METHOD: &str = "UNSUBSCRIBE";
 
 /// The order book levels (i.e. depth) to subscribe to).
 /// Valid <levels> are 5, 10, or 20
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
 pub enum PriceLevels {
     L5 = 5,
     L10 = 10,
     L20 = 20,
 }
 
+/// The update speed of a depth stream. Spot and Binance.US offer 100 and
+/// 1000 ms, the futures markets 100, 250 and 500 ms.
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
 pub enum Speed {
     S1000 = 1000,
+    S500 = 500,
+    S250 = 250,
     S100 = 100,
 }
 
+/// The Binance market a client connects to. Each is its own venue, with its
+/// own endpoint, symbols and depth event schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Market {
+    /// Spot, e.g. `btcusdt`.
+    Spot,
+    /// USDⓈ-M futures (`fstream`), e.g. `btcusdt`.
+    UsdM,
+    /// COIN-M futures (`dstream`), e.g. `btcusd_perp`.
+    CoinM,
+    /// Binance.US spot, e.g. `btcusd`.
+    Us,
+}
+
+impl Market {
+    /// The market whose books are tagged with `exchange`, if any.
+    pub fn from_exchange(exchange: Exchange) -> Option<Market> {
+        match exchange {
+            Exchange::Binance => Some(Market::Spot),
+            Exchange::BinanceUsdm => Some(Market::UsdM),
+            Exchange::BinanceCoinm => Some(Market::CoinM),
+            Exchange::BinanceUs => Some(Market::Us),
+            _ => None,
+        }
+    }
+
+    pub fn exchange(&self) -> Exchange {
+        match self {
+            Market::Spot => Exchange::Binance,
+            Market::UsdM => Exchange::BinanceUsdm,
+            Market::CoinM => Exchange::BinanceCoinm,
+            Market::Us => Exchange::BinanceUs,
+        }
+    }
+
+    /// The public market data endpoint.
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Market::Spot => DEFAULT_MARKET_DATA_WS_BASE_URL,
+            Market::UsdM => USDM_FUTURES_WS_BASE_URL,
+            Market::CoinM => COINM_FUTURES_WS_BASE_URL,
+            Market::Us => US_WS_BASE_URL,
+        }
+    }
+
+    pub fn is_futures(&self) -> bool {
+        matches!(self, Market::UsdM | Market::CoinM)
+    }
+
+    /// The speed of [`Self::depth_stream`] when none is given.
+    pub fn default_speed(&self) -> Speed {
+        if self.is_futures() {
+            Speed::S250
+        } else {
+            Speed::S100
+        }
+    }
+
+    /// The name of the partial book depth stream of `symbol`, e.g.
+    /// `btcusdt@depth20@100ms`. The futures markets name their default 250 ms
+    /// streams without a speed.
+    pub fn depth_stream(&self, symbol: &str, levels: PriceLevels, speed: Speed) -> String {
+        let symbol = symbol.to_lowercase();
+        match speed {
+            Speed::S250 if self.is_futures() => format!("{symbol}@depth{}", levels as u8),
+            _ => format!("{symbol}@depth{}@{}ms", levels as u8, speed as u16),
+        }
+    }
+
+    /// Parses a raw websocket frame of this market from either the raw (`/ws`)
+    /// or the combined (`/stream`) endpoint, returning `None` for frames that
+    /// are not valid depth events.
+    pub fn parse_order_book(&self, frame: &str, best_of: usize) -> Option<OrderBook> {
+        if self.is_futures() {
+            parse_event::<FuturesBookEvent>(frame)?.to_order_book(self.exchange(), best_of)
+        } else {
+            parse_event::<BinanceBookEvent>(frame)?.to_order_book(self.exchange(), best_of)
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct Request<D> {
     pub method: String,
@@ -38,72 +132,251 @@ pub struct Request<D> {
     pub id: u64,
 }
 
+/// A response to a [`Request`]. Errors come either nested, as
+/// `{"error":{"code":2,"msg":".."},"id":1}`, or flat as `{"code":2,"msg":"..","id":1}`.
 #[derive(Debug, Deserialize)]
-pub struct BinanceBookEvent {
+pub struct Response {
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub result: Option<serde_json::Value>,
+    pub error: Option<ErrorResponse>,
+    pub code: Option<i64>,
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub code: i64,
+    pub msg: String,
+}
+
+impl Response {
+    fn into_result(self) -> Result<Option<serde_json::Value>> {
+        let (code, msg) = match (self.error, self.code, self.msg) {
+            (Some(error), _, _) => (error.code, error.msg),
+            (None, Some(code), Some(msg)) => (code, msg),
+            _ => return Ok(self.result),
+        };
+        let lower = msg.to_lowercase();
+        Err(if lower.contains("invalid symbol") || code == -1121 {
+            Error::UnknownSymbol(msg)
+        } else if lower.contains("too many requests") || code == -1003 || code == 429 {
+            Error::RateLimited(msg)
+        } else {
+            Error::SubscriptionRejected(format!("{code}: {msg}"))
+        })
+    }
+}
+
+/// A spot partial depth event, borrowing from the frame it was parsed from.
+#[derive(Debug, Deserialize)]
+pub struct BinanceBookEvent<'a> {
     // partial parse
     #[serde(rename = "lastUpdateId")]
     pub last_update_id: u64,
-    pub bids: Vec<(String, String)>,
-    pub asks: Vec<(String, String)>,
+    /// `[price, quantity]` pairs, best first, see [`levels::top_quotes`].
+    #[serde(borrow)]
+    pub bids: &'a RawValue,
+    #[serde(borrow)]
+    pub asks: &'a RawValue,
+}
+
+impl BinanceBookEvent<'_> {
+    /// Converts the event into an [`OrderBook`] of `exchange` holding the best
+    /// `best_of` levels per side.
+    ///
+    /// Returns `None` if a price or amount is not a number.
+    pub fn to_order_book(&self, exchange: Exchange, best_of: usize) -> Option<OrderBook> {
+        order_book(exchange, self.last_update_id, self.bids, self.asks, best_of)
+    }
+}
+
+/// A futures partial depth event, `depthUpdate` with the book's best levels.
+#[derive(Debug, Deserialize)]
+pub struct FuturesBookEvent<'a> {
+    // partial parse
+    /// The final update id of the event, as `lastUpdateId` on spot.
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b", borrow)]
+    pub bids: &'a RawValue,
+    #[serde(rename = "a", borrow)]
+    pub asks: &'a RawValue,
+}
+
+impl FuturesBookEvent<'_> {
+    /// See [`BinanceBookEvent::to_order_book`].
+    pub fn to_order_book(&self, exchange: Exchange, best_of: usize) -> Option<OrderBook> {
+        order_book(
+            exchange,
+            self.final_update_id,
+            self.bids,
+            self.asks,
+            best_of,
+        )
+    }
+}
+
+fn order_book(
+    exchange: Exchange,
+    update_id: u64,
+    bids: &RawValue,
+    asks: &RawValue,
+    best_of: usize,
+) -> Option<OrderBook> {
+    Some(OrderBook {
+        exchange,
+        last_updated: update_id.to_string(),
+        bids: levels::top_quotes(bids, best_of)?,
+        asks: levels::top_quotes(asks, best_of)?,
+        degraded: false,
+    })
+}
+
+/// A frame of the combined stream endpoint (`/stream`), wrapping the payload of
+/// the stream named `stream`.
+#[derive(Debug, Deserialize)]
+pub struct CombinedEvent<'a, D> {
+    pub stream: &'a str,
+    pub data: D,
+}
+
+/// Just the stream name of a [`CombinedEvent`], for routing.
+#[derive(Debug, Deserialize)]
+struct StreamName<'a> {
+    stream: &'a str,
+}
+
+/// Parses the payload of a frame from either the raw or the combined endpoint.
+fn parse_event<'a, D: Deserialize<'a>>(frame: &'a str) -> Option<D> {
+    match serde_json::from_str::<CombinedEvent<D>>(frame) {
+        Ok(event) => Some(event.data),
+        Err(_) => serde_json::from_str::<D>(frame).ok(),
+    }
+}
+
+/// Parses a raw spot websocket frame, see [`Market::parse_order_book`].
+pub fn parse_order_book(frame: &str, best_of: usize) -> Option<OrderBook> {
+    Market::Spot.parse_order_book(frame, best_of)
+}
+
+/// The name of the spot partial book depth stream of `symbol`, see
+/// [`Market::depth_stream`].
+pub fn depth_stream(symbol: &str, levels: PriceLevels, speed: Speed) -> String {
+    Market::Spot.depth_stream(symbol, levels, speed)
+}
+
+/// The URL of the combined stream endpoint under `base_url`, already subscribed
+/// to `streams`, e.g. `wss://data-stream.binance.vision/stream?streams=a/b`.
+pub fn combined_stream_url(base_url: &str, streams: &[String]) -> String {
+    if streams.is_empty() {
+        format!("{base_url}/stream")
+    } else {
+        format!("{base_url}/stream?streams={}", streams.join("/"))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+fn is_combined_url(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| url.path().trim_end_matches('/') == "/stream")
+}
+
 // Alternative:
 // pub const DEFAULT_WS_BASE_URL: &str = "wss://stream.binance.com:443/ws"
 // Direct link: "wss://stream.binance.com:9443/ws/ethbtc@depth10@100ms";
 #[allow(dead_code)]
 pub const DEFAULT_WS_BASE_URL: &str = "wss://stream.binance.com:9443";
 pub const DEFAULT_MARKET_DATA_WS_BASE_URL: &str = "wss://data-stream.binance.vision";
+pub const USDM_FUTURES_WS_BASE_URL: &str = "wss://fstream.binance.com";
+pub const COINM_FUTURES_WS_BASE_URL: &str = "wss://dstream.binance.com";
+pub const US_WS_BASE_URL: &str = "wss://stream.binance.us:9443";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Binance closes every connection after 24 hours; hand over well before that.
+pub const DEFAULT_MAX_CONNECTION_AGE: Duration = Duration::from_secs(23 * 60 * 60);
 
-/// A WebSocket client for Binance.
+/// A WebSocket client for one Binance [`Market`].
 pub struct BinanceClient {
-    sender: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
-    // The thread_handle will be dropped when the Client drops.
-    #[allow(dead_code)]
-    thread_handle: tokio::task::JoinHandle<()>,
-    pub broadcast: Option<tokio::sync::broadcast::Sender<String>>,
-    pub book_events: Option<Pin<Box<dyn Stream<Item = OrderBook> + Send + Sync>>>,
+    market: Market,
+    connection: Connection,
+    /// How long [`Self::call`] waits for a response.
+    pub request_timeout: Duration,
+    /// How long after connecting [`Self::handover_requested`] resolves.
+    pub max_connection_age: Duration,
+    connected_at: Instant,
     next_id: u64,
 }
 
 impl BinanceClient {
     pub async fn connect(url: &str) -> Result<Self> {
-        let (stream, _) = connect_async(url).await?;
-        let (sender, receiver) = stream.split();
-        let (broadcast_sender, _) = tokio::sync::broadcast::channel::<String>(32);
-        let broadcast = broadcast_sender.clone();
-
-        let thread_handle = tokio::spawn(async move {
-            let mut receiver = receiver;
-
-            while let Some(result) = receiver.next().await {
-                if let Ok(msg) = result {
-                    if let Message::Text(string) = msg {
-                        tracing::debug!("{string}");
-                        if let Err(err) = broadcast_sender.send(string) {
-                            tracing::trace!("{err:?}");
-                            break;
-                        }
-                    }
-                } else {
-                    tracing::error!("{:?}", result);
-                }
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_market(Market::Spot, url, recorder).await
+    }
+
+    /// Connects to `url`, an endpoint of `market`, handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_market(
+        market: Market,
+        url: &str,
+        recorder: Option<Recorder>,
+    ) -> Result<Self> {
+        // Frames of the combined endpoint are wrapped in `CombinedEvent`s naming
+        // their stream; raw endpoint frames go to every subscription.
+        let combined = is_combined_url(url);
+        let route = move |routes: &Registry, frame: &str| {
+            if !combined {
+                routes.dispatch_all(frame);
+            } else if let Ok(event) = serde_json::from_str::<StreamName>(frame) {
+                routes.dispatch(event.stream, frame);
             }
-        });
+        };
+        let connection =
+            Connection::open(url, market.exchange(), Ping::Frame, recorder, route).await?;
 
         Ok(Self {
-            sender,
-            thread_handle,
-            broadcast: Some(broadcast),
-            book_events: None,
+            market,
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_connection_age: DEFAULT_MAX_CONNECTION_AGE,
+            connected_at: Instant::now(),
             next_id: 0,
         })
     }
 
     pub async fn connect_public() -> Result<Self> {
-        let url = format!("{DEFAULT_MARKET_DATA_WS_BASE_URL}/ws");
-        Self::connect(&url).await
+        Self::connect_public_with_recorder(None).await
+    }
+
+    /// Connects to the public combined stream endpoint, so that every
+    /// subscription gets only the frames of its own stream.
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_public_market(Market::Spot, recorder).await
+    }
+
+    /// Connects to the public combined stream endpoint of `market`.
+    pub async fn connect_public_market(market: Market, recorder: Option<Recorder>) -> Result<Self> {
+        let url = combined_stream_url(market.base_url(), &[]);
+        Self::connect_market(market, &url, recorder).await
+    }
+
+    pub fn market(&self) -> Market {
+        self.market
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// Resolves once the connection is `max_connection_age` old and should be
+    /// replaced before Binance drops it.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        tokio::time::sleep_until(self.connected_at + self.max_connection_age)
     }
 
     /// Sends a message to the WebSocket.
@@ -111,30 +384,56 @@ impl BinanceClient {
     where
         R: Serialize,
     {
-        let msg = serde_json::to_string(&req).unwrap();
-        tracing::debug!("{msg}");
-        self.sender.send(Message::Text(msg.to_string())).await?;
-
-        Ok(())
+        self.connection.send(req).await
     }
 
-    /// Performs a remote procedure call.
-    pub async fn call<D>(&mut self, event: impl Into<String>, params: D) -> Result<()>
+    /// Performs a remote procedure call, waiting up to `request_timeout` for the
+    /// response with the matching `id` and returning its `result`.
+    pub async fn call<D>(
+        &mut self,
+        event: impl Into<String>,
+        params: D,
+    ) -> Result<Option<serde_json::Value>>
     where
         D: Debug + Serialize,
     {
+        let id = self.gen_next_id();
         let req = Request {
             method: event.into(),
             params,
-            id: self.gen_next_id(),
+            id,
+        };
+
+        let mut messages_receiver = self.messages();
+        self.send(req).await?;
+
+        let response = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Response>(&msg) {
+                        Ok(response) if response.id == Some(id) => return response.into_result(),
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
         };
-        self.send(req).await
+        tokio::time::timeout(self.request_timeout, response)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
     }
 
     fn gen_next_id(&mut self) -> u64 {
         self.next_id += 1;
         self.next_id
     }
+
+    /// Subscribes to the partial book depth stream of `symbol` on the client's
+    /// market.
+    ///
+    /// Fails if Binance rejects the subscription or does not acknowledge it
+    /// within `request_timeout`.
     // <https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md#partial-book-depth-streams>
     pub async fn subscribe_orderbook(
         &mut self,
@@ -142,48 +441,50 @@ impl BinanceClient {
         levels: PriceLevels,
         speed: Speed,
         best_of: usize,
-    ) {
-        let topic: String = format!("{symbol}@depth{}@{}ms", levels as u8, speed as u16);
+    ) -> Result<Subscription> {
+        let topic = self.market.depth_stream(symbol, levels, speed);
 
-        self.call(SUBSCRIBE_METHOD, vec![topic])
-            .await
-            .expect("cannot send request");
+        // Register before sending the request so that no frame is missed.
+        let subscription = self.order_book_events(&topic, best_of);
+
+        if let Err(e) = self.call(SUBSCRIBE_METHOD, vec![topic]).await {
+            self.connection.registry().remove(&subscription.handle);
+            return Err(e);
+        }
+        Ok(subscription)
+    }
 
-        let mut messages_receiver = self
-            .broadcast
-            .clone()
-            .expect("client not connected")
-            .subscribe();
+    /// Registers a subscription to the depth stream `topic` without sending a
+    /// request, for streams that were subscribed through [`combined_stream_url`].
+    ///
+    /// On the raw endpoint frames carry no stream name, so every subscription
+    /// gets every depth frame; use the combined endpoint to subscribe to more
+    /// than one stream.
+    pub fn order_book_events(&self, topic: &str, best_of: usize) -> Subscription {
+        let (handle, mut frames) = self.connection.registry().register(topic);
 
+        let market = self.market;
         let depth_events = stream! {
-            while let Ok(msg) = messages_receiver.recv().await {
-                if let Ok(msg) = serde_json::from_str::<BinanceBookEvent>(&msg) {
-                    let bids = msg.bids
-                        .iter()
-                        .take(best_of)
-                        .map(|x| (Level {
-                            exchange: Exchange::Binance.to_string(),
-                            price: x.0.parse::<f64>().unwrap(),
-                             amount: x.1.parse::<f64>().unwrap()
-                        }))
-                        .collect();
-                    let asks = msg.asks
-                        .iter()
-                        .take(best_of)
-                        .map(|x| (Level {
-                            exchange: Exchange::Binance.to_string(),
-                            price: x.0.parse::<f64>().unwrap(),
-                             amount: x.1.parse::<f64>().unwrap()
-                        }))
-                        .collect();
-                    let book_event = OrderBook { exchange: Exchange::Binance, last_updated: msg.last_update_id.to_string(), bids, asks };
+            while let Some(msg) = frames.recv().await {
+                if let Some(book_event) = market.parse_order_book(&msg, best_of) {
                     yield book_event;
                 }
             }
         };
 
-        let depth_events = Box::pin(depth_events);
+        Subscription {
+            handle,
+            book_events: Box::pin(depth_events),
+        }
+    }
 
-        self.book_events = Some(depth_events);
+    /// Ends a subscription. Binance is only asked to unsubscribe once no other
+    /// subscription of this client uses the stream.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            self.call(UNSUBSCRIBE_METHOD, vec![handle.channel.clone()])
+                .await?;
+        }
+        Ok(())
     }
 }
@@ -1,20 +1,29 @@
 //! <https://www.bitstamp.net/websocket/v2/>
 
-use std::pin::Pin;
+use std::future::Future;
+use std::time::Duration;
 
 use async_stream::stream;
-use futures::{stream::SplitSink, StreamExt};
-use futures_util::{SinkExt, Stream};
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use serde_json::value::RawValue;
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::types::{Exchange, Level, OrderBook};
+use crate::exchange::connection::{Connection, Ping};
+use crate::exchange::levels;
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
 
 use crate::exchange::error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub const SUBSCRIBE_EVENT: &str = "bts:subscribe";
+pub const SUBSCRIPTION_SUCCEEDED_EVENT: &str = "bts:subscription_succeeded";
+pub const UNSUBSCRIBE_EVENT: &str = "bts:unsubscribe";
+pub const UNSUBSCRIPTION_SUCCEEDED_EVENT: &str = "bts:unsubscription_succeeded";
+pub const ERROR_EVENT: &str = "bts:error";
+/// Sent ahead of maintenance; the connection closes shortly after.
+pub const REQUEST_RECONNECT_EVENT: &str = "bts:request_reconnect";
 
 #[derive(Debug, Serialize)]
 pub struct Request<D> {
@@ -35,70 +44,144 @@ impl SubscribeData {
     }
 }
 
+/// The envelope shared by every message Bitstamp sends.
 #[derive(Debug, Deserialize)]
-pub struct BitstampBookEvent {
-    pub data: BookData,
+pub struct Event {
+    pub event: String,
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
 }
 
+/// Just the routing part of an [`Event`].
 #[derive(Debug, Deserialize)]
-pub struct BookData {
+struct EventHeader {
+    event: String,
+    #[serde(default)]
+    channel: String,
+}
+
+impl Event {
+    /// Maps a `bts:error` event to a typed error.
+    fn into_error(self, channel: &str) -> Error {
+        let message = self.data["message"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let lower = message.to_lowercase();
+        if lower.contains("bad subscription string") || lower.contains("invalid channel") {
+            Error::UnknownSymbol(channel.to_string())
+        } else if lower.contains("rate limit") || lower.contains("too many") {
+            Error::RateLimited(message)
+        } else {
+            Error::SubscriptionRejected(message)
+        }
+    }
+}
+
+/// An order book event, borrowing from the frame it was parsed from.
+#[derive(Debug, Deserialize)]
+pub struct BitstampBookEvent<'a> {
+    #[serde(borrow)]
+    pub data: BookData<'a>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BookData<'a> {
     // parse partially
-    pub timestamp: String,
-    pub microtimestamp: String,
-    pub bids: Vec<(String, String)>,
-    pub asks: Vec<(String, String)>,
+    pub timestamp: &'a str,
+    pub microtimestamp: &'a str,
+    /// `[price, amount]` pairs, best first, see [`levels::top_quotes`].
+    #[serde(borrow)]
+    pub bids: &'a RawValue,
+    #[serde(borrow)]
+    pub asks: &'a RawValue,
+}
+
+impl BitstampBookEvent<'_> {
+    /// Converts the event into an [`OrderBook`] holding the best `best_of` levels per side.
+    ///
+    /// Returns `None` if a price or amount is not a number.
+    pub fn to_order_book(&self, best_of: usize) -> Option<OrderBook> {
+        Some(OrderBook {
+            exchange: Exchange::Bitstamp,
+            last_updated: self.data.microtimestamp.to_string(),
+            bids: levels::top_quotes(self.data.bids, best_of)?,
+            asks: levels::top_quotes(self.data.asks, best_of)?,
+            degraded: false,
+        })
+    }
+}
+
+/// Parses a raw websocket frame, returning `None` for frames that are not valid order book events.
+pub fn parse_order_book(frame: &str, best_of: usize) -> Option<OrderBook> {
+    serde_json::from_str::<BitstampBookEvent>(frame)
+        .ok()?
+        .to_order_book(best_of)
 }
 
 pub const DEFAULT_WS_BASE_URL: &str = "wss://ws.bitstamp.net";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// A WebSocket client for Bitstamp.
 pub struct BitstampClient {
-    sender: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
-    // The thread_handle will be dropped when the Client drops.
-    #[allow(dead_code)]
-    thread_handle: tokio::task::JoinHandle<()>,
-    pub broadcast: Option<tokio::sync::broadcast::Sender<String>>,
-    pub book_events: Option<Pin<Box<dyn Stream<Item = OrderBook> + Send + Sync>>>,
+    connection: Connection,
+    /// How long [`Self::subscribe`] waits for the subscription to be acknowledged.
+    pub request_timeout: Duration,
 }
 
 impl BitstampClient {
     pub async fn connect(url: &str) -> Result<Self> {
-        let (stream, _) = connect_async(url).await?;
-        let (sender, receiver) = stream.split();
-        let (broadcast_sender, _) = tokio::sync::broadcast::channel::<String>(32);
-        let broadcast = broadcast_sender.clone();
-
-        let thread_handle = tokio::spawn(async move {
-            let mut receiver = receiver;
-
-            while let Some(result) = receiver.next().await {
-                if let Ok(msg) = result {
-                    if let Message::Text(string) = msg {
-                        tracing::debug!("{string}");
-                        if let Err(err) = broadcast_sender.send(string) {
-                            tracing::trace!("{err:?}");
-                            // Break the while loop so that the receiver handle is dropped
-                            // and the task unsubscribes from the summary stream.
-                            break;
-                        }
-                    }
-                } else {
-                    tracing::error!("{:?}", result);
-                }
-            }
-        });
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        let connection =
+            Connection::open(url, Exchange::Bitstamp, Ping::Frame, recorder, route).await?;
 
         Ok(Self {
-            sender,
-            thread_handle,
-            broadcast: Some(broadcast),
-            book_events: None,
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
         })
     }
 
     pub async fn connect_public() -> Result<Self> {
+        Self::connect_public_with_recorder(None).await
+    }
+
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
         let url = format!("{DEFAULT_WS_BASE_URL}/");
-        Self::connect(&url).await
+        Self::connect_with_recorder(&url, recorder).await
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// Resolves once Bitstamp asks for the connection to be replaced with
+    /// `bts:request_reconnect`.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut messages = self.messages();
+        async move {
+            loop {
+                match messages.recv().await {
+                    Ok(msg) if msg.contains(REQUEST_RECONNECT_EVENT) => {
+                        if serde_json::from_str::<EventHeader>(&msg)
+                            .is_ok_and(|header| header.event == REQUEST_RECONNECT_EVENT)
+                        {
+                            return;
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    // A closed connection is no request; its book stream ends instead.
+                    Err(RecvError::Closed) => std::future::pending().await,
+                }
+            }
+        }
     }
 
     /// Sends a message to the WebSocket.
@@ -106,11 +189,7 @@ impl BitstampClient {
     where
         R: Serialize,
     {
-        let msg = serde_json::to_string(&req).unwrap();
-        tracing::debug!("{msg}");
-        self.sender.send(Message::Text(msg.to_string())).await?;
-
-        Ok(())
+        self.connection.send(req).await
     }
 
     /// Performs a remote procedure call.
@@ -126,49 +205,102 @@ impl BitstampClient {
         self.send(req).await
     }
 
+    /// Subscribes to `channel` and waits up to `request_timeout` for
+    /// `bts:subscription_succeeded`, or fails on `bts:error`.
+    pub async fn subscribe(&mut self, channel: &str) -> Result<()> {
+        self.channel_request(SUBSCRIBE_EVENT, SUBSCRIPTION_SUCCEEDED_EVENT, channel)
+            .await
+    }
+
+    /// Sends `event` for `channel` and waits up to `request_timeout` for
+    /// `succeeded_event` on the same channel, or fails on `bts:error`.
+    async fn channel_request(
+        &mut self,
+        event: &str,
+        succeeded_event: &str,
+        channel: &str,
+    ) -> Result<()> {
+        let mut messages_receiver = self.messages();
+        self.call(event, SubscribeData::new(channel)).await?;
+
+        let ack = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Event>(&msg) {
+                        Ok(event) if event.event == succeeded_event && event.channel == channel => {
+                            return Ok(())
+                        }
+                        // Errors are not always tagged with the channel they refer to.
+                        Ok(event) if event.event == ERROR_EVENT => {
+                            return Err(event.into_error(channel))
+                        }
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
+        };
+        tokio::time::timeout(self.request_timeout, ack)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
+    }
+
+    /// Subscribes to the order book of `symbol`.
+    ///
+    /// Fails if Bitstamp rejects the subscription or does not acknowledge it
+    /// within `request_timeout`.
     // <https://assets.bitstamp.net/static/webapp/examples/order_book_v2.3610acefe104f01a8dcd4fda1cb88403f368526b.html>
-    pub async fn subscribe_orderbook(&mut self, symbol: &str, best_of: usize) {
+    pub async fn subscribe_orderbook(
+        &mut self,
+        symbol: &str,
+        best_of: usize,
+    ) -> Result<Subscription> {
         let channel = format!("order_book_{symbol}");
 
-        self.call(SUBSCRIBE_EVENT, SubscribeData::new(&channel))
-            .await
-            .expect("cannot send request");
+        // Register before sending the request so that no frame is missed.
+        let (handle, mut frames) = self.connection.registry().register(&channel);
 
-        let mut messages_receiver = self
-            .broadcast
-            .clone()
-            .expect("client not connected")
-            .subscribe();
+        if let Err(e) = self.subscribe(&channel).await {
+            self.connection.registry().remove(&handle);
+            return Err(e);
+        }
 
         let depth_events = stream! {
-            while let Ok(msg) = messages_receiver.recv().await {
-                if let Ok(msg) = serde_json::from_str::<BitstampBookEvent>(&msg) {
-                    let bids = msg.data.bids
-                        .iter()
-                        .take(best_of)
-                        .map(|x| (Level {
-                            exchange: Exchange::Bitstamp.to_string(),
-                            price: x.0.parse::<f64>().unwrap(),
-                             amount: x.1.parse::<f64>().unwrap()
-                        }))
-                        .collect();
-                    let asks = msg.data.asks
-                        .iter()
-                        .take(best_of)
-                        .map(|x| (Level {
-                            exchange: Exchange::Bitstamp.to_string(),
-                            price: x.0.parse::<f64>().unwrap(),
-                             amount: x.1.parse::<f64>().unwrap()
-                        }))
-                        .collect();
-                    let book_event = OrderBook { exchange: Exchange::Bitstamp, last_updated: msg.data.microtimestamp, bids, asks};
+            while let Some(msg) = frames.recv().await {
+                if let Some(book_event) = parse_order_book(&msg, best_of) {
                     yield book_event;
                 }
             }
         };
 
-        let depth_events = Box::pin(depth_events);
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(depth_events),
+        })
+    }
+
+    /// Ends a subscription. Bitstamp is only asked to unsubscribe once no other
+    /// subscription of this client uses the channel.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            self.channel_request(
+                UNSUBSCRIBE_EVENT,
+                UNSUBSCRIPTION_SUCCEEDED_EVENT,
+                &handle.channel,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
 
-        self.book_events = Some(depth_events);
+/// Routes channel data to its subscriptions; `bts:*` protocol events only go
+/// to `messages`.
+fn route(routes: &Registry, frame: &str) {
+    if let Ok(header) = serde_json::from_str::<EventHeader>(frame) {
+        if !header.event.starts_with("bts:") {
+            routes.dispatch(&header.channel, frame);
+        }
     }
 }
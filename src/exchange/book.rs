@@ -0,0 +1,109 @@
+//! Building blocks for the clients that keep a local book from a snapshot and
+//! the updates that follow it.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::decimal;
+use crate::types::{Exchange, OrderBook, Quote};
+
+/// A price level key, ordered by [`f64::total_cmp`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Price(pub f64);
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A level of a [`LocalBook`]: the parsed amount, and the price and amount
+/// texts as sent, which some venues compute their checksums from.
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    pub amount: f64,
+    pub price_text: String,
+    pub amount_text: String,
+}
+
+pub(crate) type Side = BTreeMap<Price, Entry>;
+
+/// The book of one symbol, rebuilt from each snapshot and kept current by
+/// the updates in between.
+#[derive(Debug, Default)]
+pub(crate) struct LocalBook {
+    pub bids: Side,
+    pub asks: Side,
+    pub last_updated: String,
+}
+
+impl LocalBook {
+    /// Sets the level at `price` on `side`, removing it for a zero `amount`.
+    /// Returns `None` if either does not parse.
+    pub fn update(side: &mut Side, price: &str, amount: &str) -> Option<()> {
+        let key = Price(decimal::parse(price)?);
+        let value = decimal::parse(amount)?;
+        if value == 0.0 {
+            side.remove(&key);
+        } else {
+            side.insert(
+                key,
+                Entry {
+                    amount: value,
+                    price_text: price.to_string(),
+                    amount_text: amount.to_string(),
+                },
+            );
+        }
+        Some(())
+    }
+
+    /// Drops the levels beyond the best `depth` per side.
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            self.bids.pop_first();
+        }
+        while self.asks.len() > depth {
+            self.asks.pop_last();
+        }
+    }
+
+    /// The best bids, best first.
+    pub fn best_bids(&self) -> impl Iterator<Item = &Entry> {
+        self.bids.values().rev()
+    }
+
+    /// The best asks, best first.
+    pub fn best_asks(&self) -> impl Iterator<Item = &Entry> {
+        self.asks.values()
+    }
+
+    /// The best `best_of` levels per side.
+    pub fn to_order_book(&self, exchange: Exchange, best_of: usize) -> OrderBook {
+        let level = |(price, entry): (&Price, &Entry)| Quote {
+            price: price.0,
+            amount: entry.amount,
+        };
+        OrderBook {
+            exchange,
+            last_updated: self.last_updated.clone(),
+            bids: self.bids.iter().rev().take(best_of).map(level).collect(),
+            asks: self.asks.iter().take(best_of).map(level).collect(),
+            degraded: false,
+        }
+    }
+}
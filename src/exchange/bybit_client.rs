@@ -0,0 +1,338 @@
+//! <https://bybit-exchange.github.io/docs/v5/websocket/public/orderbook>
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::exchange::book::{LocalBook, Side};
+use crate::exchange::connection::{send, Connection, Ping, WsSender};
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const SUBSCRIBE_OP: &str = "subscribe";
+pub const UNSUBSCRIBE_OP: &str = "unsubscribe";
+/// Bybit recommends sending this every 20 seconds to keep the connection open.
+pub const PING: &str = r#"{"op":"ping"}"#;
+
+/// The book depth to subscribe to.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum BookDepth {
+    D1 = 1,
+    D50 = 50,
+    D200 = 200,
+}
+
+/// The topic of the book of `symbol` at `depth`, e.g. `orderbook.50.BTCUSDT`.
+pub fn topic(symbol: &str, depth: BookDepth) -> String {
+    format!("orderbook.{}.{symbol}", depth as u32)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Request {
+    pub req_id: String,
+    pub op: String,
+    pub args: Vec<String>,
+}
+
+/// The response to a request, matched by `req_id`.
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub success: bool,
+    #[serde(default)]
+    pub ret_msg: String,
+    pub req_id: Option<String>,
+    pub op: String,
+}
+
+impl Response {
+    /// Maps an unsuccessful response to a typed error.
+    fn into_result(self, symbol: &str) -> Result<()> {
+        if self.success {
+            return Ok(());
+        }
+        let lower = self.ret_msg.to_lowercase();
+        if lower.contains("invalid symbol") || lower.contains("handler not found") {
+            Err(Error::UnknownSymbol(symbol.to_string()))
+        } else if lower.contains("too many") || lower.contains("rate limit") {
+            Err(Error::RateLimited(self.ret_msg))
+        } else {
+            Err(Error::SubscriptionRejected(self.ret_msg))
+        }
+    }
+}
+
+/// An `orderbook` topic push.
+#[derive(Debug, Deserialize)]
+pub struct BookMessage {
+    pub topic: String,
+    /// `snapshot` or `delta`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub ts: u64,
+    pub data: BookData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BookData {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// `(price, size)`; a size of zero removes the price level.
+    #[serde(rename = "b")]
+    pub bids: Vec<(String, String)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(String, String)>,
+    /// The update id, incremented by one with every push of the topic.
+    #[serde(rename = "u")]
+    pub update_id: u64,
+}
+
+/// Just the routing part of a [`BookMessage`].
+#[derive(Debug, Deserialize)]
+struct MessageHeader {
+    topic: String,
+}
+
+/// The book of one topic and the update id of its last push.
+#[derive(Debug, Default)]
+struct Book {
+    book: LocalBook,
+    update_id: u64,
+}
+
+/// Applies `levels` to one side, returning `None` if a price or size is not a
+/// number.
+fn apply_side(side: &mut Side, levels: &[(String, String)]) -> Option<()> {
+    levels
+        .iter()
+        .try_for_each(|(price, size)| LocalBook::update(side, price, size))
+}
+
+/// The books of every topic seen in a sequence of raw websocket frames.
+#[derive(Debug, Default)]
+pub struct Books {
+    books: HashMap<String, Book>,
+}
+
+impl Books {
+    /// Applies a raw websocket frame, returning the updated book of its topic,
+    /// or `None` for frames that are not book pushes and for deltas of topics
+    /// without a snapshot yet.
+    ///
+    /// Fails with [`Error::SequenceGap`] if a delta does not follow the last
+    /// push, and with [`Error::CorruptBook`] if a level is not a number; either
+    /// way the topic's book is dropped until the next snapshot.
+    pub fn apply(&mut self, frame: &str, best_of: usize) -> Result<Option<OrderBook>> {
+        let Ok(message) = serde_json::from_str::<BookMessage>(frame) else {
+            return Ok(None);
+        };
+        let data = message.data;
+        let book = match message.kind.as_str() {
+            "snapshot" => {
+                let book = self.books.entry(message.topic.clone()).or_default();
+                *book = Book::default();
+                book
+            }
+            "delta" => match self.books.get_mut(&message.topic) {
+                Some(book) if data.update_id == book.update_id + 1 => book,
+                Some(_) => {
+                    self.books.remove(&message.topic);
+                    return Err(Error::SequenceGap(data.symbol));
+                }
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let applied = apply_side(&mut book.book.bids, &data.bids)
+            .and_then(|_| apply_side(&mut book.book.asks, &data.asks));
+        if applied.is_none() {
+            self.books.remove(&message.topic);
+            return Err(Error::CorruptBook(data.symbol));
+        }
+        book.book.last_updated = message.ts.to_string();
+        book.update_id = data.update_id;
+        Ok(Some(book.book.to_order_book(Exchange::Bybit, best_of)))
+    }
+}
+
+pub const DEFAULT_WS_BASE_URL: &str = "wss://stream.bybit.com/v5/public/spot";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A WebSocket client for Bybit v5 public market data.
+pub struct BybitClient {
+    connection: Connection,
+    /// How long [`Self::call`] waits for a response.
+    pub request_timeout: Duration,
+    // Shared with the book streams, which resubscribe on sequence gaps.
+    next_id: Arc<AtomicU64>,
+}
+
+impl BybitClient {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        let connection =
+            Connection::open(url, Exchange::Bybit, Ping::Text(PING), recorder, route).await?;
+
+        Ok(Self {
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            next_id: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub async fn connect_public() -> Result<Self> {
+        Self::connect_public_with_recorder(None).await
+    }
+
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_with_recorder(DEFAULT_WS_BASE_URL, recorder).await
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// Bybit restarts its services without notice and then sends a fresh
+    /// snapshot on the same connection, so this never resolves.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::pending()
+    }
+
+    /// Sends a message to the WebSocket.
+    pub async fn send<R>(&mut self, req: R) -> Result<()>
+    where
+        R: Serialize,
+    {
+        self.connection.send(req).await
+    }
+
+    /// Sends an `op` request for `topic`, waiting up to `request_timeout` for
+    /// the response with the matching `req_id`.
+    pub async fn call(&mut self, op: &str, topic: &str, symbol: &str) -> Result<()> {
+        let req_id = (self.next_id.fetch_add(1, Ordering::Relaxed) + 1).to_string();
+        let req = Request {
+            req_id: req_id.clone(),
+            op: op.to_string(),
+            args: vec![topic.to_string()],
+        };
+
+        let mut messages_receiver = self.messages();
+        self.send(req).await?;
+
+        let response = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Response>(&msg) {
+                        Ok(response)
+                            if response.op == op
+                                && response.req_id.as_deref() == Some(req_id.as_str()) =>
+                        {
+                            return response.into_result(symbol)
+                        }
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
+        };
+        tokio::time::timeout(self.request_timeout, response)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
+    }
+
+    /// Subscribes to the book of `symbol`, e.g. `BTCUSDT`.
+    ///
+    /// Every delta is checked to follow the previous push. On a gap or a level
+    /// that is not a number the stream skips deltas and resubscribes for a
+    /// fresh snapshot. Fails if Bybit
+    /// rejects the subscription or does not acknowledge it within
+    /// `request_timeout`.
+    pub async fn subscribe_orderbook(
+        &mut self,
+        symbol: &str,
+        depth: BookDepth,
+        best_of: usize,
+    ) -> Result<Subscription> {
+        let topic = topic(symbol, depth);
+        // Register before sending the request so that no frame is missed.
+        let (handle, mut frames) = self.connection.registry().register(&topic);
+
+        if let Err(e) = self.call(SUBSCRIBE_OP, &topic, symbol).await {
+            self.connection.registry().remove(&handle);
+            return Err(e);
+        }
+
+        let sender = self.connection.sender();
+        let next_id = self.next_id.clone();
+        let book_events = stream! {
+            let mut books = Books::default();
+            while let Some(msg) = frames.recv().await {
+                match books.apply(&msg, best_of) {
+                    Ok(Some(book_event)) => yield book_event,
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("{e}, resubscribing");
+                        if let Err(e) = resubscribe(&sender, &next_id, &topic).await {
+                            tracing::error!("cannot resubscribe to {topic}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(book_events),
+        })
+    }
+
+    /// Ends a subscription. Bybit is only asked to unsubscribe once no other
+    /// subscription of this client uses the topic.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            let symbol = handle.channel.rsplit('.').next().unwrap_or_default();
+            self.call(UNSUBSCRIBE_OP, &handle.channel, symbol).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Routes pushes to the subscriptions of their topic; everything else only
+/// goes to `messages`.
+fn route(routes: &Registry, frame: &str) {
+    if let Ok(header) = serde_json::from_str::<MessageHeader>(frame) {
+        routes.dispatch(&header.topic, frame);
+    }
+}
+
+/// Unsubscribes from and subscribes to `topic` again, which makes Bybit send a
+/// fresh snapshot. The responses are not waited for.
+async fn resubscribe(sender: &WsSender, next_id: &AtomicU64, topic: &str) -> Result<()> {
+    for op in [UNSUBSCRIBE_OP, SUBSCRIBE_OP] {
+        let req = Request {
+            req_id: (next_id.fetch_add(1, Ordering::Relaxed) + 1).to_string(),
+            op: op.to_string(),
+            args: vec![topic.to_string()],
+        };
+        send(sender, req).await?;
+    }
+    Ok(())
+}
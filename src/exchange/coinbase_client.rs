@@ -0,0 +1,308 @@
+//! <https://docs.cloud.coinbase.com/exchange/docs/websocket-overview>
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::exchange::book::LocalBook;
+use crate::exchange::connection::{Connection, Ping};
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const SUBSCRIBE_TYPE: &str = "subscribe";
+pub const UNSUBSCRIBE_TYPE: &str = "unsubscribe";
+/// The public level2 channel, batching updates every 50 ms. The unbatched
+/// `level2` channel requires authentication.
+pub const LEVEL2_CHANNEL: &str = "level2_batch";
+
+#[derive(Debug, Serialize)]
+pub struct Request {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub product_ids: Vec<String>,
+    pub channels: Vec<String>,
+}
+
+/// The messages Coinbase sends on the level2 channel, tagged by `type`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The full book of a product, sent once after subscribing.
+    Snapshot {
+        product_id: String,
+        bids: Vec<(String, String)>,
+        asks: Vec<(String, String)>,
+    },
+    /// Changes to the book as `(side, price, size)`; a size of zero removes the
+    /// price level.
+    L2update {
+        product_id: String,
+        #[serde(default)]
+        time: String,
+        changes: Vec<(String, String, String)>,
+    },
+    /// The channels subscribed to after a subscribe or unsubscribe request.
+    Subscriptions { channels: Vec<ChannelSubscription> },
+    Error {
+        message: String,
+        #[serde(default)]
+        reason: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelSubscription {
+    pub name: String,
+    #[serde(default)]
+    pub product_ids: Vec<String>,
+}
+
+/// Just the routing part of an [`Event`].
+#[derive(Debug, Deserialize)]
+struct EventHeader {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    product_id: String,
+}
+
+/// Whether `channels` includes the level2 channel of `product_id`.
+fn is_subscribed(channels: &[ChannelSubscription], product_id: &str) -> bool {
+    channels
+        .iter()
+        .any(|c| c.name == LEVEL2_CHANNEL && c.product_ids.iter().any(|p| p == product_id))
+}
+
+/// Maps an `error` message to a typed error.
+fn into_error(message: String, reason: String, product_id: &str) -> Error {
+    let lower = format!("{message} {reason}").to_lowercase();
+    if lower.contains("not a valid product") {
+        Error::UnknownSymbol(product_id.to_string())
+    } else if lower.contains("rate limit") || lower.contains("too many") {
+        Error::RateLimited(reason)
+    } else if reason.is_empty() {
+        Error::SubscriptionRejected(message)
+    } else {
+        Error::SubscriptionRejected(format!("{message}: {reason}"))
+    }
+}
+
+/// The books of every product seen in a sequence of raw websocket frames.
+///
+/// Coinbase only sends the full book once, so unlike the other exchanges a
+/// frame cannot be parsed on its own.
+#[derive(Debug, Default)]
+pub struct Books {
+    books: HashMap<String, LocalBook>,
+}
+
+impl Books {
+    /// Applies a raw websocket frame, returning the updated book of its
+    /// product, or `None` for frames that are not book events and for updates
+    /// of products without a snapshot yet.
+    pub fn apply(&mut self, frame: &str, best_of: usize) -> Option<OrderBook> {
+        match serde_json::from_str::<Event>(frame).ok()? {
+            Event::Snapshot {
+                product_id,
+                bids,
+                asks,
+            } => {
+                let mut book = LocalBook::default();
+                for (price, size) in &bids {
+                    LocalBook::update(&mut book.bids, price, size)?;
+                }
+                for (price, size) in &asks {
+                    LocalBook::update(&mut book.asks, price, size)?;
+                }
+                let ob = book.to_order_book(Exchange::Coinbase, best_of);
+                self.books.insert(product_id, book);
+                Some(ob)
+            }
+            Event::L2update {
+                product_id,
+                time,
+                changes,
+            } => {
+                let book = self.books.get_mut(&product_id)?;
+                // Changes that do not parse are skipped.
+                for (side, price, size) in &changes {
+                    let levels = match side.as_str() {
+                        "buy" => &mut book.bids,
+                        "sell" => &mut book.asks,
+                        _ => continue,
+                    };
+                    LocalBook::update(levels, price, size);
+                }
+                book.last_updated = time;
+                Some(book.to_order_book(Exchange::Coinbase, best_of))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub const DEFAULT_WS_BASE_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A WebSocket client for Coinbase Exchange.
+pub struct CoinbaseClient {
+    connection: Connection,
+    /// How long [`Self::subscribe`] waits for the subscription to be acknowledged.
+    pub request_timeout: Duration,
+}
+
+impl CoinbaseClient {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        let connection =
+            Connection::open(url, Exchange::Coinbase, Ping::Frame, recorder, route).await?;
+
+        Ok(Self {
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        })
+    }
+
+    pub async fn connect_public() -> Result<Self> {
+        Self::connect_public_with_recorder(None).await
+    }
+
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_with_recorder(DEFAULT_WS_BASE_URL, recorder).await
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// Coinbase never asks for a connection to be replaced, so this never
+    /// resolves.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::pending()
+    }
+
+    /// Sends a message to the WebSocket.
+    pub async fn send<R>(&mut self, req: R) -> Result<()>
+    where
+        R: Serialize,
+    {
+        self.connection.send(req).await
+    }
+
+    /// Subscribes to the level2 channel of `product_id` and waits up to
+    /// `request_timeout` for the `subscriptions` message listing it, or fails
+    /// on `error`.
+    pub async fn subscribe(&mut self, product_id: &str) -> Result<()> {
+        self.channel_request(SUBSCRIBE_TYPE, product_id, true).await
+    }
+
+    /// Sends a `kind` request for the level2 channel of `product_id` and waits
+    /// up to `request_timeout` for the `subscriptions` message in which the
+    /// product is `subscribed` or not, or fails on `error`.
+    async fn channel_request(
+        &mut self,
+        kind: &str,
+        product_id: &str,
+        subscribed: bool,
+    ) -> Result<()> {
+        let mut messages_receiver = self.messages();
+        self.send(Request {
+            kind: kind.to_string(),
+            product_ids: vec![product_id.to_string()],
+            channels: vec![LEVEL2_CHANNEL.to_string()],
+        })
+        .await?;
+
+        let ack = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Event>(&msg) {
+                        Ok(Event::Subscriptions { channels })
+                            if is_subscribed(&channels, product_id) == subscribed =>
+                        {
+                            return Ok(())
+                        }
+                        Ok(Event::Error { message, reason }) => {
+                            return Err(into_error(message, reason, product_id))
+                        }
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
+        };
+        tokio::time::timeout(self.request_timeout, ack)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
+    }
+
+    /// Subscribes to the level2 book of `product_id`, e.g. `BTC-USD`.
+    ///
+    /// Fails if Coinbase rejects the subscription or does not acknowledge it
+    /// within `request_timeout`.
+    // <https://docs.cloud.coinbase.com/exchange/docs/websocket-channels#level2-batch-channel>
+    pub async fn subscribe_orderbook(
+        &mut self,
+        product_id: &str,
+        best_of: usize,
+    ) -> Result<Subscription> {
+        // Register before sending the request so that no frame is missed.
+        let (handle, mut frames) = self.connection.registry().register(product_id);
+
+        if let Err(e) = self.subscribe(product_id).await {
+            self.connection.registry().remove(&handle);
+            return Err(e);
+        }
+
+        let book_events = stream! {
+            let mut books = Books::default();
+            while let Some(msg) = frames.recv().await {
+                if let Some(book_event) = books.apply(&msg, best_of) {
+                    yield book_event;
+                }
+            }
+        };
+
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(book_events),
+        })
+    }
+
+    /// Ends a subscription. Coinbase is only asked to unsubscribe once no other
+    /// subscription of this client uses the product.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            self.channel_request(UNSUBSCRIBE_TYPE, &handle.channel, false)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Routes book events to the subscriptions of their product; everything else
+/// only goes to `messages`.
+fn route(routes: &Registry, frame: &str) {
+    if let Ok(header) = serde_json::from_str::<EventHeader>(frame) {
+        if header.kind == "snapshot" || header.kind == "l2update" {
+            routes.dispatch(&header.product_id, frame);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! The websocket plumbing shared by the exchange clients.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::exchange::error::Error;
+use crate::exchange::subscription::Registry;
+use crate::recorder::Recorder;
+use crate::types::Exchange;
+
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+/// The write half of a connection, shared by a client and its reader task.
+pub(crate) type WsSender = Arc<Mutex<SplitSink<WsStream, Message>>>;
+pub(crate) type WsReceiver = SplitStream<WsStream>;
+
+/// A connection is pinged once it received nothing for this long.
+pub const PING_AFTER_IDLE: Duration = Duration::from_secs(20);
+/// A pinged connection that still receives nothing for this long is dead.
+pub const PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a connection is kept alive.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Ping {
+    /// A websocket ping frame, sent once the connection is idle.
+    Frame,
+    /// An application-level text message, e.g. OKX's `ping`, sent at least every
+    /// [`PING_AFTER_IDLE`] whether or not the connection is idle, for venues
+    /// that drop clients which do not send it.
+    Text(&'static str),
+}
+
+/// The read half of a connection, answering pings and pinging the venue.
+pub(crate) struct FrameReader {
+    receiver: WsReceiver,
+    sender: WsSender,
+    ping: Ping,
+    last_ping: Instant,
+}
+
+impl FrameReader {
+    pub(crate) fn new(receiver: WsReceiver, sender: WsSender, ping: Ping) -> Self {
+        Self {
+            receiver,
+            sender,
+            ping,
+            last_ping: Instant::now(),
+        }
+    }
+
+    async fn send_ping(&mut self) -> bool {
+        let message = match self.ping {
+            Ping::Frame => Message::Ping(Vec::new()),
+            Ping::Text(text) => Message::Text(text.to_string()),
+        };
+        self.last_ping = Instant::now();
+        if let Err(e) = self.sender.lock().await.send(message).await {
+            tracing::error!("cannot ping: {e:?}");
+            return false;
+        }
+        true
+    }
+
+    /// Reads the next text frame.
+    ///
+    /// Returns `None` once the connection closed, failed, or went silent for
+    /// [`PING_AFTER_IDLE`] plus [`PONG_TIMEOUT`].
+    pub(crate) async fn next_text(&mut self) -> Option<String> {
+        let mut idle_since = Instant::now();
+        let mut pinged = false;
+        loop {
+            if matches!(self.ping, Ping::Text(_))
+                && self.last_ping.elapsed() >= PING_AFTER_IDLE
+                && !self.send_ping().await
+            {
+                return None;
+            }
+            let limit = if pinged {
+                PING_AFTER_IDLE + PONG_TIMEOUT
+            } else {
+                PING_AFTER_IDLE
+            };
+            let mut wake = idle_since + limit;
+            if matches!(self.ping, Ping::Text(_)) {
+                wake = wake.min(self.last_ping + PING_AFTER_IDLE);
+            }
+            match tokio::time::timeout_at(wake, self.receiver.next()).await {
+                Ok(Some(Ok(Message::Text(text)))) => return Some(text),
+                Ok(Some(Ok(Message::Ping(payload)))) => {
+                    idle_since = Instant::now();
+                    pinged = false;
+                    // Binance drops connections that do not answer its pings.
+                    if let Err(e) = self.sender.lock().await.send(Message::Pong(payload)).await {
+                        tracing::error!("cannot answer ping: {e:?}");
+                        return None;
+                    }
+                }
+                Ok(Some(Ok(_))) => {
+                    idle_since = Instant::now();
+                    pinged = false;
+                }
+                Ok(Some(Err(e))) => {
+                    tracing::error!("{e:?}");
+                    return None;
+                }
+                Ok(None) => return None,
+                // A text ping is due, which the next iteration sends.
+                Err(_) if idle_since.elapsed() < limit => {}
+                Err(_) if pinged => {
+                    tracing::warn!(
+                        "no frame within {PONG_TIMEOUT:?} of a ping, dropping connection"
+                    );
+                    return None;
+                }
+                Err(_) => {
+                    pinged = true;
+                    if !self.send_ping().await {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A connection to a venue, shared by its client's requests and subscriptions.
+///
+/// A reader task hands every text frame to the venue's `route`, which passes
+/// it on to the subscriptions it belongs to, and then to every receiver of
+/// [`Self::messages`].
+pub(crate) struct Connection {
+    sender: WsSender,
+    // The reader task is aborted when the connection drops.
+    reader: JoinHandle<()>,
+    // Kept so that new subscribers can be created with `resubscribe`; the channel
+    // closes once the reader task exits and drops the only sender.
+    messages: broadcast::Receiver<String>,
+    registry: Registry,
+}
+
+impl Connection {
+    /// Connects to `url`, kept alive with `ping`, handing every received text
+    /// frame to `recorder` as a frame of `exchange`.
+    pub(crate) async fn open<F>(
+        url: &str,
+        exchange: Exchange,
+        ping: Ping,
+        recorder: Option<Recorder>,
+        route: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(&Registry, &str) + Send + 'static,
+    {
+        let (stream, _) = connect_async(url).await?;
+        let (sender, receiver) = stream.split();
+        let sender = Arc::new(Mutex::new(sender));
+        let mut reader = FrameReader::new(receiver, sender.clone(), ping);
+        let (broadcast_sender, messages) = broadcast::channel::<String>(32);
+        let registry = Registry::default();
+        let routes = registry.clone();
+
+        let reader = tokio::spawn(async move {
+            while let Some(string) = reader.next_text().await {
+                tracing::debug!("{string}");
+                if let Some(recorder) = &recorder {
+                    recorder.record(exchange, &string);
+                }
+                route(&routes, &string);
+                if let Err(err) = broadcast_sender.send(string) {
+                    tracing::trace!("{err:?}");
+                    break;
+                }
+            }
+            routes.close();
+        });
+
+        Ok(Self {
+            sender,
+            reader,
+            messages,
+            registry,
+        })
+    }
+
+    /// The write half, for streams that send requests of their own.
+    pub(crate) fn sender(&self) -> WsSender {
+        self.sender.clone()
+    }
+
+    pub(crate) fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub(crate) fn messages(&self) -> broadcast::Receiver<String> {
+        self.messages.resubscribe()
+    }
+
+    /// Sends `req` as JSON.
+    pub(crate) async fn send<R: Serialize>(&self, req: R) -> Result<(), Error> {
+        send(&self.sender, req).await
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Sends `req` as JSON on the write half of a connection.
+pub(crate) async fn send<R: Serialize>(sender: &WsSender, req: R) -> Result<(), Error> {
+    send_text(sender, serde_json::to_string(&req)?).await
+}
+
+/// Sends a text frame on the write half of a connection.
+pub(crate) async fn send_text(sender: &WsSender, text: String) -> Result<(), Error> {
+    tracing::debug!("{text}");
+    sender.lock().await.send(Message::Text(text)).await?;
+    Ok(())
+}
@@ -7,6 +7,24 @@ pub enum Error {
     Internal(String),
     #[error("malformed JSON payload: {0}")]
     MalformedJSON(String),
+    #[error("unknown symbol: {0}")]
+    UnknownSymbol(String),
+    #[error("subscription rejected: {0}")]
+    SubscriptionRejected(String),
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    #[error("no response within {0} ms")]
+    Timeout(u64),
+    #[error("connection closed")]
+    ConnectionClosed,
+    #[error("book checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+    #[error("book sequence gap: {0}")]
+    SequenceGap(String),
+    #[error("corrupt book: {0}")]
+    CorruptBook(String),
+    #[error("HTTP request failed: {0}")]
+    Http(String),
 }
 
 impl From<tokio_tungstenite::tungstenite::Error> for Error {
@@ -20,3 +38,9 @@ impl From<serde_json::Error> for Error {
         Self::MalformedJSON(e.to_string())
     }
 }
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e.to_string())
+    }
+}
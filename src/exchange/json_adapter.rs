@@ -0,0 +1,207 @@
+//! A client for venues that push top-N book snapshots as plain JSON, declared
+//! in configuration instead of code.
+
+use std::future::Future;
+
+use async_stream::stream;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::decimal;
+use crate::exchange::connection::{send_text, Connection, Ping};
+use crate::exchange::subscription::{Registry, Subscription};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook, Quote};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Where a venue's books are and what they look like, e.g. for a venue
+/// shaped like Bitstamp:
+///
+/// ```json
+/// {
+///     "url": "wss://ws.example.com",
+///     "subscribe": "{\"event\":\"subscribe\",\"channel\":\"book.{symbol}\"}",
+///     "bids": "/data/bids",
+///     "asks": "/data/asks",
+///     "timestamp": "/data/timestamp"
+/// }
+/// ```
+///
+/// `{symbol}` in `url` and `subscribe` is replaced with the subscribed symbol,
+/// which `subscribe` expects inside a JSON string.
+/// Every frame with arrays at `bids` and `asks` is taken as a full snapshot,
+/// best level first; other frames are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AdapterConfig {
+    pub url: String,
+    /// The message sent after connecting, if the URL does not subscribe already.
+    #[serde(default)]
+    pub subscribe: Option<String>,
+    /// JSON pointers to the bid and ask arrays.
+    pub bids: String,
+    pub asks: String,
+    /// A JSON pointer to the book's timestamp, a string or a number.
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    /// JSON pointers into a level to its price and amount, strings or numbers;
+    /// by default the first two elements of a `[price, amount]` array.
+    #[serde(default = "default_price")]
+    pub price: String,
+    #[serde(default = "default_amount")]
+    pub amount: String,
+}
+
+fn default_price() -> String {
+    "/0".to_string()
+}
+
+fn default_amount() -> String {
+    "/1".to_string()
+}
+
+impl AdapterConfig {
+    pub fn url(&self, symbol: &str) -> String {
+        self.url.replace("{symbol}", symbol)
+    }
+
+    /// The subscribe message for `symbol`, escaped to stand inside a JSON
+    /// string.
+    pub fn subscribe_message(&self, symbol: &str) -> Option<String> {
+        let quoted = Value::from(symbol).to_string();
+        let escaped = &quoted[1..quoted.len() - 1];
+        Some(self.subscribe.as_ref()?.replace("{symbol}", escaped))
+    }
+
+    /// Parses a raw websocket frame into an [`OrderBook`] of `exchange`
+    /// holding the best `best_of` levels per side, returning `None` for frames
+    /// without books and for books with a level that is not a number.
+    pub fn parse_order_book(
+        &self,
+        exchange: Exchange,
+        frame: &str,
+        best_of: usize,
+    ) -> Option<OrderBook> {
+        let value = serde_json::from_str::<Value>(frame).ok()?;
+        let levels = |pointer: &str| {
+            value
+                .pointer(pointer)?
+                .as_array()?
+                .iter()
+                .take(best_of)
+                .map(|level| {
+                    Some(Quote {
+                        price: number(level.pointer(&self.price)?)?,
+                        amount: number(level.pointer(&self.amount)?)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+        };
+        let last_updated = match self.timestamp.as_deref().and_then(|p| value.pointer(p)) {
+            Some(Value::String(text)) => text.clone(),
+            Some(other) => other.to_string(),
+            None => String::new(),
+        };
+        Some(OrderBook {
+            exchange,
+            last_updated,
+            bids: levels(&self.bids)?,
+            asks: levels(&self.asks)?,
+            degraded: false,
+        })
+    }
+}
+
+/// A number sent either as a JSON number or as a decimal string.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => decimal::parse(text),
+        _ => None,
+    }
+}
+
+/// A WebSocket client for a venue described by an [`AdapterConfig`], on a
+/// connection to one symbol.
+pub struct JsonAdapterClient {
+    exchange: Exchange,
+    config: AdapterConfig,
+    connection: Connection,
+}
+
+impl JsonAdapterClient {
+    /// Connects to the URL of `symbol`, handing every received text frame to
+    /// `recorder` as frames of `exchange`.
+    pub async fn connect(
+        exchange: Exchange,
+        config: AdapterConfig,
+        symbol: &str,
+        recorder: Option<Recorder>,
+    ) -> Result<Self> {
+        // Frames name no channel the adapter could route by, so a connection
+        // only ever carries one symbol.
+        let url = config.url(symbol);
+        let connection = Connection::open(
+            &url,
+            exchange,
+            Ping::Frame,
+            recorder,
+            Registry::dispatch_all,
+        )
+        .await?;
+
+        Ok(Self {
+            exchange,
+            config,
+            connection,
+        })
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// The adapter knows no reconnect requests, so this never resolves.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::pending()
+    }
+
+    /// Sends the configured subscribe message for `symbol`, if any, and returns
+    /// the stream of its books.
+    ///
+    /// The adapter knows no acknowledgement to wait for, so a rejected
+    /// subscription only shows as a stream without books.
+    pub async fn subscribe_orderbook(
+        &mut self,
+        symbol: &str,
+        best_of: usize,
+    ) -> Result<Subscription> {
+        // Register before sending the request so that no frame is missed.
+        let (handle, mut frames) = self.connection.registry().register(symbol);
+
+        if let Some(message) = self.config.subscribe_message(symbol) {
+            let sent = send_text(&self.connection.sender(), message).await;
+            if let Err(e) = sent {
+                self.connection.registry().remove(&handle);
+                return Err(e);
+            }
+        }
+
+        let exchange = self.exchange;
+        let config = self.config.clone();
+        let book_events = stream! {
+            while let Some(msg) = frames.recv().await {
+                if let Some(book_event) = config.parse_order_book(exchange, &msg, best_of) {
+                    yield book_event;
+                }
+            }
+        };
+
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(book_events),
+        })
+    }
+}
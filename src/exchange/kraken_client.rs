@@ -0,0 +1,420 @@
+//! <https://docs.kraken.com/websockets-v2/>
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::exchange::book::{LocalBook, Side};
+use crate::exchange::connection::{send, Connection, Ping, WsSender};
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const SUBSCRIBE_METHOD: &str = "subscribe";
+pub const UNSUBSCRIBE_METHOD: &str = "unsubscribe";
+pub const BOOK_CHANNEL: &str = "book";
+/// The number of levels per side covered by the book checksum.
+pub const CHECKSUM_LEVELS: usize = 10;
+
+/// The book depth to subscribe to.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum BookDepth {
+    D10 = 10,
+    D25 = 25,
+    D100 = 100,
+    D500 = 500,
+    D1000 = 1000,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Request<P> {
+    pub method: String,
+    pub params: P,
+    pub req_id: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookParams {
+    pub channel: String,
+    pub symbol: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+}
+
+impl BookParams {
+    pub fn new(symbol: &str, depth: Option<BookDepth>) -> Self {
+        Self {
+            channel: BOOK_CHANNEL.to_string(),
+            symbol: vec![symbol.to_string()],
+            depth: depth.map(|depth| depth as u32),
+        }
+    }
+}
+
+/// The response to a request, matched by `req_id`.
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub method: String,
+    pub req_id: Option<u64>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl Response {
+    /// Maps an unsuccessful response to a typed error.
+    fn into_result(self, symbol: &str) -> Result<()> {
+        if self.success {
+            return Ok(());
+        }
+        let message = self.error.unwrap_or_default();
+        let lower = message.to_lowercase();
+        if lower.contains("currency pair not supported") || lower.contains("invalid symbol") {
+            Err(Error::UnknownSymbol(symbol.to_string()))
+        } else if lower.contains("rate") && lower.contains("exceeded") {
+            Err(Error::RateLimited(message))
+        } else {
+            Err(Error::SubscriptionRejected(message))
+        }
+    }
+}
+
+/// A `book` channel message.
+#[derive(Debug, Deserialize)]
+pub struct BookMessage {
+    pub channel: String,
+    /// `snapshot` or `update`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub data: Vec<BookData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BookData {
+    pub symbol: String,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+    pub checksum: u32,
+    #[serde(default)]
+    pub timestamp: String,
+}
+
+/// A price level as sent. The numbers are kept as their JSON text, which the
+/// checksum is computed from.
+#[derive(Debug, Deserialize)]
+pub struct BookLevel {
+    pub price: Box<RawValue>,
+    pub qty: Box<RawValue>,
+}
+
+/// Just the routing part of a [`BookMessage`].
+#[derive(Debug, Deserialize)]
+struct MessageHeader {
+    channel: String,
+    #[serde(default)]
+    data: Vec<SymbolHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SymbolHeader {
+    symbol: String,
+}
+
+/// The book of one symbol and the subscribed depth, which Kraken sends with
+/// every snapshot; levels pushed beyond it by updates are dropped.
+#[derive(Debug, Default)]
+struct Book {
+    book: LocalBook,
+    depth: usize,
+}
+
+impl Book {
+    /// Applies `levels` to one side, returning `None` if a price or quantity is
+    /// not a number.
+    fn apply_side(side: &mut Side, levels: &[BookLevel]) -> Option<()> {
+        levels
+            .iter()
+            .try_for_each(|level| LocalBook::update(side, level.price.get(), level.qty.get()))
+    }
+
+    /// The CRC32 of the best [`CHECKSUM_LEVELS`] asks and then bids, each level
+    /// being its price and quantity without the decimal point and leading zeros.
+    fn checksum(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        let asks = self.book.best_asks().take(CHECKSUM_LEVELS);
+        let bids = self.book.best_bids().take(CHECKSUM_LEVELS);
+        for entry in asks.chain(bids) {
+            for text in [&entry.price_text, &entry.amount_text] {
+                let digits = text.replace('.', "");
+                hasher.update(digits.trim_start_matches('0').as_bytes());
+            }
+        }
+        hasher.finalize()
+    }
+}
+
+/// The books of every symbol seen in a sequence of raw websocket frames.
+///
+/// Kraken computes its checksum over the subscribed depth, so books are cut to
+/// it; books made with `default`, e.g. for recordings, are cut to the depth of
+/// their snapshot instead.
+#[derive(Debug, Default)]
+pub struct Books {
+    books: HashMap<String, Book>,
+    depth: Option<usize>,
+}
+
+impl Books {
+    /// Books of symbols subscribed with `depth`.
+    pub fn with_depth(depth: BookDepth) -> Self {
+        Self {
+            books: HashMap::new(),
+            depth: Some(depth as usize),
+        }
+    }
+
+    /// Applies a raw websocket frame, returning the updated book of its symbol,
+    /// or `None` for frames that are not book events and for updates of
+    /// symbols without a snapshot yet.
+    ///
+    /// Fails with [`Error::ChecksumMismatch`] if the updated book does not match
+    /// Kraken's checksum; the symbol's book is dropped until the next snapshot.
+    pub fn apply(&mut self, frame: &str, best_of: usize) -> Result<Option<OrderBook>> {
+        let Ok(message) = serde_json::from_str::<BookMessage>(frame) else {
+            return Ok(None);
+        };
+        if message.channel != BOOK_CHANNEL {
+            return Ok(None);
+        }
+
+        let mut latest = None;
+        for data in message.data {
+            let book = match message.kind.as_str() {
+                "snapshot" => {
+                    let book = self.books.entry(data.symbol.clone()).or_default();
+                    *book = Book {
+                        depth: self
+                            .depth
+                            .unwrap_or_else(|| data.bids.len().max(data.asks.len())),
+                        ..Book::default()
+                    };
+                    book
+                }
+                "update" => match self.books.get_mut(&data.symbol) {
+                    Some(book) => book,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let applied = Book::apply_side(&mut book.book.bids, &data.bids)
+                .and_then(|_| Book::apply_side(&mut book.book.asks, &data.asks));
+            book.book.truncate(book.depth);
+            book.book.last_updated = data.timestamp;
+
+            if applied.is_none() || book.checksum() != data.checksum {
+                self.books.remove(&data.symbol);
+                return Err(Error::ChecksumMismatch(data.symbol));
+            }
+            latest = Some(book.book.to_order_book(Exchange::Kraken, best_of));
+        }
+        Ok(latest)
+    }
+}
+
+pub const DEFAULT_WS_BASE_URL: &str = "wss://ws.kraken.com/v2";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A WebSocket client for Kraken spot.
+pub struct KrakenClient {
+    connection: Connection,
+    /// How long [`Self::call`] waits for a response.
+    pub request_timeout: Duration,
+    // Shared with the book streams, which resubscribe on checksum mismatches.
+    next_id: Arc<AtomicU64>,
+    depths: HashMap<String, BookDepth>,
+}
+
+impl KrakenClient {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        let connection =
+            Connection::open(url, Exchange::Kraken, Ping::Frame, recorder, route).await?;
+
+        Ok(Self {
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            next_id: Arc::new(AtomicU64::new(0)),
+            depths: HashMap::new(),
+        })
+    }
+
+    pub async fn connect_public() -> Result<Self> {
+        Self::connect_public_with_recorder(None).await
+    }
+
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_with_recorder(DEFAULT_WS_BASE_URL, recorder).await
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// Kraken announces maintenance on the `status` channel and then closes the
+    /// connection, so this never resolves.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::pending()
+    }
+
+    /// Sends a message to the WebSocket.
+    pub async fn send<R>(&mut self, req: R) -> Result<()>
+    where
+        R: Serialize,
+    {
+        self.connection.send(req).await
+    }
+
+    /// Performs a remote procedure call, waiting up to `request_timeout` for the
+    /// response with the matching `req_id`.
+    pub async fn call<P>(&mut self, method: &str, params: P, symbol: &str) -> Result<()>
+    where
+        P: Debug + Serialize,
+    {
+        let req_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let req = Request {
+            method: method.to_string(),
+            params,
+            req_id,
+        };
+
+        let mut messages_receiver = self.messages();
+        self.send(req).await?;
+
+        let response = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Response>(&msg) {
+                        Ok(response) if response.req_id == Some(req_id) => {
+                            return response.into_result(symbol)
+                        }
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
+        };
+        tokio::time::timeout(self.request_timeout, response)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
+    }
+
+    /// Subscribes to the book of `symbol`, e.g. `BTC/USD`.
+    ///
+    /// Every update is checked against Kraken's checksum. On a mismatch the
+    /// stream skips updates and resubscribes for a fresh snapshot. Fails if
+    /// Kraken rejects the subscription or does not acknowledge it within
+    /// `request_timeout`.
+    // <https://docs.kraken.com/websockets-v2/#book>
+    pub async fn subscribe_orderbook(
+        &mut self,
+        symbol: &str,
+        depth: BookDepth,
+        best_of: usize,
+    ) -> Result<Subscription> {
+        // Register before sending the request so that no frame is missed.
+        let (handle, mut frames) = self.connection.registry().register(symbol);
+
+        let params = BookParams::new(symbol, Some(depth));
+        if let Err(e) = self.call(SUBSCRIBE_METHOD, params, symbol).await {
+            self.connection.registry().remove(&handle);
+            return Err(e);
+        }
+        self.depths.insert(symbol.to_string(), depth);
+
+        let sender = self.connection.sender();
+        let next_id = self.next_id.clone();
+        let symbol = symbol.to_string();
+        let book_events = stream! {
+            let mut books = Books::with_depth(depth);
+            while let Some(msg) = frames.recv().await {
+                match books.apply(&msg, best_of) {
+                    Ok(Some(book_event)) => yield book_event,
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("{e}, resubscribing");
+                        if let Err(e) = resubscribe(&sender, &next_id, &symbol, depth).await {
+                            tracing::error!("cannot resubscribe to {symbol}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(book_events),
+        })
+    }
+
+    /// Ends a subscription. Kraken is only asked to unsubscribe once no other
+    /// subscription of this client uses the symbol.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            let depth = self.depths.remove(&handle.channel);
+            let params = BookParams::new(&handle.channel, depth);
+            self.call(UNSUBSCRIBE_METHOD, params, &handle.channel)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Routes book messages to the subscriptions of their symbol; everything else
+/// only goes to `messages`.
+fn route(routes: &Registry, frame: &str) {
+    if let Ok(header) = serde_json::from_str::<MessageHeader>(frame) {
+        if header.channel == BOOK_CHANNEL {
+            if let Some(data) = header.data.first() {
+                routes.dispatch(&data.symbol, frame);
+            }
+        }
+    }
+}
+
+/// Unsubscribes from and subscribes to the book of `symbol` again, which makes
+/// Kraken send a fresh snapshot. The responses are not waited for.
+async fn resubscribe(
+    sender: &WsSender,
+    next_id: &AtomicU64,
+    symbol: &str,
+    depth: BookDepth,
+) -> Result<()> {
+    for method in [UNSUBSCRIBE_METHOD, SUBSCRIBE_METHOD] {
+        let req = Request {
+            method: method.to_string(),
+            params: BookParams::new(symbol, Some(depth)),
+            req_id: next_id.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        send(sender, req).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Borrowed parsing of the `[["price", "amount"], ..]` arrays the venues send
+//! the sides of their book snapshots as.
+
+use std::fmt;
+
+use serde::de::{DeserializeSeed, Error as _, IgnoredAny, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+
+use crate::types::Quote;
+
+/// The best `best_of` levels of a side, parsed straight from the frame's text
+/// without allocating per level; the levels beyond them are skipped unparsed.
+///
+/// Returns `None` if `side` is not an array of string pairs, or if one of the
+/// kept levels is not a number.
+pub fn top_quotes(side: &RawValue, best_of: usize) -> Option<Vec<Quote>> {
+    let mut deserializer = serde_json::Deserializer::from_str(side.get());
+    TopQuotes { best_of }.deserialize(&mut deserializer).ok()
+}
+
+struct TopQuotes {
+    best_of: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for TopQuotes {
+    type Value = Vec<Quote>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TopQuotes {
+    type Value = Vec<Quote>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of [price, amount] pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut quotes = Vec::with_capacity(self.best_of);
+        while quotes.len() < self.best_of {
+            let Some((price, amount)) = seq.next_element::<(&str, &str)>()? else {
+                break;
+            };
+            let quote = Quote::from_strs(price, amount)
+                .ok_or_else(|| A::Error::custom("level is not a number"))?;
+            quotes.push(quote);
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(quotes)
+    }
+}
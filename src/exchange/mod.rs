@@ -1,3 +1,13 @@
 pub mod binance_client;
 pub mod bitstamp_client;
+pub(crate) mod book;
+pub mod bybit_client;
+pub mod coinbase_client;
+pub mod connection;
 pub mod error;
+pub mod json_adapter;
+pub mod kraken_client;
+pub mod levels;
+pub mod okx_client;
+pub mod rest_client;
+pub mod subscription;
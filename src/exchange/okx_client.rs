@@ -0,0 +1,412 @@
+//! <https://www.okx.com/docs-v5/en/#order-book-trading-market-data-ws-order-book-channel>
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::exchange::book::{LocalBook, Side};
+use crate::exchange::connection::{send, Connection, Ping, WsSender};
+use crate::exchange::subscription::{Registry, Subscription, SubscriptionHandle};
+use crate::recorder::Recorder;
+use crate::types::{Exchange, OrderBook};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const SUBSCRIBE_OP: &str = "subscribe";
+pub const UNSUBSCRIBE_OP: &str = "unsubscribe";
+/// OKX closes connections that send nothing for 30 seconds, and answers this
+/// with `pong`.
+pub const PING: &str = "ping";
+/// The number of levels per side covered by the book checksum.
+pub const CHECKSUM_LEVELS: usize = 25;
+
+/// The book channel to subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookChannel {
+    /// 400 levels, a snapshot followed by updates every 100 ms.
+    Books,
+    /// 5 levels, each push a full snapshot, every 100 ms.
+    Books5,
+}
+
+impl BookChannel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BookChannel::Books => "books",
+            BookChannel::Books5 => "books5",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Request {
+    pub op: String,
+    pub args: Vec<Arg>,
+}
+
+/// A channel of an instrument, e.g. `books` of `BTC-USDT`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Arg {
+    pub channel: String,
+    #[serde(rename = "instId", default)]
+    pub inst_id: String,
+}
+
+impl Arg {
+    pub fn new(channel: BookChannel, inst_id: &str) -> Self {
+        Self {
+            channel: channel.name().to_string(),
+            inst_id: inst_id.to_string(),
+        }
+    }
+
+    /// What subscriptions are registered and pushes routed by, e.g.
+    /// `books:BTC-USDT`, as an instrument may be subscribed on several
+    /// channels of one connection.
+    pub fn route(&self) -> String {
+        format!("{}:{}", self.channel, self.inst_id)
+    }
+}
+
+/// The response to a request: `subscribe`, `unsubscribe` or `error`.
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub event: String,
+    pub arg: Option<Arg>,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+}
+
+impl Response {
+    /// Maps an `error` response to a typed error.
+    fn into_error(self, inst_id: &str) -> Error {
+        let lower = self.msg.to_lowercase();
+        if lower.contains("doesn't exist") || lower.contains("does not exist") {
+            Error::UnknownSymbol(inst_id.to_string())
+        } else if self.code == "60014" || lower.contains("too many") {
+            Error::RateLimited(self.msg)
+        } else {
+            Error::SubscriptionRejected(format!("{} {}", self.code, self.msg))
+        }
+    }
+}
+
+/// A book channel push.
+#[derive(Debug, Deserialize)]
+pub struct BookMessage {
+    pub arg: Arg,
+    /// `snapshot` or `update` on `books`, absent on `books5`, whose every push
+    /// is a snapshot.
+    pub action: Option<String>,
+    pub data: Vec<BookData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookData {
+    pub asks: Vec<BookLevel>,
+    pub bids: Vec<BookLevel>,
+    #[serde(default)]
+    pub ts: String,
+    /// Absent on `books5`.
+    pub checksum: Option<i32>,
+    #[serde(default)]
+    pub prev_seq_id: Option<i64>,
+    #[serde(default)]
+    pub seq_id: Option<i64>,
+}
+
+/// `[price, size, deprecated, number of orders]`, kept as sent, which the
+/// checksum is computed from.
+#[derive(Debug, Deserialize)]
+pub struct BookLevel(pub String, pub String, pub String, pub String);
+
+/// Just the routing part of a [`BookMessage`].
+#[derive(Debug, Deserialize)]
+struct MessageHeader {
+    arg: Arg,
+    // Only pushes carry `data`, responses echo `arg` as well.
+    #[serde(rename = "data")]
+    _data: serde::de::IgnoredAny,
+}
+
+/// The book of one instrument and the `seqId` of its last push.
+#[derive(Debug, Default)]
+struct Book {
+    book: LocalBook,
+    seq_id: Option<i64>,
+}
+
+impl Book {
+    /// Applies `levels` to one side, returning `None` if a price or size is
+    /// not a number.
+    fn apply_side(side: &mut Side, levels: &[BookLevel]) -> Option<()> {
+        levels
+            .iter()
+            .try_for_each(|level| LocalBook::update(side, &level.0, &level.1))
+    }
+
+    /// The CRC32, as a signed integer, of the best [`CHECKSUM_LEVELS`] bids
+    /// and asks interleaved as `bid price:bid size:ask price:ask size:...`.
+    fn checksum(&self) -> i32 {
+        let mut bids = self.book.best_bids().take(CHECKSUM_LEVELS);
+        let mut asks = self.book.best_asks().take(CHECKSUM_LEVELS);
+        let mut fields = Vec::new();
+        loop {
+            let (bid, ask) = (bids.next(), asks.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for entry in bid.into_iter().chain(ask) {
+                fields.push(entry.price_text.as_str());
+                fields.push(entry.amount_text.as_str());
+            }
+        }
+        crc32fast::hash(fields.join(":").as_bytes()) as i32
+    }
+}
+
+/// The books of every instrument seen in a sequence of raw websocket frames.
+#[derive(Debug, Default)]
+pub struct Books {
+    books: HashMap<String, Book>,
+}
+
+impl Books {
+    /// Applies a raw websocket frame, returning the updated book of its
+    /// instrument, or `None` for frames that are not book pushes and for
+    /// updates of instruments without a snapshot yet.
+    ///
+    /// Fails with [`Error::SequenceGap`] if an update does not follow the last
+    /// push, and with [`Error::ChecksumMismatch`] if the updated book does not
+    /// match OKX's checksum; either way the instrument's book is dropped until
+    /// the next snapshot.
+    pub fn apply(&mut self, frame: &str, best_of: usize) -> Result<Option<OrderBook>> {
+        let Ok(message) = serde_json::from_str::<BookMessage>(frame) else {
+            return Ok(None);
+        };
+        let inst_id = message.arg.inst_id;
+
+        let mut latest = None;
+        for data in message.data {
+            let book = match message.action.as_deref() {
+                None | Some("snapshot") => {
+                    let book = self.books.entry(inst_id.clone()).or_default();
+                    *book = Book::default();
+                    book
+                }
+                Some("update") => match self.books.get_mut(&inst_id) {
+                    Some(book) if data.prev_seq_id == book.seq_id => book,
+                    Some(_) => {
+                        self.books.remove(&inst_id);
+                        return Err(Error::SequenceGap(inst_id));
+                    }
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let applied = Book::apply_side(&mut book.book.bids, &data.bids)
+                .and_then(|_| Book::apply_side(&mut book.book.asks, &data.asks));
+            book.book.last_updated = data.ts;
+            book.seq_id = data.seq_id;
+
+            let matches = data.checksum.is_none_or(|sum| book.checksum() == sum);
+            if applied.is_none() || !matches {
+                self.books.remove(&inst_id);
+                return Err(Error::ChecksumMismatch(inst_id));
+            }
+            latest = Some(book.book.to_order_book(Exchange::Okx, best_of));
+        }
+        Ok(latest)
+    }
+}
+
+pub const DEFAULT_WS_BASE_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A WebSocket client for OKX public market data.
+pub struct OkxClient {
+    connection: Connection,
+    /// How long [`Self::subscribe`] waits for a response.
+    pub request_timeout: Duration,
+    /// The channel and instrument of each route, to unsubscribe from.
+    channels: HashMap<String, (BookChannel, String)>,
+}
+
+impl OkxClient {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with_recorder(url, None).await
+    }
+
+    /// Connects like [`Self::connect`], additionally handing every received text
+    /// frame to `recorder`.
+    pub async fn connect_with_recorder(url: &str, recorder: Option<Recorder>) -> Result<Self> {
+        let connection =
+            Connection::open(url, Exchange::Okx, Ping::Text(PING), recorder, route).await?;
+
+        Ok(Self {
+            connection,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            channels: HashMap::new(),
+        })
+    }
+
+    pub async fn connect_public() -> Result<Self> {
+        Self::connect_public_with_recorder(None).await
+    }
+
+    pub async fn connect_public_with_recorder(recorder: Option<Recorder>) -> Result<Self> {
+        Self::connect_with_recorder(DEFAULT_WS_BASE_URL, recorder).await
+    }
+
+    /// Returns a receiver of every raw text frame received from now on.
+    pub fn messages(&self) -> tokio::sync::broadcast::Receiver<String> {
+        self.connection.messages()
+    }
+
+    /// OKX announces service upgrades by email rather than on the connection,
+    /// so this never resolves.
+    pub fn handover_requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::pending()
+    }
+
+    /// Sends a message to the WebSocket.
+    pub async fn send<R>(&mut self, req: R) -> Result<()>
+    where
+        R: Serialize,
+    {
+        self.connection.send(req).await
+    }
+
+    /// Sends an `op` request for `channel` of `inst_id` and waits up to
+    /// `request_timeout` for the matching event, or fails on `error`.
+    async fn request(&mut self, op: &str, channel: BookChannel, inst_id: &str) -> Result<()> {
+        let mut messages_receiver = self.messages();
+        let arg = Arg::new(channel, inst_id);
+        self.send(Request {
+            op: op.to_string(),
+            args: vec![arg.clone()],
+        })
+        .await?;
+
+        let response = async {
+            loop {
+                match messages_receiver.recv().await {
+                    Ok(msg) => match serde_json::from_str::<Response>(&msg) {
+                        Ok(response) if response.event == "error" => {
+                            return Err(response.into_error(inst_id))
+                        }
+                        Ok(Response {
+                            event,
+                            arg: Some(acked),
+                            ..
+                        }) if event == op
+                            && acked.channel == arg.channel
+                            && acked.inst_id == arg.inst_id =>
+                        {
+                            return Ok(())
+                        }
+                        _ => {}
+                    },
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Err(Error::ConnectionClosed),
+                }
+            }
+        };
+        tokio::time::timeout(self.request_timeout, response)
+            .await
+            .map_err(|_| Error::Timeout(self.request_timeout.as_millis() as u64))?
+    }
+
+    /// Subscribes to `channel` of `inst_id` and waits up to `request_timeout`
+    /// for the acknowledgement.
+    pub async fn subscribe(&mut self, channel: BookChannel, inst_id: &str) -> Result<()> {
+        self.request(SUBSCRIBE_OP, channel, inst_id).await
+    }
+
+    /// Subscribes to the book of `inst_id`, e.g. `BTC-USDT`.
+    ///
+    /// Every update of `books` is checked against the previous `seqId` and
+    /// OKX's checksum. On a gap or mismatch the stream skips updates and
+    /// resubscribes for a fresh snapshot. Fails if OKX rejects the
+    /// subscription or does not acknowledge it within `request_timeout`.
+    pub async fn subscribe_orderbook(
+        &mut self,
+        inst_id: &str,
+        channel: BookChannel,
+        best_of: usize,
+    ) -> Result<Subscription> {
+        // Register before sending the request so that no frame is missed.
+        let route = Arg::new(channel, inst_id).route();
+        let (handle, mut frames) = self.connection.registry().register(&route);
+
+        if let Err(e) = self.subscribe(channel, inst_id).await {
+            self.connection.registry().remove(&handle);
+            return Err(e);
+        }
+        self.channels.insert(route, (channel, inst_id.to_string()));
+
+        let sender = self.connection.sender();
+        let inst_id = inst_id.to_string();
+        let book_events = stream! {
+            let mut books = Books::default();
+            while let Some(msg) = frames.recv().await {
+                match books.apply(&msg, best_of) {
+                    Ok(Some(book_event)) => yield book_event,
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("{e}, resubscribing");
+                        if let Err(e) = resubscribe(&sender, channel, &inst_id).await {
+                            tracing::error!("cannot resubscribe to {inst_id}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Subscription {
+            handle,
+            book_events: Box::pin(book_events),
+        })
+    }
+
+    /// Ends a subscription. OKX is only asked to unsubscribe once no other
+    /// subscription of this client uses the channel of the instrument.
+    pub async fn unsubscribe(&mut self, handle: &SubscriptionHandle) -> Result<()> {
+        if self.connection.registry().remove(handle) {
+            if let Some((channel, inst_id)) = self.channels.remove(&handle.channel) {
+                self.request(UNSUBSCRIBE_OP, channel, &inst_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Routes pushes to the subscriptions of their channel and instrument;
+/// everything else only goes to `messages`.
+fn route(routes: &Registry, frame: &str) {
+    if let Ok(header) = serde_json::from_str::<MessageHeader>(frame) {
+        routes.dispatch(&header.arg.route(), frame);
+    }
+}
+
+/// Unsubscribes from and subscribes to `channel` of `inst_id` again, which
+/// makes OKX send a fresh snapshot. The responses are not waited for.
+async fn resubscribe(sender: &WsSender, channel: BookChannel, inst_id: &str) -> Result<()> {
+    for op in [UNSUBSCRIBE_OP, SUBSCRIBE_OP] {
+        let req = Request {
+            op: op.to_string(),
+            args: vec![Arg::new(channel, inst_id)],
+        };
+        send(sender, req).await?;
+    }
+    Ok(())
+}
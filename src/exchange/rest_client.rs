@@ -0,0 +1,164 @@
+//! Order books polled from the venues' REST depth endpoints, the fallback for
+//! when their websockets are unavailable.
+//!
+//! <https://binance-docs.github.io/apidocs/spot/en/#order-book>
+//! <https://www.bitstamp.net/api/#order-book>
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::exchange::binance_client::{BinanceBookEvent, Market};
+use crate::exchange::bitstamp_client::{BitstampBookEvent, BookData};
+use crate::types::{Exchange, OrderBook};
+
+use crate::exchange::error::Error;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const BITSTAMP_REST_BASE_URL: &str = "https://www.bitstamp.net";
+pub const BINANCE_REST_BASE_URL: &str = "https://api.binance.com";
+pub const USDM_FUTURES_REST_BASE_URL: &str = "https://fapi.binance.com";
+pub const COINM_FUTURES_REST_BASE_URL: &str = "https://dapi.binance.com";
+pub const US_REST_BASE_URL: &str = "https://api.binance.us";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The depths Binance's futures endpoints accept; spot accepts any up to 5000.
+const BINANCE_LIMITS: [usize; 7] = [5, 10, 20, 50, 100, 500, 1000];
+
+/// A venue with a REST depth endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestVenue {
+    Bitstamp,
+    Binance(Market),
+}
+
+impl RestVenue {
+    /// The venue whose books are tagged with `exchange`, if it has a REST
+    /// depth endpoint.
+    pub fn from_exchange(exchange: Exchange) -> Option<RestVenue> {
+        match exchange {
+            Exchange::Bitstamp => Some(RestVenue::Bitstamp),
+            _ => Market::from_exchange(exchange).map(RestVenue::Binance),
+        }
+    }
+
+    pub fn exchange(&self) -> Exchange {
+        match self {
+            RestVenue::Bitstamp => Exchange::Bitstamp,
+            RestVenue::Binance(market) => market.exchange(),
+        }
+    }
+
+    /// The public REST endpoint.
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            RestVenue::Bitstamp => BITSTAMP_REST_BASE_URL,
+            RestVenue::Binance(Market::Spot) => BINANCE_REST_BASE_URL,
+            RestVenue::Binance(Market::UsdM) => USDM_FUTURES_REST_BASE_URL,
+            RestVenue::Binance(Market::CoinM) => COINM_FUTURES_REST_BASE_URL,
+            RestVenue::Binance(Market::Us) => US_REST_BASE_URL,
+        }
+    }
+
+    /// The path and query of the book of `symbol`, with at least `best_of`
+    /// levels where the venue takes a depth, e.g.
+    /// `/api/v3/depth?symbol=BTCUSDT&limit=10`.
+    pub fn depth_path(&self, symbol: &str, best_of: usize) -> String {
+        let path = match self {
+            RestVenue::Bitstamp => {
+                return format!("/api/v2/order_book/{}/", symbol.to_lowercase());
+            }
+            RestVenue::Binance(Market::Spot | Market::Us) => "/api/v3/depth",
+            RestVenue::Binance(Market::UsdM) => "/fapi/v1/depth",
+            RestVenue::Binance(Market::CoinM) => "/dapi/v1/depth",
+        };
+        let limit = BINANCE_LIMITS
+            .into_iter()
+            .find(|&limit| limit >= best_of)
+            .unwrap_or(1000);
+        format!("{path}?symbol={}&limit={limit}", symbol.to_uppercase())
+    }
+
+    /// Parses a depth response into a degraded [`OrderBook`] holding the best
+    /// `best_of` levels per side.
+    pub fn parse_order_book(&self, body: &str, best_of: usize) -> Result<OrderBook> {
+        let ob = match self {
+            // The response is the `data` of a websocket book event.
+            RestVenue::Bitstamp => BitstampBookEvent {
+                data: serde_json::from_str::<BookData>(body)?,
+            }
+            .to_order_book(best_of),
+            // Spot and futures responses both have `lastUpdateId`, `bids` and
+            // `asks`, like a spot partial depth event.
+            RestVenue::Binance(market) => serde_json::from_str::<BinanceBookEvent>(body)?
+                .to_order_book(market.exchange(), best_of),
+        };
+        let mut ob = ob.ok_or_else(|| Error::MalformedJSON("level is not a number".into()))?;
+        ob.degraded = true;
+        Ok(ob)
+    }
+}
+
+/// Maps a failed response to a typed error.
+fn into_error(status: StatusCode, body: String, symbol: &str) -> Error {
+    let lower = body.to_lowercase();
+    if status == StatusCode::NOT_FOUND || lower.contains("invalid symbol") {
+        Error::UnknownSymbol(symbol.to_string())
+    } else if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT {
+        Error::RateLimited(body)
+    } else {
+        Error::Http(format!("{status}: {body}"))
+    }
+}
+
+/// A REST client polling the depth endpoint of one venue.
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    venue: RestVenue,
+    base_url: String,
+    http: reqwest::Client,
+    /// How long [`Self::order_book`] waits for a response.
+    pub request_timeout: Duration,
+}
+
+impl RestClient {
+    pub fn new(venue: RestVenue, base_url: &str) -> Self {
+        Self {
+            venue,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    pub fn public(venue: RestVenue) -> Self {
+        Self::new(venue, venue.base_url())
+    }
+
+    pub fn venue(&self) -> RestVenue {
+        self.venue
+    }
+
+    /// Fetches the book of `symbol`, holding the best `best_of` levels per side
+    /// and marked as degraded.
+    pub async fn order_book(&self, symbol: &str, best_of: usize) -> Result<OrderBook> {
+        let url = format!(
+            "{}{}",
+            self.base_url,
+            self.venue.depth_path(symbol, best_of)
+        );
+        tracing::debug!("GET {url}");
+        let response = self
+            .http
+            .get(url)
+            .timeout(self.request_timeout)
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(into_error(status, body, symbol));
+        }
+        self.venue.parse_order_book(&body, best_of)
+    }
+}
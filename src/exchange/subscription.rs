@@ -0,0 +1,105 @@
+//! Routing of raw frames to the subscriptions of one exchange connection.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+use crate::types::OrderBookStream;
+
+/// Identifies one subscription of a client, for [`unsubscribe`]-ing it later.
+///
+/// [`unsubscribe`]: crate::exchange::binance_client::BinanceClient::unsubscribe
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriptionHandle {
+    pub id: u64,
+    /// The exchange's name for the subscribed channel, e.g. `order_book_btcusd`.
+    pub channel: String,
+}
+
+/// A subscription and the order books of its channel.
+///
+/// `book_events` ends once the subscription is unsubscribed or the connection
+/// closes.
+pub struct Subscription {
+    pub handle: SubscriptionHandle,
+    pub book_events: OrderBookStream,
+}
+
+type Routes = HashMap<String, Vec<(u64, mpsc::UnboundedSender<String>)>>;
+
+/// The subscriptions of one connection, shared between the client and its
+/// reader task.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Registry {
+    inner: Arc<Mutex<RegistryInner>>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryInner {
+    routes: Routes,
+    next_id: u64,
+}
+
+impl Registry {
+    /// Adds a subscription to `channel`, returning its handle and the receiver of
+    /// the channel's frames.
+    pub(crate) fn register(
+        &self,
+        channel: &str,
+    ) -> (SubscriptionHandle, mpsc::UnboundedReceiver<String>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner
+            .routes
+            .entry(channel.to_string())
+            .or_default()
+            .push((id, tx));
+        let handle = SubscriptionHandle {
+            id,
+            channel: channel.to_string(),
+        };
+        (handle, rx)
+    }
+
+    /// Removes a subscription, returning whether it was the last one of its
+    /// channel, i.e. whether the exchange should be told to unsubscribe.
+    pub(crate) fn remove(&self, handle: &SubscriptionHandle) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(subscribers) = inner.routes.get_mut(&handle.channel) else {
+            return false;
+        };
+        let before = subscribers.len();
+        subscribers.retain(|(id, _)| *id != handle.id);
+        if subscribers.len() == before || !subscribers.is_empty() {
+            return false;
+        }
+        inner.routes.remove(&handle.channel);
+        true
+    }
+
+    /// Hands `frame` to every subscription of `channel`.
+    pub(crate) fn dispatch(&self, channel: &str, frame: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(subscribers) = inner.routes.get_mut(channel) {
+            // Subscriptions whose stream was dropped stop receiving frames.
+            subscribers.retain(|(_, tx)| tx.send(frame.to_string()).is_ok());
+        }
+    }
+
+    /// Ends the streams of every subscription, once the connection closed.
+    pub(crate) fn close(&self) {
+        self.inner.lock().unwrap().routes.clear();
+    }
+
+    /// Hands `frame` to every subscription, for connections whose frames do not
+    /// name their channel.
+    pub(crate) fn dispatch_all(&self, frame: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        for subscribers in inner.routes.values_mut() {
+            subscribers.retain(|(_, tx)| tx.send(frame.to_string()).is_ok());
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! An HTTP gateway for consumers that can't speak gRPC, such as browsers:
+//! the summaries of the manager's broadcast channel as JSON, over WebSocket
+//! at `/ws` and Server-Sent Events at `/sse`.
+//!
+//! Both take the [`Selection`] of a `SummaryRequest` as query parameters,
+//! e.g. `/ws?depth=5&venues=binance,kraken`. Levels always carry their
+//! venue's name.
+//!
+//! With an [`Auth`], every route needs an API key like the gRPC service does,
+//! as a header or, for browsers' WebSockets which cannot set one, as `key=`.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_stream::stream;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tonic::{Code, Status};
+
+use crate::auth::{key_of, Auth, Grant, StreamPermit};
+use crate::types::{Exchange, Level, Selection, Summary, SummaryRequest};
+
+/// The query parameters of `/ws` and `/sse`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SummaryQuery {
+    /// The most levels per side, or `0` for all.
+    #[serde(default)]
+    pub depth: u32,
+    /// Comma separated venue names, or none for all.
+    #[serde(default)]
+    pub venues: Option<String>,
+    /// The API key, for clients that cannot send it as a header.
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// The query parameters of `/venues`.
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyQuery {
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl SummaryQuery {
+    pub fn selection(&self) -> Result<Selection, String> {
+        let venues = self
+            .venues
+            .iter()
+            .flat_map(|venues| venues.split(','))
+            .map(str::trim)
+            .filter(|venue| !venue.is_empty())
+            .map(str::to_string)
+            .collect();
+        Selection::from_request(&SummaryRequest {
+            venue_names: true,
+            depth: self.depth,
+            venues,
+        })
+    }
+}
+
+/// A [`Summary`] as the gateway sends it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSummary {
+    pub spread: f64,
+    pub bids: Vec<JsonLevel>,
+    pub asks: Vec<JsonLevel>,
+    pub degraded: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonLevel {
+    pub exchange: String,
+    pub venue: u32,
+    pub price: f64,
+    pub amount: f64,
+}
+
+impl From<Summary> for JsonSummary {
+    fn from(summary: Summary) -> Self {
+        let levels = |levels: Vec<Level>| {
+            levels
+                .into_iter()
+                .map(|level| JsonLevel {
+                    exchange: level.exchange,
+                    venue: level.venue,
+                    price: level.price,
+                    amount: level.amount,
+                })
+                .collect()
+        };
+        JsonSummary {
+            spread: summary.spread,
+            bids: levels(summary.bids),
+            asks: levels(summary.asks),
+            degraded: summary.degraded,
+        }
+    }
+}
+
+/// The summary as the JSON text the gateway sends.
+fn to_json(summary: Summary, selection: &Selection) -> String {
+    let summary = JsonSummary::from(selection.apply(summary));
+    serde_json::to_string(&summary).expect("summaries serialize")
+}
+
+#[derive(Clone)]
+struct GatewayState {
+    s_tx: broadcast::Sender<Summary>,
+    auth: Option<Auth>,
+}
+
+#[allow(clippy::result_large_err)] // Refusals are the `Status` of `Auth`.
+impl GatewayState {
+    /// The caller's grant if the gateway requires keys, taken from the
+    /// `authorization` or `x-api-key` header, or else the `key` parameter.
+    fn grant(&self, headers: &HeaderMap, key: Option<&str>) -> Result<Option<Arc<Grant>>, Status> {
+        let Some(auth) = &self.auth else {
+            return Ok(None);
+        };
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let key = key_of(header("authorization"), header("x-api-key")).or(key);
+        auth.authorize(key).map(Some)
+    }
+
+    /// The selection of `query`, restricted to `grant`, and the stream's permit.
+    fn open(
+        &self,
+        headers: &HeaderMap,
+        query: &SummaryQuery,
+    ) -> Result<(Selection, Option<StreamPermit>), Status> {
+        let mut selection = query.selection().map_err(Status::invalid_argument)?;
+        let permit = match self.grant(headers, query.key.as_deref())? {
+            Some(grant) => {
+                grant.restrict(&mut selection)?;
+                Some(grant.open_stream()?)
+            }
+            None => None,
+        };
+        Ok((selection, permit))
+    }
+}
+
+/// The gateway's routes, serving the summaries sent on `s_tx`.
+pub fn router(s_tx: broadcast::Sender<Summary>) -> Router {
+    router_with(s_tx, None)
+}
+
+/// [`router`] requiring the keys of `auth`, if any.
+pub fn router_with(s_tx: broadcast::Sender<Summary>, auth: Option<Auth>) -> Router {
+    Router::new()
+        .route("/ws", get(websocket))
+        .route("/sse", get(sse))
+        .route("/venues", get(venues))
+        .with_state(GatewayState { s_tx, auth })
+}
+
+/// Serves the gateway on an already bound listener.
+pub async fn serve_gateway(listener: TcpListener, s_tx: broadcast::Sender<Summary>) {
+    serve_gateway_with(listener, s_tx, None).await
+}
+
+pub async fn serve_gateway_with(
+    listener: TcpListener,
+    s_tx: broadcast::Sender<Summary>,
+    auth: Option<Auth>,
+) {
+    let listener = listener.into_std().expect("listener is valid");
+    let server = axum::Server::from_tcp(listener)
+        .expect("listener is valid")
+        .serve(router_with(s_tx, auth).into_make_service());
+    if let Err(e) = server.await {
+        eprintln!("Gateway stopped: {e}");
+    }
+}
+
+pub async fn start_gateway(addr: SocketAddr, s_tx: broadcast::Sender<Summary>) {
+    start_gateway_with(addr, s_tx, None).await
+}
+
+pub async fn start_gateway_with(
+    addr: SocketAddr,
+    s_tx: broadcast::Sender<Summary>,
+    auth: Option<Auth>,
+) {
+    let listener = TcpListener::bind(addr).await.unwrap();
+    serve_gateway_with(listener, s_tx, auth).await
+}
+
+/// A refused request as the HTTP status it stands for.
+fn refused(status: Status) -> Response {
+    let code = match status.code() {
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (code, status.message().to_string()).into_response()
+}
+
+async fn websocket(
+    ws: WebSocketUpgrade,
+    Query(query): Query<SummaryQuery>,
+    headers: HeaderMap,
+    State(state): State<GatewayState>,
+) -> Response {
+    let (selection, permit) = match state.open(&headers, &query) {
+        Ok(opened) => opened,
+        Err(status) => return refused(status),
+    };
+    let s_rx = state.s_tx.subscribe();
+    ws.on_upgrade(move |socket| async move {
+        forward_to_websocket(socket, s_rx, selection).await;
+        drop(permit);
+    })
+}
+
+/// Sends every summary until the client goes away; summaries the client was
+/// too slow for are skipped.
+async fn forward_to_websocket(
+    mut socket: WebSocket,
+    mut s_rx: broadcast::Receiver<Summary>,
+    selection: Selection,
+) {
+    loop {
+        tokio::select! {
+            summary = s_rx.recv() => match summary {
+                Ok(summary) => {
+                    let json = to_json(summary, &selection);
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn sse(
+    Query(query): Query<SummaryQuery>,
+    headers: HeaderMap,
+    State(state): State<GatewayState>,
+) -> Response {
+    let (selection, permit) = match state.open(&headers, &query) {
+        Ok(opened) => opened,
+        Err(status) => return refused(status),
+    };
+    let mut s_rx = state.s_tx.subscribe();
+    let events = stream! {
+        // Held until the client goes away and the stream is dropped.
+        let _permit = permit;
+        loop {
+            match s_rx.recv().await {
+                Ok(summary) => {
+                    yield Ok::<_, Infallible>(Event::default().data(to_json(summary, &selection)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Every venue known to the server, or those the caller's [`Grant`] permits,
+/// as `{"id": .., "name": ..}`.
+async fn venues(
+    Query(query): Query<KeyQuery>,
+    headers: HeaderMap,
+    State(state): State<GatewayState>,
+) -> Response {
+    let grant = match state.grant(&headers, query.key.as_deref()) {
+        Ok(grant) => grant,
+        Err(status) => return refused(status),
+    };
+    let venues = Exchange::all()
+        .into_iter()
+        .filter(|exchange| grant.as_ref().is_none_or(|grant| grant.allows(*exchange)))
+        .map(|exchange| serde_json::json!({"id": exchange.id(), "name": exchange.name()}))
+        .collect::<Vec<_>>();
+    axum::Json(venues).into_response()
+}
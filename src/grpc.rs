@@ -1,53 +1,245 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
+use tokio::time::{Instant, MissedTickBehavior};
 
-use tokio_stream::wrappers::ReceiverStream;
-use tonic::transport::Server;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
 use tonic::{Request, Response, Status};
+use tonic_web::GrpcWebLayer;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
 
+use crate::auth::{Auth, Grant};
 use crate::types::{
-    Empty, Exchange, OrderBook, OrderbookAggregator, OrderbookAggregatorServer, Summary,
+    Empty, Exchange, OrderBook, OrderbookAggregator, OrderbookAggregatorServer, Selection, Summary,
+    SummaryRequest, Venue, VenueList,
 };
 
+/// How the gRPC server accepts clients besides plain gRPC ones.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcConfig {
+    /// Whether to also accept gRPC-Web requests, which browsers send over
+    /// HTTP/1.1 as well as HTTP/2.
+    pub grpc_web: bool,
+    /// The origins a page may call the server from with gRPC-Web, e.g.
+    /// `https://dashboard.example.com`, or `*` for any. With none, only pages
+    /// served from the server's own origin can.
+    pub cors_origins: Vec<String>,
+    /// Serves over TLS instead of plaintext when set.
+    pub tls: Option<ServerTls>,
+    /// Requires an API key of every call when set, see [`Auth`].
+    pub auth: Option<Auth>,
+}
+
+/// The server's TLS identity and, for mutual TLS, the CA that client
+/// certificates must be signed by.
+#[derive(Debug, Clone)]
+pub struct ServerTls {
+    /// The PEM encoded certificate chain.
+    pub cert: Vec<u8>,
+    /// The PEM encoded private key of the certificate.
+    pub key: Vec<u8>,
+    /// The PEM encoded CA certificates to verify clients with. With any, a
+    /// client without a certificate they signed is refused.
+    pub client_ca: Option<Vec<u8>>,
+}
+
+impl ServerTls {
+    /// Reads the PEM files at the given paths.
+    pub fn load(cert: &Path, key: &Path, client_ca: Option<&Path>) -> io::Result<Self> {
+        Ok(Self {
+            cert: fs::read(cert)?,
+            key: fs::read(key)?,
+            client_ca: client_ca.map(fs::read).transpose()?,
+        })
+    }
+
+    fn server_config(&self) -> ServerTlsConfig {
+        let config = ServerTlsConfig::new().identity(Identity::from_pem(&self.cert, &self.key));
+        match &self.client_ca {
+            Some(ca) => config.client_ca_root(Certificate::from_pem(ca)),
+            None => config,
+        }
+    }
+}
+
 pub async fn start_grpc_server(server: &str, s_tx_clone: broadcast::Sender<Summary>) {
-    let addr = server.parse().unwrap();
+    start_grpc_server_with(server, s_tx_clone, GrpcConfig::default()).await
+}
+
+pub async fn start_grpc_server_with(
+    server: &str,
+    s_tx_clone: broadcast::Sender<Summary>,
+    config: GrpcConfig,
+) {
+    let listener = TcpListener::bind(server).await.unwrap();
+    serve_grpc_with(listener, s_tx_clone, config).await
+}
+
+/// Serves the aggregator on an already bound listener, e.g. one on an
+/// OS-assigned port.
+pub async fn serve_grpc(listener: TcpListener, s_tx_clone: broadcast::Sender<Summary>) {
+    serve_grpc_with(listener, s_tx_clone, GrpcConfig::default()).await
+}
+
+pub async fn serve_grpc_with(
+    listener: TcpListener,
+    s_tx_clone: broadcast::Sender<Summary>,
+    config: GrpcConfig,
+) {
     let oas = OrderbookAggregatorService {
         s_tx: Some(s_tx_clone),
     };
+    let auth = config.auth.clone();
+    #[allow(clippy::result_large_err)] // The signature tonic expects.
+    let service =
+        InterceptedService::new(
+            OrderbookAggregatorServer::new(oas),
+            move |request| match &auth {
+                Some(auth) => auth.authenticate(request),
+                None => Ok(request),
+            },
+        );
+    let incoming = TcpListenerStream::new(listener);
 
-    let _ = Server::builder()
-        .add_service(OrderbookAggregatorServer::new(oas))
-        .serve(addr)
-        .await;
+    let mut builder = Server::builder();
+    if let Some(tls) = &config.tls {
+        builder = match builder.tls_config(tls.server_config()) {
+            Ok(builder) => builder,
+            Err(e) => {
+                eprintln!("Invalid TLS configuration: {e}");
+                return;
+            }
+        };
+    }
+    let result = if config.grpc_web {
+        builder
+            .accept_http1(true)
+            .layer(cors(&config.cors_origins))
+            .layer(GrpcWebLayer::new())
+            .add_service(service)
+            .serve_with_incoming(incoming)
+            .await
+    } else {
+        builder
+            .add_service(service)
+            .serve_with_incoming(incoming)
+            .await
+    };
+    if let Err(e) = result {
+        eprintln!("gRPC server stopped: {e}");
+    }
+}
+
+/// The CORS policy letting pages from `origins` call the server with gRPC-Web
+/// and read its status trailers.
+fn cors(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|origin| {
+            let value = HeaderValue::from_str(origin);
+            if value.is_err() {
+                eprintln!("Ignoring invalid CORS origin: {origin:?}");
+            }
+            value.ok()
+        }))
+    };
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::POST])
+        .allow_headers(AllowHeaders::mirror_request())
+        .expose_headers([
+            HeaderName::from_static("grpc-status"),
+            HeaderName::from_static("grpc-message"),
+            HeaderName::from_static("grpc-status-details-bin"),
+        ])
+        .max_age(Duration::from_secs(24 * 60 * 60))
 }
 
+/// How long [`manager`] merges a venue's book without an update from it.
+pub const STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// Merges the latest book of each of `venues` into a [`Summary`] whenever one
+/// of them updates, see [`manager_with`], with books going stale after
+/// [`STALE_AFTER`].
 pub async fn manager(
+    venues: Vec<Exchange>,
+    rx: broadcast::Receiver<OrderBook>,
+    s_tx: broadcast::Sender<Summary>,
+    best_of: usize,
+) {
+    manager_with(venues, rx, s_tx, best_of, STALE_AFTER).await
+}
+
+/// Merges the latest book of each of `venues` into a [`Summary`] whenever one
+/// of them updates. Levels at the same price are ordered like `venues`.
+///
+/// A venue without a book, or whose book is older than `stale_after` because
+/// its feed stopped or cannot reconnect, is left out and listed in
+/// `degraded`; a summary is also sent when a book goes stale. The first
+/// summary waits for every venue's first book, but at most `stale_after`.
+pub async fn manager_with(
+    venues: Vec<Exchange>,
     mut rx: broadcast::Receiver<OrderBook>,
     s_tx: broadcast::Sender<Summary>,
     best_of: usize,
+    stale_after: Duration,
 ) {
-    let mut ob_bitstamp = None;
-    let mut ob_binance = None;
-    while let Ok(ob) = rx.recv().await {
-        // println!("GOT_manager = {:?}", ob.last_updated);
-        match ob.exchange {
-            Exchange::Binance => {
-                ob_binance = Some(ob);
-            }
-            Exchange::Bitstamp => {
-                ob_bitstamp = Some(ob);
-            }
+    let mut books: HashMap<Exchange, (OrderBook, Instant)> = HashMap::new();
+    let started = Instant::now();
+    let mut sent_first = false;
+    let mut missing = Vec::new();
+    // A zero period would panic.
+    let mut check = tokio::time::interval((stale_after / 4).max(Duration::from_millis(10)));
+    check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let updated = tokio::select! {
+            ob = rx.recv() => match ob {
+                Ok(ob) => {
+                    books.insert(ob.exchange, (ob, Instant::now()));
+                    true
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    eprintln!("Manager lagged, skipped {skipped} order books");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = check.tick() => false,
+        };
+        let now = Instant::now();
+        let (fresh, left_out): (Vec<_>, Vec<_>) = venues.iter().partition(|venue| {
+            books
+                .get(venue)
+                .is_some_and(|(_, received)| now - *received <= stale_after)
+        });
+        if !sent_first
+            && now - started < stale_after
+            && !venues.iter().all(|venue| books.contains_key(venue))
+        {
+            continue;
         }
-        if ob_binance.is_none() || ob_bitstamp.is_none() {
+        // Only updates and venues going stale or coming back change the summary.
+        if !updated && left_out == missing {
             continue;
         }
-        let ob_merged = Summary::merge(
-            ob_bitstamp.clone().unwrap(),
-            ob_binance.clone().unwrap(),
-            best_of,
-        );
+        let latest = fresh.iter().map(|venue| books[venue].0.clone());
+        let mut ob_merged = Summary::merge_all(latest, best_of);
+        ob_merged
+            .degraded
+            .extend(left_out.iter().map(|venue| venue.to_string()));
+        missing = left_out;
+        sent_first = true;
 
-        // println!("{} {}", &ob_merged.bids.len(), &ob_merged.asks.len());
         if let Err(e) = s_tx.send(ob_merged) {
             eprintln!("Error sending message: {}", e);
         }
@@ -63,30 +255,63 @@ pub struct OrderbookAggregatorService {
 impl OrderbookAggregator for OrderbookAggregatorService {
     type BookSummaryStream = ReceiverStream<Result<Summary, Status>>;
 
+    /// Streams every summary, cut down to the request's [`Selection`] and
+    /// to what the caller's [`Grant`] permits, if any.
     async fn book_summary(
         &self,
-        _request: Request<Empty>,
+        request: Request<SummaryRequest>,
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
-        println!("Got a request: {:?}", _request);
+        let mut selection =
+            Selection::from_request(request.get_ref()).map_err(Status::invalid_argument)?;
+        // Only the selection: the metadata may carry an API key.
+        println!(
+            "Got a request from {:?}: {selection:?}",
+            request.remote_addr()
+        );
+        let permit = match request.extensions().get::<Arc<Grant>>() {
+            Some(grant) => {
+                grant.restrict(&mut selection)?;
+                Some(grant.open_stream()?)
+            }
+            None => None,
+        };
 
         let mut s_rx = self.s_tx.clone().expect("not connected").subscribe();
 
         let (tx, rx) = mpsc::channel(4);
         tokio::spawn(async move {
+            // Held until the client goes away.
+            let _permit = permit;
             loop {
-                let data = s_rx.recv().await;
-                if let Ok(ob) = data {
-                    match tx.send(Ok(ob)).await {
-                        Ok(_) => {}
-                        Err(_) => {
-                            println!("Stopped sending data to gRPC client");
+                let data = tokio::select! {
+                    data = s_rx.recv() => data,
+                    _ = tx.closed() => break,
+                };
+                match data {
+                    Ok(ob) => {
+                        if tx.send(Ok(selection.apply(ob))).await.is_err() {
                             break;
                         }
-                    };
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
                 }
             }
+            println!("Stopped sending data to gRPC client");
         });
 
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    /// Lists every venue known to the server, by id, or those the caller's
+    /// [`Grant`] permits.
+    async fn venues(&self, request: Request<Empty>) -> Result<Response<VenueList>, Status> {
+        let grant = request.extensions().get::<Arc<Grant>>();
+        let venues = Exchange::all()
+            .into_iter()
+            .filter(|exchange| grant.is_none_or(|grant| grant.allows(*exchange)))
+            .map(Venue::from)
+            .collect();
+        Ok(Response::new(VenueList { venues }))
+    }
 }
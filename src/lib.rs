@@ -0,0 +1,14 @@
+pub mod aggregator_client;
+pub mod auth;
+pub mod config;
+pub mod decimal;
+pub mod exchange;
+pub mod gateway;
+pub mod grpc;
+pub mod output;
+pub mod recorder;
+pub mod registry;
+pub mod replay;
+pub mod streaming;
+pub mod tui;
+pub mod types;
@@ -0,0 +1,287 @@
+//! Writes the merged books an [`AggregatorClient`](crate::aggregator_client::AggregatorClient)
+//! receives as data files, flattened into one [`LevelRow`] per level: JSON
+//! lines, CSV, or Parquet for batch research.
+
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde::Serialize;
+
+use crate::aggregator_client::MergedBook;
+use crate::types::{Quote, Venue};
+
+/// The rows a Parquet row group is written with.
+const ROW_GROUP_ROWS: usize = 64 * 1024;
+
+/// The Parquet schema of a [`LevelRow`].
+const PARQUET_SCHEMA: &str = "
+message level {
+    required int64 timestamp (TIMESTAMP(MICROS, true));
+    required binary side (STRING);
+    required int32 rank;
+    required binary exchange (STRING);
+    required double price;
+    required double amount;
+    required double spread;
+}
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Csv,
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    /// Accepts `jsonl`, `csv` and `parquet`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "json" => Ok(Format::JsonLines),
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(format!("unknown output format: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A level of a merged book, with the summary it belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LevelRow {
+    /// When the summary was received, in microseconds since the Unix epoch.
+    pub timestamp: i64,
+    pub side: Side,
+    /// The level's position on its side, `0` being the best.
+    pub rank: u32,
+    /// The venue's name.
+    pub exchange: String,
+    pub price: f64,
+    pub amount: f64,
+    /// The summary's spread.
+    pub spread: f64,
+}
+
+/// The rows of `book`, received at `received`: bids then asks, best first.
+pub fn rows(received: SystemTime, book: &MergedBook) -> impl Iterator<Item = LevelRow> + '_ {
+    let timestamp = received
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_micros() as i64);
+    side_rows(timestamp, Side::Bid, &book.bids, book.spread).chain(side_rows(
+        timestamp,
+        Side::Ask,
+        &book.asks,
+        book.spread,
+    ))
+}
+
+fn side_rows(
+    timestamp: i64,
+    side: Side,
+    levels: &[(Venue, Quote)],
+    spread: f64,
+) -> impl Iterator<Item = LevelRow> + '_ {
+    levels
+        .iter()
+        .enumerate()
+        .map(move |(rank, (venue, quote))| LevelRow {
+            timestamp,
+            side,
+            rank: rank as u32,
+            exchange: venue.name.clone(),
+            price: quote.price,
+            amount: quote.amount,
+            spread,
+        })
+}
+
+/// Writes merged books in a [`Format`]. Call [`finish`](Self::finish) once
+/// done, which Parquet needs to write its footer.
+pub struct SummaryWriter {
+    sink: Sink,
+}
+
+enum Sink {
+    JsonLines(BufWriter<Box<dyn Write + Send>>),
+    Csv(csv::Writer<Box<dyn Write + Send>>),
+    Parquet(ParquetSink),
+}
+
+impl SummaryWriter {
+    pub fn new(format: Format, out: Box<dyn Write + Send>) -> io::Result<Self> {
+        let sink = match format {
+            Format::JsonLines => Sink::JsonLines(BufWriter::new(out)),
+            Format::Csv => Sink::Csv(csv::Writer::from_writer(out)),
+            Format::Parquet => Sink::Parquet(ParquetSink::new(out)?),
+        };
+        Ok(Self { sink })
+    }
+
+    /// Writes the rows of `book`. JSON lines and CSV are flushed after every
+    /// book, Parquet once a row group is full.
+    pub fn write(&mut self, received: SystemTime, book: &MergedBook) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::JsonLines(out) => {
+                for row in rows(received, book) {
+                    serde_json::to_writer(&mut *out, &row)?;
+                    out.write_all(b"\n")?;
+                }
+                out.flush()
+            }
+            Sink::Csv(out) => {
+                for row in rows(received, book) {
+                    out.serialize(row).map_err(io::Error::other)?;
+                }
+                out.flush()
+            }
+            Sink::Parquet(out) => {
+                out.extend(rows(received, book));
+                if out.len() >= ROW_GROUP_ROWS {
+                    out.write_row_group()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes what is buffered and, for Parquet, the file's footer.
+    pub fn finish(self) -> io::Result<()> {
+        match self.sink {
+            Sink::JsonLines(mut out) => out.flush(),
+            Sink::Csv(mut out) => out.flush(),
+            Sink::Parquet(out) => out.finish(),
+        }
+    }
+}
+
+/// Buffers rows by column until a row group is written.
+struct ParquetSink {
+    writer: SerializedFileWriter<Box<dyn Write + Send>>,
+    timestamp: Vec<i64>,
+    side: Vec<ByteArray>,
+    rank: Vec<i32>,
+    exchange: Vec<ByteArray>,
+    price: Vec<f64>,
+    amount: Vec<f64>,
+    spread: Vec<f64>,
+}
+
+impl ParquetSink {
+    fn new(out: Box<dyn Write + Send>) -> io::Result<Self> {
+        let schema = Arc::new(parse_message_type(PARQUET_SCHEMA).map_err(io::Error::other)?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let writer =
+            SerializedFileWriter::new(out, schema, properties).map_err(io::Error::other)?;
+        Ok(Self {
+            writer,
+            timestamp: Vec::new(),
+            side: Vec::new(),
+            rank: Vec::new(),
+            exchange: Vec::new(),
+            price: Vec::new(),
+            amount: Vec::new(),
+            spread: Vec::new(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.timestamp.len()
+    }
+
+    fn extend(&mut self, rows: impl Iterator<Item = LevelRow>) {
+        for row in rows {
+            self.timestamp.push(row.timestamp);
+            self.side.push(row.side.as_str().into());
+            self.rank.push(row.rank as i32);
+            self.exchange.push(row.exchange.as_str().into());
+            self.price.push(row.price);
+            self.amount.push(row.amount);
+            self.spread.push(row.spread);
+        }
+    }
+
+    fn write_row_group(&mut self) -> io::Result<()> {
+        if self.len() == 0 {
+            return Ok(());
+        }
+        self.write_columns().map_err(io::Error::other)?;
+        self.timestamp.clear();
+        self.side.clear();
+        self.rank.clear();
+        self.exchange.clear();
+        self.price.clear();
+        self.amount.clear();
+        self.spread.clear();
+        Ok(())
+    }
+
+    fn write_columns(&mut self) -> parquet::errors::Result<()> {
+        let mut group = self.writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column()? {
+            match index {
+                0 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.timestamp, None, None)?,
+                1 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&self.side, None, None)?,
+                2 => column
+                    .typed::<Int32Type>()
+                    .write_batch(&self.rank, None, None)?,
+                3 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&self.exchange, None, None)?,
+                4 => column
+                    .typed::<DoubleType>()
+                    .write_batch(&self.price, None, None)?,
+                5 => column
+                    .typed::<DoubleType>()
+                    .write_batch(&self.amount, None, None)?,
+                _ => column
+                    .typed::<DoubleType>()
+                    .write_batch(&self.spread, None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        group.close()?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.write_row_group()?;
+        self.writer.close().map_err(io::Error::other)?;
+        Ok(())
+    }
+}
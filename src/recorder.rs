@@ -0,0 +1,194 @@
+//! Capture of raw exchange websocket frames to rotating, gzip-compressed
+//! JSON-lines files.
+//!
+//! Every line is a [`Record`]: the receive timestamp, the exchange the frame
+//! came from and the frame text exactly as it arrived on the socket.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::types::Exchange;
+
+pub const FILE_PREFIX: &str = "frames-";
+pub const FILE_SUFFIX: &str = ".jsonl.gz";
+
+/// One captured websocket frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Receive time in microseconds since the Unix epoch.
+    pub received_at: u64,
+    pub exchange: Exchange,
+    pub frame: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    pub dir: PathBuf,
+    /// Start a new file once this many uncompressed bytes have been written.
+    pub max_file_bytes: u64,
+    /// Start a new file once the current one has been open this long.
+    pub max_file_age: Duration,
+}
+
+impl RecorderConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_file_bytes: 64 * 1024 * 1024,
+            max_file_age: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Handle used by the exchange clients to hand frames to the writer task.
+///
+/// Cloning is cheap; the writer finishes the current file and exits once every
+/// clone has been dropped.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    tx: mpsc::UnboundedSender<Record>,
+}
+
+impl Recorder {
+    /// Creates `config.dir` if needed and spawns the writer task.
+    pub fn start(config: RecorderConfig) -> io::Result<(Self, JoinHandle<io::Result<()>>)> {
+        fs::create_dir_all(&config.dir)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn_blocking(move || write_records(config, rx));
+        Ok((Self { tx }, handle))
+    }
+
+    /// Stamps `frame` with the current time and queues it for writing.
+    pub fn record(&self, exchange: Exchange, frame: &str) {
+        let record = Record {
+            received_at: now_micros(),
+            exchange,
+            frame: frame.to_string(),
+        };
+        if self.tx.send(record).is_err() {
+            tracing::warn!("recorder stopped, dropping {exchange} frame");
+        }
+    }
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// Lists the recording files in `dir`, oldest first.
+pub fn recording_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|path| file_order(path));
+    Ok(files)
+}
+
+/// The millisecond timestamp embedded in a recording file's name, and the
+/// number [`RecordingFile::create`] appends to files opened within the same
+/// millisecond.
+fn file_order(path: &Path) -> (u64, u64) {
+    let stem = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX))
+        .unwrap_or_default();
+    let (millis, n) = stem.split_once('-').unwrap_or((stem, "0"));
+    (
+        millis.parse().unwrap_or_default(),
+        n.parse().unwrap_or_default(),
+    )
+}
+
+struct RecordingFile {
+    encoder: GzEncoder<BufWriter<File>>,
+    opened_at: Instant,
+    written: u64,
+}
+
+impl RecordingFile {
+    fn create(dir: &Path) -> io::Result<Self> {
+        let millis = now_micros() / 1000;
+        let mut path = dir.join(format!("{FILE_PREFIX}{millis}{FILE_SUFFIX}"));
+        let mut n = 0;
+        while path.exists() {
+            n += 1;
+            path = dir.join(format!("{FILE_PREFIX}{millis}-{n}{FILE_SUFFIX}"));
+        }
+        let file = File::create(&path)?;
+        tracing::info!("recording frames to {}", path.display());
+        Ok(Self {
+            encoder: GzEncoder::new(BufWriter::new(file), Compression::default()),
+            opened_at: Instant::now(),
+            written: 0,
+        })
+    }
+
+    fn is_full(&self, config: &RecorderConfig) -> bool {
+        self.written >= config.max_file_bytes || self.opened_at.elapsed() >= config.max_file_age
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.encoder.finish()?.flush()
+    }
+}
+
+fn write_records(
+    config: RecorderConfig,
+    mut rx: mpsc::UnboundedReceiver<Record>,
+) -> io::Result<()> {
+    let mut current: Option<RecordingFile> = None;
+
+    while let Some(record) = rx.blocking_recv() {
+        write_record(&config, &mut current, &record)?;
+        while let Ok(record) = rx.try_recv() {
+            write_record(&config, &mut current, &record)?;
+        }
+        // Sync-flush whenever the queue is drained so that a file cut short by a
+        // crash still decompresses up to the last idle point.
+        if let Some(file) = current.as_mut() {
+            file.encoder.flush()?;
+        }
+    }
+
+    match current {
+        Some(file) => file.finish(),
+        None => Ok(()),
+    }
+}
+
+fn write_record(
+    config: &RecorderConfig,
+    current: &mut Option<RecordingFile>,
+    record: &Record,
+) -> io::Result<()> {
+    if current.as_ref().is_some_and(|file| file.is_full(config)) {
+        current.take().unwrap().finish()?;
+    }
+    let file = match current {
+        Some(file) => file,
+        None => current.insert(RecordingFile::create(&config.dir)?),
+    };
+
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.encoder.write_all(&line)?;
+    file.written += line.len() as u64;
+    Ok(())
+}
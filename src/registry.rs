@@ -0,0 +1,80 @@
+//! Venues by name: each registers a factory that runs its feed for a symbol,
+//! so that the server can start whichever venues its configuration lists.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::recorder::Recorder;
+use crate::streaming::{self, Watchdog};
+use crate::types::{Exchange, OrderBook};
+
+/// What a feed is started with.
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    /// The venue the feed's books must be tagged with.
+    pub exchange: Exchange,
+    pub symbol: String,
+    pub tx: broadcast::Sender<OrderBook>,
+    pub best_of: usize,
+    pub recorder: Option<Recorder>,
+    pub watchdog: Watchdog,
+}
+
+/// A running feed, which forwards books to [`FeedConfig::tx`] until it gives up.
+pub type Feed = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Starts the feed of a venue.
+pub type FeedFactory = Arc<dyn Fn(FeedConfig) -> Feed + Send + Sync>;
+
+/// The venues a server can run, by name.
+#[derive(Clone, Default)]
+pub struct VenueRegistry {
+    factories: BTreeMap<Exchange, FeedFactory>,
+}
+
+impl VenueRegistry {
+    /// A registry of the venues this crate implements, see
+    /// [`streaming::register_venues`].
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        streaming::register_venues(&mut registry);
+        registry
+    }
+
+    /// Registers `factory` under `name`, replacing any earlier one, and
+    /// returns the venue's [`Exchange`].
+    pub fn register<F>(&mut self, name: &str, factory: F) -> Exchange
+    where
+        F: Fn(FeedConfig) -> Feed + Send + Sync + 'static,
+    {
+        let exchange = Exchange::intern(name);
+        self.factories.insert(exchange, Arc::new(factory));
+        exchange
+    }
+
+    /// The registered venue named `name`.
+    pub fn lookup(&self, name: &str) -> Result<Exchange, String> {
+        Exchange::lookup(name)
+            .filter(|exchange| self.factories.contains_key(exchange))
+            .ok_or_else(|| {
+                let names = self.names().collect::<Vec<_>>().join(", ");
+                format!("unknown venue: {name} (known: {names})")
+            })
+    }
+
+    /// The names of every registered venue, in id order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factories.keys().map(Exchange::name)
+    }
+
+    /// Starts the feed of `config.exchange`, or returns `None` if no such
+    /// venue is registered.
+    pub fn feed(&self, config: FeedConfig) -> Option<Feed> {
+        let factory = self.factories.get(&config.exchange)?;
+        Some(factory(config))
+    }
+}
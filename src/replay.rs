@@ -0,0 +1,254 @@
+//! Replays frames captured by [`crate::recorder`] through the same parsing path
+//! as the live exchange clients.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use flate2::read::MultiGzDecoder;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+
+use crate::exchange::binance_client::Market;
+use crate::exchange::error::Error;
+use crate::exchange::{
+    binance_client, bitstamp_client, bybit_client, coinbase_client, kraken_client, okx_client,
+};
+use crate::recorder::{recording_files, Record};
+use crate::types::{Exchange, OrderBook};
+
+/// Records are read from disk and handed to the pacing loop in batches of this size.
+const READ_BATCH: usize = 1024;
+
+/// In [`Pacing::AsFastAsPossible`] mode, books are only sent while the slowest
+/// consumer is fewer than this many books behind, so that nothing is dropped.
+const MAX_BACKLOG: usize = 16;
+const BACKLOG_POLL: Duration = Duration::from_millis(1);
+
+/// How quickly recorded frames are played back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    /// Keep the gaps between frames as they were recorded.
+    RealTime,
+    /// Divide the recorded gaps by the given factor.
+    Accelerated(f64),
+    /// Do not wait between frames, only for the consumers to keep up.
+    AsFastAsPossible,
+}
+
+impl Pacing {
+    fn speed(&self) -> Option<f64> {
+        match self {
+            Pacing::RealTime => Some(1.0),
+            Pacing::Accelerated(speed) => Some(*speed),
+            Pacing::AsFastAsPossible => None,
+        }
+    }
+}
+
+impl FromStr for Pacing {
+    type Err = String;
+
+    /// Accepts `realtime`, `max` or a speed-up factor such as `10` or `10x`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "realtime" => Ok(Pacing::RealTime),
+            "max" => Ok(Pacing::AsFastAsPossible),
+            _ => match s.trim_end_matches('x').parse::<f64>() {
+                Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(Pacing::Accelerated(speed)),
+                _ => Err(format!("invalid replay pacing: {s}")),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// A single recording file, or a directory whose recording files are
+    /// replayed oldest first.
+    pub path: PathBuf,
+    pub pacing: Pacing,
+}
+
+impl ReplayConfig {
+    pub fn new(path: impl Into<PathBuf>, pacing: Pacing) -> Self {
+        Self {
+            path: path.into(),
+            pacing,
+        }
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        if self.path.is_dir() {
+            recording_files(&self.path)
+        } else {
+            Ok(vec![self.path.clone()])
+        }
+    }
+}
+
+/// Turns recorded frames into order books using the parser of the exchange
+/// they were captured from.
+#[derive(Debug)]
+pub struct RecordParser {
+    best_of: usize,
+    coinbase: coinbase_client::Books,
+    kraken: kraken_client::Books,
+    okx: okx_client::Books,
+    bybit: bybit_client::Books,
+}
+
+impl RecordParser {
+    pub fn new(best_of: usize) -> Self {
+        Self {
+            best_of,
+            coinbase: coinbase_client::Books::default(),
+            kraken: kraken_client::Books::default(),
+            okx: okx_client::Books::default(),
+            bybit: bybit_client::Books::default(),
+        }
+    }
+
+    /// Whether records of `exchange` can be parsed: it is one of this crate's
+    /// venues rather than one registered elsewhere, e.g. a JSON adapter venue.
+    pub fn supports(exchange: Exchange) -> bool {
+        matches!(
+            exchange,
+            Exchange::Binance
+                | Exchange::BinanceUsdm
+                | Exchange::BinanceCoinm
+                | Exchange::BinanceUs
+                | Exchange::Bitstamp
+                | Exchange::Coinbase
+                | Exchange::Kraken
+                | Exchange::Okx
+                | Exchange::Bybit
+        )
+    }
+
+    /// Parses the next record, keeping the books of venues that only send
+    /// updates after the first snapshot. Records of venues without
+    /// [`Self::supports`] are skipped.
+    pub fn parse(&mut self, record: &Record) -> Option<OrderBook> {
+        match record.exchange {
+            Exchange::Binance => binance_client::parse_order_book(&record.frame, self.best_of),
+            Exchange::BinanceUsdm => Market::UsdM.parse_order_book(&record.frame, self.best_of),
+            Exchange::BinanceCoinm => Market::CoinM.parse_order_book(&record.frame, self.best_of),
+            Exchange::BinanceUs => Market::Us.parse_order_book(&record.frame, self.best_of),
+            Exchange::Bitstamp => bitstamp_client::parse_order_book(&record.frame, self.best_of),
+            Exchange::Coinbase => self.coinbase.apply(&record.frame, self.best_of),
+            // The live clients resubscribe on a checksum mismatch or sequence
+            // gap; a recording carries on with the next snapshot it contains.
+            Exchange::Kraken => skip_err(self.kraken.apply(&record.frame, self.best_of)),
+            Exchange::Okx => skip_err(self.okx.apply(&record.frame, self.best_of)),
+            Exchange::Bybit => skip_err(self.bybit.apply(&record.frame, self.best_of)),
+            _ => None,
+        }
+    }
+}
+
+fn skip_err(parsed: Result<Option<OrderBook>, Error>) -> Option<OrderBook> {
+    parsed.unwrap_or_else(|e| {
+        tracing::warn!("{e}");
+        None
+    })
+}
+
+/// Iterates over the records of one recording file.
+///
+/// A file whose gzip stream was cut short, e.g. because the recorder was
+/// killed, yields the records that were flushed before the cut.
+pub struct RecordReader {
+    path: PathBuf,
+    lines: io::Lines<BufReader<MultiGzDecoder<File>>>,
+}
+
+impl RecordReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines: BufReader::new(MultiGzDecoder::new(File::open(path)?)).lines(),
+        })
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                tracing::warn!("{} is truncated", self.path.display());
+                return None;
+            }
+            Err(e) => return Some(Err(e)),
+        };
+        match serde_json::from_str::<Record>(&line) {
+            Ok(record) => Some(Ok(record)),
+            // A partially flushed last line.
+            Err(e) if e.is_eof() => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// Plays back the recording at `config.path` into `tx` as order books.
+///
+/// Returns once every file has been replayed.
+pub async fn replay(
+    config: ReplayConfig,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+) -> io::Result<()> {
+    let files = config.files()?;
+    let (records_tx, mut records_rx) = mpsc::channel::<Vec<Record>>(4);
+    let reader = tokio::task::spawn_blocking(move || -> io::Result<()> {
+        for file in files {
+            let mut batch = Vec::with_capacity(READ_BATCH);
+            for record in RecordReader::open(&file)? {
+                batch.push(record?);
+                if batch.len() == READ_BATCH {
+                    let full = std::mem::replace(&mut batch, Vec::with_capacity(READ_BATCH));
+                    if records_tx.blocking_send(full).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            if !batch.is_empty() && records_tx.blocking_send(batch).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    });
+
+    let start = Instant::now();
+    let mut first_received_at = None;
+    let mut parser = RecordParser::new(best_of);
+    while let Some(records) = records_rx.recv().await {
+        for record in records {
+            let first = *first_received_at.get_or_insert(record.received_at);
+            match config.pacing.speed() {
+                Some(speed) => {
+                    let offset = Duration::from_micros(record.received_at.saturating_sub(first));
+                    tokio::time::sleep_until(start + offset.div_f64(speed)).await;
+                }
+                None => {
+                    while tx.len() >= MAX_BACKLOG {
+                        tokio::time::sleep(BACKLOG_POLL).await;
+                    }
+                }
+            }
+
+            if let Some(ob) = parser.parse(&record) {
+                if tx.send(ob).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    reader.await.map_err(io::Error::other)?
+}
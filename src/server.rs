@@ -1,56 +1,186 @@
-mod exchange;
-mod grpc;
-mod streaming;
-mod types;
-
-use grpc::{manager, start_grpc_server};
+use algo_challenge::auth::{Auth, AuthConfig};
+use algo_challenge::config::Config;
+use algo_challenge::gateway::start_gateway_with;
+use algo_challenge::grpc::{manager, start_grpc_server_with, GrpcConfig, ServerTls};
+use algo_challenge::recorder::{Recorder, RecorderConfig};
+use algo_challenge::registry::{FeedConfig, VenueRegistry};
+use algo_challenge::replay::{self, Pacing, RecordParser, ReplayConfig};
+use algo_challenge::streaming::Watchdog;
+use algo_challenge::types::{OrderBook, Summary};
 use std::env;
+use std::path::Path;
+use std::time::Duration;
+use tokio::signal;
 use tokio::sync::broadcast;
 
-use types::{OrderBook, Summary};
-
 const BEST_OF: usize = 10;
 const SERVER: &str = "[::1]:50051";
 
 // cargo run --release --bin server btcusdt btcusdt
-// cargo run --release --bin server ethbtc ethbtc
+// cargo run --release --bin server bitstamp=btcusd binance=btcusdt coinbase=BTC-USD kraken=BTC/USD okx=BTC-USDT bybit=BTCUSDT
+// cargo run --release --bin server binance=btcusdt binance-usdm=btcusdt binance-coinm=btcusd_perp
+// RECORD_DIR=./recordings cargo run --release --bin server btcusdt btcusdt
+// REPLAY_PATH=./recordings REPLAY_PACING=10x cargo run --release --bin server
+// NO_DATA_TIMEOUT=10 cargo run --release --bin server btcusdt btcusdt
+// REST_POLL_INTERVAL_MS=500 cargo run --release --bin server btcusdt btcusdt
+// VENUES_CONFIG=venues.json cargo run --release --bin server
+// GATEWAY_ADDR=127.0.0.1:8080 cargo run --release --bin server
+// GRPC_WEB_ORIGINS=http://localhost:3000 cargo run --release --bin server
+// GRPC_ADDR=0.0.0.0:50051 TLS_CERT=server.pem TLS_KEY=server.key cargo run --release --bin server
+// TLS_CERT=server.pem TLS_KEY=server.key TLS_CLIENT_CA=clients.pem cargo run --release --bin server
+// AUTH_KEYS=keys.json cargo run --release --bin server
 
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
     println!(
-        "Usage: {} <symbol_for_bitstamp> <symbol_for_binance>",
+        "Usage: {} [<symbol_for_bitstamp> <symbol_for_binance> | <venue>=<symbol>...]",
         args[0]
     );
-    let (bitstamp_symbol, binance_symbol) = if args.len() < 3 {
-        println!("Using: default symbols: ethbtc ethbtc");
-        // (String::from("ethbtc"), String::from("ethbtc"))
-        (String::from("btcusdt"), String::from("btcusdt"))
+    let config = if let Ok(path) = env::var("VENUES_CONFIG") {
+        Config::load(Path::new(&path)).expect("invalid VENUES_CONFIG")
+    } else if args.len() < 2 {
+        Config::default()
     } else {
-        println!("Using: defined symbols: {:?} {:?}", args[1], args[2]);
-        (String::from(&args[1]), String::from(&args[2]))
+        Config::from_args(&args[1..]).expect("invalid arguments")
     };
+    let mut registry = VenueRegistry::builtin();
+    config.register_adapters(&mut registry);
+    let venues = config
+        .venues
+        .iter()
+        .map(|venue| Ok((registry.lookup(&venue.venue)?, venue.symbol.clone())))
+        .collect::<Result<Vec<_>, String>>()
+        .expect("invalid venues");
+    println!("Using: {venues:?}");
+    let mut exchanges = venues
+        .iter()
+        .map(|(exchange, _)| *exchange)
+        .collect::<Vec<_>>();
+    let instruments = venues.clone();
 
-    let (tx1, rx) = broadcast::channel::<OrderBook>(32);
-    let tx2 = tx1.clone();
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
 
-    let bitstamp_handle =
-        tokio::spawn(async move { streaming::bitstamp(&bitstamp_symbol, tx1, BEST_OF).await });
+    let mut writer = None;
+    let feeds = if let Ok(path) = env::var("REPLAY_PATH") {
+        let pacing = env::var("REPLAY_PACING")
+            .map(|pacing| pacing.parse::<Pacing>().expect("invalid REPLAY_PACING"))
+            .unwrap_or(Pacing::RealTime);
+        println!("Replaying recorded frames from: {path} ({pacing:?})");
+        exchanges.retain(|exchange| {
+            let supported = RecordParser::supports(*exchange);
+            if !supported {
+                eprintln!("Cannot replay {exchange}, leaving it out");
+            }
+            supported
+        });
+        let config = ReplayConfig::new(path, pacing);
+        vec![tokio::spawn(async move {
+            if let Err(e) = replay::replay(config, tx, BEST_OF).await {
+                eprintln!("Replay failed: {e}");
+            }
+        })]
+    } else {
+        let recorder = match env::var("RECORD_DIR") {
+            Ok(dir) => {
+                println!("Recording raw frames to: {dir}");
+                let (recorder, handle) =
+                    Recorder::start(RecorderConfig::new(dir)).expect("cannot start recorder");
+                writer = Some(handle);
+                Some(recorder)
+            }
+            Err(_) => None,
+        };
+        let mut watchdog = Watchdog::default();
+        if let Ok(secs) = env::var("NO_DATA_TIMEOUT") {
+            let secs = secs.parse::<u64>().expect("invalid NO_DATA_TIMEOUT");
+            watchdog.no_data_timeout = Duration::from_secs(secs);
+        }
+        if let Ok(millis) = env::var("REST_POLL_INTERVAL_MS") {
+            let millis = millis
+                .parse::<u64>()
+                .expect("invalid REST_POLL_INTERVAL_MS");
+            watchdog.rest_poll_interval =
+                Some(Duration::from_millis(millis)).filter(|i| !i.is_zero());
+        }
 
-    let binance_handle =
-        tokio::spawn(
-            async move { streaming::binance(&binance_symbol, None, None, tx2, BEST_OF).await },
-        );
+        venues
+            .into_iter()
+            .filter_map(|(exchange, symbol)| {
+                registry.feed(FeedConfig {
+                    exchange,
+                    symbol,
+                    tx: tx.clone(),
+                    best_of: BEST_OF,
+                    recorder: recorder.clone(),
+                    watchdog,
+                })
+            })
+            .map(tokio::spawn)
+            .collect::<Vec<_>>()
+    };
 
     let (s_tx, mut _s_rx) = broadcast::channel::<Summary>(32);
     let s_tx_clone = s_tx.clone();
 
-    let server = tokio::spawn(async move { start_grpc_server(SERVER, s_tx_clone).await });
+    let mut grpc_config = GrpcConfig::default();
+    if let Ok(origins) = env::var("GRPC_WEB_ORIGINS") {
+        grpc_config.grpc_web = true;
+        grpc_config.cors_origins = origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect();
+        println!("Accepting gRPC-Web from: {:?}", grpc_config.cors_origins);
+    }
+    if let (Ok(cert), Ok(key)) = (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+        let client_ca = env::var("TLS_CLIENT_CA").ok();
+        let tls = ServerTls::load(
+            Path::new(&cert),
+            Path::new(&key),
+            client_ca.as_deref().map(Path::new),
+        )
+        .expect("cannot read TLS_CERT, TLS_KEY or TLS_CLIENT_CA");
+        match &client_ca {
+            Some(ca) => println!("Serving gRPC over TLS, requiring client certificates of: {ca}"),
+            None => println!("Serving gRPC over TLS"),
+        }
+        grpc_config.tls = Some(tls);
+    }
+    if let Ok(path) = env::var("AUTH_KEYS") {
+        let config = AuthConfig::load(Path::new(&path)).expect("invalid AUTH_KEYS");
+        let auth = Auth::new(&config, &instruments).expect("invalid AUTH_KEYS");
+        println!("Requiring one of {} API keys", config.keys.len());
+        grpc_config.auth = Some(auth);
+    }
+    let addr = env::var("GRPC_ADDR").unwrap_or_else(|_| SERVER.to_string());
+    let gateway_auth = grpc_config.auth.clone();
+    let server =
+        tokio::spawn(async move { start_grpc_server_with(&addr, s_tx_clone, grpc_config).await });
+
+    if let Ok(addr) = env::var("GATEWAY_ADDR") {
+        let addr = addr.parse().expect("invalid GATEWAY_ADDR");
+        println!("Serving summaries over WebSocket and SSE on: {addr}");
+        tokio::spawn(start_gateway_with(addr, s_tx.clone(), gateway_auth));
+    }
 
-    let manager = tokio::spawn(async move { manager(rx, s_tx, BEST_OF).await });
+    tokio::spawn(async move { manager(exchanges, rx, s_tx, BEST_OF).await });
 
-    manager.await.unwrap();
-    bitstamp_handle.await.unwrap();
-    binance_handle.await.unwrap();
-    let _ = server.await.unwrap();
+    tokio::select! {
+        _ = signal::ctrl_c() => println!("Shutting down"),
+        _ = server => {}
+    }
+    // Stopping the feeds drops their recorders, so that the writer finishes
+    // the last file.
+    for feed in &feeds {
+        feed.abort();
+    }
+    if let Some(writer) = writer {
+        match writer.await {
+            Ok(Ok(())) => println!("Finished recording"),
+            Ok(Err(e)) => eprintln!("Recording failed: {e}"),
+            Err(e) => eprintln!("Recorder panicked: {e}"),
+        }
+    }
 }
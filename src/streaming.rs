@@ -1,41 +1,485 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
 use futures::StreamExt;
 use tokio::sync::broadcast;
 
-use crate::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use crate::exchange::binance_client::{BinanceClient, Market, PriceLevels, Speed};
 use crate::exchange::bitstamp_client::BitstampClient;
-use crate::types::OrderBook;
+use crate::exchange::bybit_client::{self, BybitClient};
+use crate::exchange::coinbase_client::CoinbaseClient;
+use crate::exchange::error::Error;
+use crate::exchange::json_adapter::{AdapterConfig, JsonAdapterClient};
+use crate::exchange::kraken_client::{BookDepth, KrakenClient};
+use crate::exchange::okx_client::{BookChannel, OkxClient};
+use crate::exchange::rest_client::{RestClient, RestVenue};
+use crate::recorder::Recorder;
+use crate::registry::VenueRegistry;
+use crate::types::{Exchange, OrderBook, OrderBookStream};
+
+/// When a feed gives up on its connection, and how soon it reconnects.
+#[derive(Debug, Clone, Copy)]
+pub struct Watchdog {
+    /// Reconnect once no order book arrived for this long.
+    pub no_data_timeout: Duration,
+    /// The wait before reconnecting, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How often a feed with a REST [`Fallback`] polls it while reconnecting,
+    /// or `None` to only wait.
+    pub rest_poll_interval: Option<Duration>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            no_data_timeout: Duration::from_secs(30),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            rest_poll_interval: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Why a feed dropped its connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disconnect {
+    NoData(Duration),
+    Closed,
+    /// The exchange asked for the connection to be replaced.
+    Handover,
+}
+
+impl fmt::Display for Disconnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Disconnect::NoData(timeout) => write!(f, "no order book for {timeout:?}"),
+            Disconnect::Closed => write!(f, "connection closed"),
+            Disconnect::Handover => write!(f, "handover requested"),
+        }
+    }
+}
+
+/// A subscribed connection, as opened by a feed's `connect`.
+pub struct Session<C> {
+    /// Kept alive as long as `book_events` is read.
+    pub client: C,
+    pub book_events: OrderBookStream,
+    /// Resolves once the exchange wants the connection replaced.
+    pub handover: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl<C> Session<C> {
+    /// Forwards books to `tx` until the connection should be dropped.
+    async fn forward(
+        &mut self,
+        watchdog: &Watchdog,
+        tx: &broadcast::Sender<OrderBook>,
+    ) -> Disconnect {
+        loop {
+            tokio::select! {
+                book = tokio::time::timeout(watchdog.no_data_timeout, self.book_events.next()) => {
+                    match book {
+                        Ok(Some(ob)) => {
+                            tx.send(ob).unwrap();
+                        }
+                        Ok(None) => return Disconnect::Closed,
+                        Err(_) => return Disconnect::NoData(watchdog.no_data_timeout),
+                    }
+                }
+                _ = &mut self.handover => return Disconnect::Handover,
+            }
+        }
+    }
+}
+
+/// The REST depth endpoint a feed polls while its websocket is unavailable.
+#[derive(Debug, Clone)]
+pub struct Fallback {
+    pub client: RestClient,
+    pub symbol: String,
+    pub best_of: usize,
+    pub interval: Duration,
+}
+
+impl Fallback {
+    /// The fallback of `symbol` on `venue`'s public endpoint, if `watchdog`
+    /// polls at all.
+    pub fn public(
+        venue: RestVenue,
+        symbol: &str,
+        best_of: usize,
+        watchdog: &Watchdog,
+    ) -> Option<Self> {
+        Some(Self {
+            client: RestClient::public(venue),
+            symbol: symbol.to_string(),
+            best_of,
+            interval: watchdog.rest_poll_interval?,
+        })
+    }
+
+    /// Forwards a degraded book to `tx` every `interval`, starting right away.
+    /// Failed requests are skipped.
+    async fn poll(&self, venue: &str, tx: &broadcast::Sender<OrderBook>) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match self.client.order_book(&self.symbol, self.best_of).await {
+                Ok(ob) => {
+                    tx.send(ob).unwrap();
+                }
+                Err(e) => eprintln!("{venue}: REST fallback: {e}"),
+            }
+        }
+    }
+}
 
-pub async fn bitstamp(symbol: &str, tx: broadcast::Sender<OrderBook>, best_of: usize) {
-    let mut bitstamp_client = BitstampClient::connect_public()
-        .await
-        .expect("cannot connect");
-    bitstamp_client.subscribe_orderbook(symbol, best_of).await;
-    let mut book_events = bitstamp_client.book_events.unwrap();
-    while let Some(ob) = book_events.next().await {
-        tx.send(ob).unwrap();
+/// Forwards the order books of the sessions opened by `connect` to `tx`,
+/// reconnecting whenever the watchdog fires or the connection closes.
+///
+/// A requested handover opens the next session while the current one is still
+/// forwarding, and only switches over with the next session's first book, so
+/// that the merged book has no gap. Gives up on errors that a new connection
+/// would not fix, such as an unknown symbol.
+pub async fn supervise<C, F, Fut>(
+    venue: &str,
+    watchdog: Watchdog,
+    tx: broadcast::Sender<OrderBook>,
+    connect: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Session<C>, Error>>,
+{
+    supervise_with_fallback(venue, watchdog, None, tx, connect).await
+}
+
+/// Supervises like [`supervise`], polling `fallback` instead of waiting
+/// between reconnects and while reconnecting, so that the venue keeps sending
+/// degraded books while its websocket is unavailable.
+///
+/// A connect taking longer than the watchdog's `no_data_timeout` fails.
+pub async fn supervise_with_fallback<C, F, Fut>(
+    venue: &str,
+    watchdog: Watchdog,
+    fallback: Option<Fallback>,
+    tx: broadcast::Sender<OrderBook>,
+    mut connect: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Session<C>, Error>>,
+{
+    let mut backoff = watchdog.initial_backoff;
+    let mut reconnecting = false;
+    loop {
+        let connecting = async {
+            let timeout = watchdog.no_data_timeout;
+            tokio::time::timeout(timeout, connect())
+                .await
+                .unwrap_or(Err(Error::Timeout(timeout.as_millis() as u64)))
+        };
+        let connected = match &fallback {
+            Some(fallback) if reconnecting => tokio::select! {
+                connected = connecting => connected,
+                _ = fallback.poll(venue, &tx) => unreachable!("polling never ends"),
+            },
+            _ => connecting.await,
+        };
+        reconnecting = true;
+        match connected {
+            Ok(mut session) => {
+                backoff = watchdog.initial_backoff;
+                loop {
+                    let reason = session.forward(&watchdog, &tx).await;
+                    if reason != Disconnect::Handover {
+                        eprintln!("{venue}: {reason}, reconnecting in {backoff:?}");
+                        break;
+                    }
+                    eprintln!("{venue}: {reason}, opening a new connection");
+                    match handover(session, &mut connect, &watchdog, &tx).await {
+                        Ok(next) => session = next,
+                        Err(e) => {
+                            eprintln!("{venue}: handover failed: {e}, reconnecting in {backoff:?}");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e @ (Error::UnknownSymbol(_) | Error::SubscriptionRejected(_))) => {
+                eprintln!("Cannot subscribe to {venue}: {e}");
+                return;
+            }
+            Err(e) => eprintln!("{venue}: {e}, reconnecting in {backoff:?}"),
+        }
+        match &fallback {
+            Some(fallback) => {
+                let _ = tokio::time::timeout(backoff, fallback.poll(venue, &tx)).await;
+            }
+            None => tokio::time::sleep(backoff).await,
+        }
+        backoff = (backoff * 2).min(watchdog.max_backoff);
     }
 }
 
+/// Opens the session replacing `current`, forwarding the books of `current`
+/// until the new session delivered its first one. `current` is dropped, and
+/// so closed, either way.
+async fn handover<C, F, Fut>(
+    mut current: Session<C>,
+    connect: &mut F,
+    watchdog: &Watchdog,
+    tx: &broadcast::Sender<OrderBook>,
+) -> Result<Session<C>, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Session<C>, Error>>,
+{
+    let next = async {
+        let mut next = connect().await?;
+        let first = tokio::time::timeout(watchdog.no_data_timeout, next.book_events.next())
+            .await
+            .map_err(|_| Error::Timeout(watchdog.no_data_timeout.as_millis() as u64))?
+            .ok_or(Error::ConnectionClosed)?;
+        Ok::<_, Error>((next, first))
+    };
+    tokio::pin!(next);
+
+    let mut current_open = true;
+    loop {
+        tokio::select! {
+            result = &mut next => {
+                let (next, first) = result?;
+                tx.send(first).unwrap();
+                return Ok(next);
+            }
+            book = current.book_events.next(), if current_open => match book {
+                Some(ob) => {
+                    tx.send(ob).unwrap();
+                }
+                None => current_open = false,
+            },
+        }
+    }
+}
+
+/// Registers the venues of this crate, see [`bitstamp`], [`binance`],
+/// [`coinbase`], [`kraken`], [`okx`] and [`bybit`].
+pub fn register_venues(registry: &mut VenueRegistry) {
+    registry.register("Bitstamp", |c| {
+        Box::pin(async move { bitstamp(&c.symbol, c.tx, c.best_of, c.recorder, c.watchdog).await })
+    });
+    for market in [Market::Spot, Market::UsdM, Market::CoinM, Market::Us] {
+        registry.register(market.exchange().name(), move |c| {
+            Box::pin(async move {
+                binance(
+                    market, &c.symbol, None, None, c.tx, c.best_of, c.recorder, c.watchdog,
+                )
+                .await
+            })
+        });
+    }
+    registry.register("Coinbase", |c| {
+        Box::pin(async move { coinbase(&c.symbol, c.tx, c.best_of, c.recorder, c.watchdog).await })
+    });
+    registry.register("Kraken", |c| {
+        Box::pin(
+            async move { kraken(&c.symbol, None, c.tx, c.best_of, c.recorder, c.watchdog).await },
+        )
+    });
+    registry.register("OKX", |c| {
+        Box::pin(async move { okx(&c.symbol, None, c.tx, c.best_of, c.recorder, c.watchdog).await })
+    });
+    registry.register("Bybit", |c| {
+        Box::pin(
+            async move { bybit(&c.symbol, None, c.tx, c.best_of, c.recorder, c.watchdog).await },
+        )
+    });
+}
+
+/// Registers a venue named `name` whose books are read by a
+/// [`JsonAdapterClient`] configured with `config`, see [`json_adapter`].
+pub fn register_json_adapter(
+    registry: &mut VenueRegistry,
+    name: &str,
+    config: AdapterConfig,
+) -> Exchange {
+    registry.register(name, move |c| {
+        let config = config.clone();
+        Box::pin(async move {
+            let exchange = c.exchange;
+            json_adapter(
+                exchange, config, &c.symbol, c.tx, c.best_of, c.recorder, c.watchdog,
+            )
+            .await
+        })
+    })
+}
+
+/// Runs the feed of `symbol` on Bitstamp, polling the REST order book while
+/// the websocket is unavailable, see [`Watchdog::rest_poll_interval`].
+pub async fn bitstamp(
+    symbol: &str,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("Bitstamp {symbol}");
+    let fallback = Fallback::public(RestVenue::Bitstamp, symbol, best_of, &watchdog);
+    supervise_with_fallback(&venue, watchdog, fallback, tx, || async {
+        let mut client = BitstampClient::connect_public_with_recorder(recorder.clone()).await?;
+        let subscription = client.subscribe_orderbook(symbol, best_of).await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+/// Runs the feed of `symbol` on a Binance `market`, subscribing to the
+/// 20-level depth stream at the market's default speed unless given, and
+/// polling the REST depth while the websocket is unavailable.
+#[allow(clippy::too_many_arguments)]
 pub async fn binance(
+    market: Market,
     symbol: &str,
     levels: Option<PriceLevels>,
     speed: Option<Speed>,
     tx: broadcast::Sender<OrderBook>,
     best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
 ) {
-    let mut binance_client = BinanceClient::connect_public()
-        .await
-        .expect("cannot connect");
-    binance_client
-        .subscribe_orderbook(
-            symbol,
-            levels.unwrap_or(PriceLevels::L20),
-            speed.unwrap_or(Speed::S100),
-            best_of,
-        )
-        .await;
-    let mut depth_events = binance_client.book_events.unwrap();
-    while let Some(ob) = depth_events.next().await {
-        tx.send(ob).unwrap();
-    }
+    let venue = format!("{} {symbol}", market.exchange());
+    let levels = levels.unwrap_or(PriceLevels::L20);
+    let speed = speed.unwrap_or(market.default_speed());
+    let fallback = Fallback::public(RestVenue::Binance(market), symbol, best_of, &watchdog);
+    supervise_with_fallback(&venue, watchdog, fallback, tx, || async {
+        let mut client = BinanceClient::connect_public_market(market, recorder.clone()).await?;
+        let subscription = client
+            .subscribe_orderbook(symbol, levels, speed, best_of)
+            .await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+pub async fn coinbase(
+    product_id: &str,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("Coinbase {product_id}");
+    supervise(&venue, watchdog, tx, || async {
+        let mut client = CoinbaseClient::connect_public_with_recorder(recorder.clone()).await?;
+        let subscription = client.subscribe_orderbook(product_id, best_of).await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+pub async fn kraken(
+    symbol: &str,
+    depth: Option<BookDepth>,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("Kraken {symbol}");
+    let depth = depth.unwrap_or(BookDepth::D10);
+    supervise(&venue, watchdog, tx, || async {
+        let mut client = KrakenClient::connect_public_with_recorder(recorder.clone()).await?;
+        let subscription = client.subscribe_orderbook(symbol, depth, best_of).await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+pub async fn okx(
+    inst_id: &str,
+    channel: Option<BookChannel>,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("OKX {inst_id}");
+    let channel = channel.unwrap_or(BookChannel::Books);
+    supervise(&venue, watchdog, tx, || async {
+        let mut client = OkxClient::connect_public_with_recorder(recorder.clone()).await?;
+        let subscription = client
+            .subscribe_orderbook(inst_id, channel, best_of)
+            .await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+pub async fn bybit(
+    symbol: &str,
+    depth: Option<bybit_client::BookDepth>,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("Bybit {symbol}");
+    let depth = depth.unwrap_or(bybit_client::BookDepth::D50);
+    supervise(&venue, watchdog, tx, || async {
+        let mut client = BybitClient::connect_public_with_recorder(recorder.clone()).await?;
+        let subscription = client.subscribe_orderbook(symbol, depth, best_of).await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
+}
+
+pub async fn json_adapter(
+    exchange: Exchange,
+    config: AdapterConfig,
+    symbol: &str,
+    tx: broadcast::Sender<OrderBook>,
+    best_of: usize,
+    recorder: Option<Recorder>,
+    watchdog: Watchdog,
+) {
+    let venue = format!("{exchange} {symbol}");
+    supervise(&venue, watchdog, tx, || async {
+        let mut client =
+            JsonAdapterClient::connect(exchange, config.clone(), symbol, recorder.clone()).await?;
+        let subscription = client.subscribe_orderbook(symbol, best_of).await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    })
+    .await
 }
@@ -0,0 +1,245 @@
+//! A terminal view of the merged book for `client --tui`: the consolidated
+//! ladder with each level's venue color-coded, the spread and mid, and the
+//! levels that changed since the last summary highlighted.
+//!
+//! Keys: `↑`/`+` and `↓`/`-` change the depth, `space` or `p` pauses and
+//! `q`, `Esc` or `Ctrl-C` quits.
+
+use std::io;
+
+use futures::StreamExt;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc;
+
+use crate::aggregator_client::{MergedBook, MergedBookStream};
+use crate::types::{Quote, Venue};
+
+/// The most levels per side the depth can be raised to.
+pub const MAX_DEPTH: usize = 50;
+
+/// The venue colors, picked by [`Venue::id`]; red and green are left to
+/// the sides.
+const VENUE_COLORS: [Color; 8] = [
+    Color::Yellow,
+    Color::Cyan,
+    Color::Magenta,
+    Color::LightBlue,
+    Color::LightYellow,
+    Color::LightCyan,
+    Color::LightMagenta,
+    Color::White,
+];
+
+/// The color `venue` is shown in.
+pub fn venue_color(venue: &Venue) -> Color {
+    VENUE_COLORS[venue.id as usize % VENUE_COLORS.len()]
+}
+
+/// What the view should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+/// The state of the view: the latest two summaries, the depth and whether
+/// updates are paused.
+#[derive(Debug, Clone)]
+pub struct Ladder {
+    current: Option<MergedBook>,
+    previous: Option<MergedBook>,
+    depth: usize,
+    paused: bool,
+}
+
+impl Ladder {
+    /// An empty ladder showing `depth` levels per side.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            current: None,
+            previous: None,
+            depth: depth.clamp(1, MAX_DEPTH),
+            paused: false,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Shows `book`, unless paused, in which case it is dropped.
+    pub fn update(&mut self, book: MergedBook) {
+        if !self.paused {
+            self.previous = self.current.replace(book);
+        }
+    }
+
+    /// Applies a key press. Raw mode turns `Ctrl-C` into one rather than a
+    /// signal, so it quits like `q`.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Control {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Control::Quit
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Control::Quit,
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.paused = !self.paused,
+            KeyCode::Up | KeyCode::Char('+') => self.depth = (self.depth + 1).min(MAX_DEPTH),
+            KeyCode::Down | KeyCode::Char('-') => self.depth = (self.depth - 1).max(1),
+            _ => {}
+        }
+        Control::Continue
+    }
+
+    /// Whether `level` of a side was not in that side of the previous summary,
+    /// with the same amount.
+    fn changed(&self, level: &(Venue, Quote), side: fn(&MergedBook) -> &[(Venue, Quote)]) -> bool {
+        match &self.previous {
+            Some(previous) => !side(previous).contains(level),
+            None => false,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [header, ladder, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(Paragraph::new(self.header()), header);
+        frame.render_widget(
+            Paragraph::new("↑/+ ↓/- depth   space pause   q quit")
+                .style(Style::default().fg(Color::DarkGray)),
+            footer,
+        );
+
+        let Some(book) = &self.current else {
+            frame.render_widget(Paragraph::new("Waiting for summaries…"), ladder);
+            return;
+        };
+        let row = |level: &(Venue, Quote), side_color: Color, changed: bool| {
+            let (venue, quote) = level;
+            let mut style = Style::default();
+            if changed {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Row::new([
+                Cell::from(venue.name.clone()).style(Style::default().fg(venue_color(venue))),
+                Cell::from(format!("{:.8}", quote.price)).style(Style::default().fg(side_color)),
+                Cell::from(format!("{:.8}", quote.amount)),
+            ])
+            .style(style)
+        };
+
+        let asks = book
+            .asks
+            .iter()
+            .take(self.depth)
+            .rev()
+            .map(|level| row(level, Color::Red, self.changed(level, |b| &b.asks)));
+        let spread = Row::new([
+            Cell::from(""),
+            Cell::from(format!("{:.8}", book.spread)),
+            Cell::from("spread"),
+        ])
+        .style(Style::default().add_modifier(Modifier::DIM));
+        let bids = book
+            .bids
+            .iter()
+            .take(self.depth)
+            .map(|level| row(level, Color::Green, self.changed(level, |b| &b.bids)));
+
+        let table = Table::new(
+            asks.chain([spread]).chain(bids),
+            [
+                Constraint::Length(16),
+                Constraint::Length(20),
+                Constraint::Length(20),
+            ],
+        )
+        .header(
+            Row::new(["Exchange", "Price", "Amount"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title("Merged book"));
+        frame.render_widget(table, ladder);
+    }
+
+    fn header(&self) -> Line<'static> {
+        let mut spans = Vec::new();
+        if let Some(book) = &self.current {
+            spans.push(Span::raw(format!("spread {:.8}", book.spread)));
+            if let (Some((_, bid)), Some((_, ask))) = (book.best_bid(), book.best_ask()) {
+                spans.push(Span::raw(format!(
+                    "  mid {:.8}",
+                    (bid.price + ask.price) / 2.0
+                )));
+            }
+            if !book.degraded.is_empty() {
+                spans.push(Span::styled(
+                    format!("  degraded {}", book.degraded.join(", ")),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+        }
+        spans.push(Span::raw(format!("  depth {}", self.depth)));
+        if self.paused {
+            spans.push(Span::styled(
+                "  PAUSED",
+                Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            ));
+        }
+        Line::from(spans)
+    }
+}
+
+/// Shows `books` until `q` is pressed, taking over the terminal meanwhile,
+/// or until the server refuses the client, returning its error.
+pub async fn run(books: MergedBookStream, depth: usize) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, books, depth).await;
+    ratatui::restore();
+    result
+}
+
+async fn show(
+    terminal: &mut DefaultTerminal,
+    mut books: MergedBookStream,
+    depth: usize,
+) -> io::Result<()> {
+    // Key presses are read on their own thread, as reading blocks.
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event {
+                if key.kind == KeyEventKind::Press && keys_tx.send(key).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut ladder = Ladder::new(depth);
+    loop {
+        terminal.draw(|frame| ladder.render(frame))?;
+        tokio::select! {
+            Some(book) = books.next() => ladder.update(book.map_err(io::Error::other)?),
+            Some(key) = keys.recv() => {
+                if ladder.handle_key(key) == Control::Quit {
+                    return Ok(());
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
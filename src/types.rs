@@ -1,16 +1,150 @@
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub enum Exchange {
-    Binance = 0,
-    Bitstamp = 1,
+use std::fmt;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+
+use futures::Stream;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::decimal;
+
+/// A venue, identified by a small number interned from its name.
+///
+/// The built-in venues are constants with fixed ids; others get the next free
+/// id the first time their name is [interned](Exchange::intern), e.g. when
+/// they are registered in a [`VenueRegistry`](crate::registry::VenueRegistry).
+/// Names match in any case and ignoring punctuation, so `binance-usdm` is
+/// `Binance-USDM`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Exchange(u16);
+
+// Named like the enum variants they replace.
+#[allow(non_upper_case_globals)]
+impl Exchange {
+    pub const Binance: Exchange = Exchange(0);
+    pub const Bitstamp: Exchange = Exchange(1);
+    pub const Coinbase: Exchange = Exchange(2);
+    pub const Kraken: Exchange = Exchange(3);
+    pub const Okx: Exchange = Exchange(4);
+    pub const Bybit: Exchange = Exchange(5);
+    /// Binance USDⓈ-M futures.
+    pub const BinanceUsdm: Exchange = Exchange(6);
+    /// Binance COIN-M futures.
+    pub const BinanceCoinm: Exchange = Exchange(7);
+    pub const BinanceUs: Exchange = Exchange(8);
+}
+
+/// The names of the built-in venues, indexed by id.
+const BUILTIN_NAMES: [&str; 9] = [
+    "Binance",
+    "Bitstamp",
+    "Coinbase",
+    "Kraken",
+    "OKX",
+    "Bybit",
+    "Binance-USDM",
+    "Binance-COINM",
+    "Binance-US",
+];
+
+/// Every interned name, indexed by id. Names are leaked, there are only ever a
+/// handful.
+fn names() -> &'static RwLock<Vec<&'static str>> {
+    static NAMES: OnceLock<RwLock<Vec<&'static str>>> = OnceLock::new();
+    NAMES.get_or_init(|| RwLock::new(BUILTIN_NAMES.to_vec()))
+}
+
+/// The key names are matched by: lowercase, letters and digits only.
+pub(crate) fn name_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
 }
 
-impl ToString for Exchange {
-    fn to_string(&self) -> String {
-        match self {
-            Exchange::Binance => "Binance".to_string(),
-            Exchange::Bitstamp => "Bitstamp".to_string(),
+impl Exchange {
+    /// The exchange named `name`, if it was interned.
+    pub fn lookup(name: &str) -> Option<Exchange> {
+        let key = name_key(name);
+        let names = names().read().unwrap();
+        let id = names.iter().position(|known| name_key(known) == key)?;
+        Some(Exchange(id as u16))
+    }
+
+    /// The exchange named `name`, interning the name if it is new.
+    ///
+    /// Panics once more than `u16::MAX` names were interned.
+    pub fn intern(name: &str) -> Exchange {
+        if let Some(exchange) = Self::lookup(name) {
+            return exchange;
+        }
+        let mut names = names().write().unwrap();
+        // Another thread may have interned it meanwhile.
+        let key = name_key(name);
+        if let Some(id) = names.iter().position(|known| name_key(known) == key) {
+            return Exchange(id as u16);
         }
+        let id = u16::try_from(names.len()).expect("too many exchanges");
+        names.push(Box::leak(name.to_string().into_boxed_str()));
+        Exchange(id)
+    }
+
+    /// The numeric id, stable for the built-in venues.
+    pub fn id(&self) -> u16 {
+        self.0
+    }
+
+    /// The exchange with the numeric `id`, if one was interned with it.
+    pub fn from_id(id: u32) -> Option<Exchange> {
+        let id = u16::try_from(id).ok()?;
+        (usize::from(id) < names().read().unwrap().len()).then_some(Exchange(id))
+    }
+
+    /// Every interned exchange, in id order.
+    pub fn all() -> Vec<Exchange> {
+        (0..names().read().unwrap().len() as u16)
+            .map(Exchange)
+            .collect()
+    }
+
+    /// The name the exchange was interned with.
+    pub fn name(&self) -> &'static str {
+        names().read().unwrap()[self.0 as usize]
+    }
+}
+
+impl FromStr for Exchange {
+    type Err = String;
+
+    /// Accepts the names of interned exchanges, see [`Exchange::lookup`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Exchange::lookup(s).ok_or_else(|| format!("unknown exchange: {s}"))
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Debug for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Serialized as its name, so that recordings do not depend on interning order.
+impl Serialize for Exchange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Exchange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Exchange::intern(&name))
     }
 }
 
@@ -18,27 +152,173 @@ pub mod orderbook_aggregator {
     tonic::include_proto!("orderbook"); // The string specified here must match the proto package name
 }
 
-pub use orderbook_aggregator::{Empty, Level, Summary};
+pub use orderbook_aggregator::{Empty, Level, Summary, SummaryRequest, Venue, VenueList};
+
+impl From<Exchange> for Venue {
+    fn from(exchange: Exchange) -> Self {
+        Venue {
+            id: exchange.id().into(),
+            name: exchange.to_string(),
+        }
+    }
+}
+
+impl Level {
+    /// Sets `exchange` to the name of the venue `venue` refers to, if any.
+    pub fn name_venue(&mut self) {
+        if let Some(exchange) = Exchange::from_id(self.venue) {
+            self.exchange = exchange.name().to_string();
+        }
+    }
+}
+
+/// A level of an [`OrderBook`], whose venue is the book's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quote {
+    pub price: f64,
+    pub amount: f64,
+}
+
+impl Quote {
+    /// Builds a quote from the decimal strings the exchanges send, or `None` if
+    /// either of them does not parse.
+    pub fn from_strs(price: &str, amount: &str) -> Option<Quote> {
+        Some(Quote {
+            price: decimal::parse(price)?,
+            amount: decimal::parse(amount)?,
+        })
+    }
+
+    /// Whether the quote can be traded against: a finite price and a positive amount.
+    pub fn is_valid(&self) -> bool {
+        self.price.is_finite() && self.amount.is_finite() && self.amount > 0.0
+    }
+
+    /// The summary level of this quote on `exchange`, identified by its id
+    /// only, see [`Level::name_venue`].
+    pub fn to_level(self, exchange: Exchange) -> Level {
+        Level {
+            exchange: String::new(),
+            price: self.price,
+            amount: self.amount,
+            venue: u32::from(exchange.id()),
+        }
+    }
+}
 
 impl Summary {
-    #[allow(dead_code)]
+    /// Merges two books into the best `best_of` levels per side, see [`Summary::merge_all`].
     pub fn merge(ob1: OrderBook, ob2: OrderBook, best_of: usize) -> Summary {
-        //# TODO: improve merging
-        let mut bids = ob1.bids.clone();
-        bids.extend(ob2.bids.clone());
-        bids.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
+        Summary::merge_all([ob1, ob2], best_of)
+    }
+
+    /// Merges any number of books into the best `best_of` levels per side,
+    /// listing the venues of degraded books, i.e. polled over REST, in
+    /// `degraded`. [`manager_with`](crate::grpc::manager_with) adds the
+    /// venues whose book is stale or missing.
+    ///
+    /// Bids are sorted by descending and asks by ascending price, levels at the
+    /// same price keep their input order. Levels without a finite price or a
+    /// positive amount are dropped. The spread is `0.0` while either side is empty.
+    ///
+    /// Levels only carry their venue's id, see [`Summary::with_venue_names`].
+    pub fn merge_all(books: impl IntoIterator<Item = OrderBook>, best_of: usize) -> Summary {
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        let mut degraded = Vec::new();
+        for ob in books {
+            if ob.degraded {
+                degraded.push(ob.exchange.to_string());
+            }
+            let valid = |quote: &Quote| quote.is_valid();
+            bids.extend(ob.bids.into_iter().filter(valid).map(|q| (ob.exchange, q)));
+            asks.extend(ob.asks.into_iter().filter(valid).map(|q| (ob.exchange, q)));
+        }
+
+        bids.sort_by(|(_, a), (_, b)| b.price.total_cmp(&a.price));
         bids.truncate(best_of);
 
-        let mut asks = ob1.asks.clone();
-        asks.extend(ob2.asks.clone());
-        asks.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
+        asks.sort_by(|(_, a), (_, b)| a.price.total_cmp(&b.price));
         asks.truncate(best_of);
 
+        let spread = match (bids.first(), asks.first()) {
+            (Some((_, bid)), Some((_, ask))) => ask.price - bid.price,
+            _ => 0.0,
+        };
+
+        let levels = |side: Vec<(Exchange, Quote)>| {
+            side.into_iter()
+                .map(|(exchange, quote)| quote.to_level(exchange))
+                .collect()
+        };
         Summary {
-            spread: asks[0].price - bids[0].price,
-            bids,
-            asks,
+            spread,
+            bids: levels(bids),
+            asks: levels(asks),
+            degraded,
+        }
+    }
+
+    /// The summary with every level's `exchange` set to its venue's name, for
+    /// clients that ask for names.
+    pub fn with_venue_names(mut self) -> Summary {
+        self.bids.iter_mut().for_each(Level::name_venue);
+        self.asks.iter_mut().for_each(Level::name_venue);
+        self
+    }
+}
+
+/// The part of the summaries a client asked for, see [`SummaryRequest`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// The most levels per side, or `0` for all.
+    pub depth: usize,
+    /// The venues to keep the levels of, or none for all.
+    pub venues: Vec<Exchange>,
+    pub venue_names: bool,
+}
+
+impl Selection {
+    /// The selection of `request`, or an error naming a venue that is not known.
+    pub fn from_request(request: &SummaryRequest) -> Result<Selection, String> {
+        let venues = request
+            .venues
+            .iter()
+            .map(|name| Exchange::lookup(name).ok_or_else(|| format!("unknown venue: {name}")))
+            .collect::<Result<_, _>>()?;
+        Ok(Selection {
+            depth: request.depth as usize,
+            venues,
+            venue_names: request.venue_names,
+        })
+    }
+
+    /// `summary` cut down to the selection. Leaving venues out recomputes the
+    /// spread from the levels kept, and may leave fewer than `depth` of them,
+    /// as the summary only holds the best levels over all venues.
+    pub fn apply(&self, mut summary: Summary) -> Summary {
+        if !self.venues.is_empty() {
+            let kept = |level: &Level| {
+                Exchange::from_id(level.venue).is_some_and(|e| self.venues.contains(&e))
+            };
+            summary.bids.retain(kept);
+            summary.asks.retain(kept);
+            summary
+                .degraded
+                .retain(|name| Exchange::lookup(name).is_some_and(|e| self.venues.contains(&e)));
+            summary.spread = match (summary.bids.first(), summary.asks.first()) {
+                (Some(bid), Some(ask)) => ask.price - bid.price,
+                _ => 0.0,
+            };
+        }
+        if self.depth > 0 {
+            summary.bids.truncate(self.depth);
+            summary.asks.truncate(self.depth);
         }
+        if self.venue_names {
+            summary = summary.with_venue_names();
+        }
+        summary
     }
 }
 
@@ -47,10 +327,17 @@ pub use orderbook_aggregator::orderbook_aggregator_server::{
     OrderbookAggregator, OrderbookAggregatorServer,
 };
 
+/// The order books of one subscription, as handed out by the exchange clients.
+pub type OrderBookStream = Pin<Box<dyn Stream<Item = OrderBook> + Send + Sync>>;
+
 #[derive(Debug, Clone)]
 pub struct OrderBook {
     pub exchange: Exchange,
     pub last_updated: String,
-    pub bids: Vec<Level>,
-    pub asks: Vec<Level>,
+    /// The best levels, best first.
+    pub bids: Vec<Quote>,
+    pub asks: Vec<Quote>,
+    /// Whether the book was polled over REST while the venue's websocket is
+    /// unavailable, so may be stale and shallower than a streamed one.
+    pub degraded: bool,
 }
@@ -0,0 +1,263 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::io::copy_bidirectional;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use algo_challenge::aggregator_client::{
+    AggregatorClient, ClientConfig, MergedBook, MergedBookStream, Overflow, VenueMap,
+};
+use algo_challenge::types::{
+    Empty, Exchange, Level, OrderBook, OrderbookAggregator, OrderbookAggregatorServer, Quote,
+    Summary, SummaryRequest, Venue, VenueList,
+};
+use common::{book, start_aggregator};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn quote(price: f64) -> Quote {
+    Quote { price, amount: 1.0 }
+}
+
+/// A level at `price` on `exchange` as the client resolves it.
+fn level(exchange: Exchange, price: f64) -> (Venue, Quote) {
+    (Venue::from(exchange), quote(price))
+}
+
+fn config(url: String) -> ClientConfig {
+    ClientConfig {
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(100),
+        ..ClientConfig::new(url)
+    }
+}
+
+#[test]
+fn summaries_convert_into_typed_books() {
+    let remote = Venue {
+        id: 4242,
+        name: "Aggregator-Client-Remote".to_string(),
+    };
+    let venues = VenueMap::from(VenueList {
+        venues: vec![Venue::from(Exchange::Bitstamp), remote.clone()],
+    });
+    assert_eq!(venues.get(4242), Some("Aggregator-Client-Remote"));
+    assert_eq!(venues.get(0), None);
+
+    let mut summary = Summary::merge(
+        book(Exchange::Bitstamp, 100.0, 102.0),
+        book(Exchange::Binance, 101.0, 103.0),
+        BEST_OF,
+    );
+    summary.degraded = vec!["Bitstamp".to_string()];
+    summary.bids[1].venue = 4242;
+
+    let merged = MergedBook::from_summary(summary.clone(), &venues);
+    assert_eq!(merged.spread, 1.0);
+    // Binance is not in the map, so its levels are dropped.
+    assert_eq!(merged.bids, vec![(remote, quote(100.0))]);
+    assert_eq!(merged.asks, vec![level(Exchange::Bitstamp, 102.0)]);
+    assert_eq!(merged.degraded, vec!["Bitstamp"]);
+
+    let named = MergedBook::from_summary(summary.with_venue_names(), &VenueMap::default());
+    assert_eq!(named.best_ask(), Some(&level(Exchange::Bitstamp, 102.0)));
+
+    let books = named.venue_books();
+    let sides = books
+        .iter()
+        .map(|book| {
+            (
+                book.venue.name.as_str(),
+                book.bids.clone(),
+                book.asks.clone(),
+                book.degraded,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sides,
+        vec![
+            ("Binance", vec![quote(101.0)], vec![quote(103.0)], false),
+            ("Bitstamp", vec![], vec![quote(102.0)], true),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn summaries_stream_merged_books() {
+    let (tx, url) = start_aggregator(&[Exchange::Bitstamp, Exchange::Binance], BEST_OF).await;
+    let client = AggregatorClient::with_config(config(url));
+    assert_eq!(client.venues().await.unwrap().get(1), Some("Bitstamp"));
+
+    let mut books = client.summaries();
+    tx.send(book(Exchange::Bitstamp, 100.0, 102.0)).unwrap();
+    let merged = next_with(&mut books, &tx, book(Exchange::Binance, 101.0, 103.0)).await;
+    assert_eq!(merged.best_bid(), Some(&level(Exchange::Binance, 101.0)));
+    assert_eq!(merged.best_ask(), Some(&level(Exchange::Bitstamp, 102.0)));
+}
+
+#[tokio::test]
+async fn unreachable_servers_fail_without_retrying() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let client = AggregatorClient::new(url);
+    assert!(timeout(WAIT, client.venues()).await.unwrap().is_err());
+}
+
+/// Forwards every connection on `listener` to `upstream`, until the task is
+/// aborted, which cuts them all off.
+async fn forward(listener: TcpListener, upstream: String) {
+    let mut connections = JoinSet::new();
+    loop {
+        let (mut inbound, _) = listener.accept().await.unwrap();
+        let upstream = upstream.clone();
+        connections.spawn(async move {
+            let mut outbound = TcpStream::connect(upstream).await.unwrap();
+            let _ = copy_bidirectional(&mut inbound, &mut outbound).await;
+        });
+    }
+}
+
+/// Sends `book` until a summary arrives, as the subscription may still be on
+/// its way.
+async fn next_with(
+    books: &mut MergedBookStream,
+    tx: &broadcast::Sender<OrderBook>,
+    book: OrderBook,
+) -> MergedBook {
+    timeout(WAIT, async {
+        loop {
+            let _ = tx.send(book.clone());
+            if let Ok(Some(merged)) = timeout(Duration::from_millis(50), books.next()).await {
+                return merged.unwrap();
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn summaries_resume_after_the_connection_drops() {
+    let (tx, url) = start_aggregator(&[Exchange::Bitstamp], BEST_OF).await;
+    let upstream = url.trim_start_matches("http://").to_string();
+    let addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    };
+    // Subscribing before the server is reachable keeps retrying.
+    let client = AggregatorClient::with_config(config(format!("http://{addr}")));
+    let mut books = client.summaries();
+    assert!(timeout(Duration::from_millis(100), books.next())
+        .await
+        .is_err());
+
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let proxy = tokio::spawn(forward(listener, upstream.clone()));
+    let merged = next_with(&mut books, &tx, book(Exchange::Bitstamp, 100.0, 102.0)).await;
+    assert_eq!(merged.best_bid(), Some(&level(Exchange::Bitstamp, 100.0)));
+
+    proxy.abort();
+    let _ = proxy.await;
+
+    let listener = TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(forward(listener, upstream));
+    let merged = next_with(&mut books, &tx, book(Exchange::Bitstamp, 200.0, 202.0)).await;
+    assert_eq!(merged.best_bid(), Some(&level(Exchange::Bitstamp, 200.0)));
+}
+
+#[tokio::test]
+async fn slow_consumers_can_skip_to_the_latest_summary() {
+    let (tx, url) = start_aggregator(&[Exchange::Bitstamp], BEST_OF).await;
+    let client = AggregatorClient::with_config(ClientConfig {
+        overflow: Overflow::Latest,
+        ..config(url)
+    });
+    let mut books = client.summaries();
+    let first = next_with(&mut books, &tx, book(Exchange::Bitstamp, 1.0, 2.0)).await;
+    assert_eq!(first.best_bid(), Some(&level(Exchange::Bitstamp, 1.0)));
+
+    for bid in 2..=20 {
+        tx.send(book(Exchange::Bitstamp, f64::from(bid), 100.0))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let latest = timeout(WAIT, books.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(latest.best_bid(), Some(&level(Exchange::Bitstamp, 20.0)));
+}
+
+/// A server listing only Bitstamp, whose summaries also quote a venue it
+/// does not list.
+#[derive(Default)]
+struct UnlistedVenue {
+    lookups: Arc<AtomicUsize>,
+}
+
+#[tonic::async_trait]
+impl OrderbookAggregator for UnlistedVenue {
+    type BookSummaryStream = ReceiverStream<Result<Summary, Status>>;
+
+    async fn book_summary(
+        &self,
+        _: Request<SummaryRequest>,
+    ) -> Result<Response<Self::BookSummaryStream>, Status> {
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        for _ in 0..5 {
+            let mut summary = Summary::merge(
+                book(Exchange::Bitstamp, 100.0, 102.0),
+                book(Exchange::Binance, 101.0, 103.0),
+                BEST_OF,
+            );
+            summary.bids.push(Level {
+                venue: 4242,
+                ..summary.bids[0].clone()
+            });
+            tx.try_send(Ok(summary)).unwrap();
+        }
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn venues(&self, _: Request<Empty>) -> Result<Response<VenueList>, Status> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        Ok(Response::new(VenueList {
+            venues: vec![Venue::from(Exchange::Bitstamp)],
+        }))
+    }
+}
+
+#[tokio::test]
+async fn unknown_venues_are_looked_up_once() {
+    let server = UnlistedVenue::default();
+    let lookups = server.lookups.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        Server::builder()
+            .add_service(OrderbookAggregatorServer::new(server))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let mut books = AggregatorClient::with_config(config(url)).summaries();
+    for _ in 0..5 {
+        let merged = timeout(WAIT, books.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(merged.best_bid(), Some(&level(Exchange::Bitstamp, 100.0)));
+        assert_eq!(merged.bids.len(), 1);
+    }
+    // Once when subscribing, and once more for the unknown venue.
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,273 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+use tonic::{Code, Request};
+
+use algo_challenge::aggregator_client::{AggregatorClient, ClientConfig, Error, Overflow};
+use algo_challenge::auth::{Auth, AuthConfig, KeyConfig};
+use algo_challenge::grpc::GrpcConfig;
+use algo_challenge::types::{Empty, Exchange, OrderbookAggregatorClient, SummaryRequest};
+use common::{book, start_aggregator_with};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+const VENUES: [Exchange; 3] = [Exchange::Bitstamp, Exchange::Binance, Exchange::Kraken];
+
+/// The symbols the test server aggregates.
+fn instruments() -> Vec<(Exchange, String)> {
+    vec![
+        (Exchange::Bitstamp, "btcusd".to_string()),
+        (Exchange::Binance, "btcusdt".to_string()),
+        (Exchange::Kraken, "BTC/USD".to_string()),
+    ]
+}
+
+fn key(key: &str) -> KeyConfig {
+    KeyConfig {
+        key: key.to_string(),
+        venues: vec![],
+        instruments: vec![],
+        max_streams: None,
+    }
+}
+
+fn auth_config(keys: Vec<KeyConfig>) -> GrpcConfig {
+    let auth = Auth::new(&AuthConfig { keys }, &instruments()).unwrap();
+    GrpcConfig {
+        auth: Some(auth),
+        ..GrpcConfig::default()
+    }
+}
+
+fn with_key<T>(message: T, key: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("authorization", format!("Bearer {key}").parse().unwrap());
+    request
+}
+
+#[test]
+fn keys_are_read_from_json() {
+    let config = serde_json::from_str::<AuthConfig>(
+        r#"{"keys": [{"key": "a"}, {"key": "b", "venues": ["kraken"], "instruments": ["btc-usd"], "max_streams": 2}]}"#,
+    )
+    .unwrap();
+    assert_eq!(config.keys[0], key("a"));
+    assert_eq!(config.keys[1].venues, ["kraken"]);
+    assert_eq!(config.keys[1].max_streams, Some(2));
+
+    let auth = Auth::new(&config, &instruments()).unwrap();
+    assert!(auth.grant("a").unwrap().allows(Exchange::Okx));
+    // Kraken's `BTC/USD` matches `btc-usd`; Bitstamp's `btcusd` does too,
+    // but Bitstamp is not among the key's venues.
+    let b = auth.grant("b").unwrap();
+    assert!(b.allows(Exchange::Kraken));
+    assert!(!b.allows(Exchange::Bitstamp));
+    assert!(auth.grant("c").is_none());
+
+    let unknown = AuthConfig {
+        keys: vec![KeyConfig {
+            venues: vec!["nowhere".to_string()],
+            ..key("a")
+        }],
+    };
+    assert!(Auth::new(&unknown, &instruments()).is_err());
+}
+
+#[tokio::test]
+async fn calls_without_a_valid_key_are_unauthenticated() {
+    let config = auth_config(vec![key("s3cr3t")]);
+    let (_tx, url) = start_aggregator_with(&VENUES, BEST_OF, config).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+
+    let status = client
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert!(status.message().contains("missing API key"));
+
+    let status = client
+        .venues(with_key(Empty {}, "guess"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.message(), "invalid API key");
+
+    let mut request = Request::new(Empty {});
+    request
+        .metadata_mut()
+        .insert("x-api-key", "s3cr3t".parse().unwrap());
+    assert_eq!(
+        client
+            .venues(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .venues
+            .len(),
+        9
+    );
+
+    // An `authorization` header of another scheme leaves the `x-api-key`.
+    let mut request = Request::new(Empty {});
+    let metadata = request.metadata_mut();
+    metadata.insert("authorization", "Basic dXNlcjpwYXNz".parse().unwrap());
+    metadata.insert("x-api-key", "s3cr3t".parse().unwrap());
+    assert!(client.venues(request).await.is_ok());
+}
+
+#[tokio::test]
+async fn keys_only_see_their_venues() {
+    let config = auth_config(vec![KeyConfig {
+        instruments: vec!["btcusd".to_string()],
+        ..key("usd")
+    }]);
+    let (tx, url) = start_aggregator_with(&VENUES, BEST_OF, config).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+
+    let status = client
+        .book_summary(with_key(
+            SummaryRequest {
+                venues: vec!["binance".to_string()],
+                ..SummaryRequest::default()
+            },
+            "usd",
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(status.message(), "venue not permitted: Binance");
+
+    let venues = client
+        .venues(with_key(Empty {}, "usd"))
+        .await
+        .unwrap()
+        .into_inner();
+    let names = venues
+        .venues
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Bitstamp", "Kraken"]);
+
+    let request = SummaryRequest {
+        venue_names: true,
+        ..SummaryRequest::default()
+    };
+    let mut summaries = client
+        .book_summary(with_key(request, "usd"))
+        .await
+        .unwrap()
+        .into_inner();
+    // Binance has the best levels, which the key does not see.
+    tx.send(book(Exchange::Bitstamp, 99.0, 102.0)).unwrap();
+    tx.send(book(Exchange::Binance, 100.0, 101.0)).unwrap();
+    tx.send(book(Exchange::Kraken, 98.0, 103.0)).unwrap();
+    let summary = timeout(WAIT, summaries.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let bids = summary
+        .bids
+        .iter()
+        .map(|level| level.exchange.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(bids, ["Bitstamp", "Kraken"]);
+    assert_eq!(summary.spread, 3.0);
+}
+
+#[tokio::test]
+async fn streams_are_capped_per_key() {
+    let config = auth_config(vec![KeyConfig {
+        max_streams: Some(1),
+        ..key("one")
+    }]);
+    let (_tx, url) = start_aggregator_with(&VENUES, BEST_OF, config).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+    let subscribe = |mut client: OrderbookAggregatorClient<_>| async move {
+        client
+            .book_summary(with_key(SummaryRequest::default(), "one"))
+            .await
+    };
+
+    let first = subscribe(client.clone()).await.unwrap();
+    let status = subscribe(client.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    // Closing the stream frees its slot.
+    drop(first);
+    timeout(WAIT, async {
+        while subscribe(client.clone()).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(client.venues(with_key(Empty {}, "one")).await.is_ok());
+}
+
+#[tokio::test]
+async fn aggregator_clients_send_their_key() {
+    let config = auth_config(vec![KeyConfig {
+        venues: vec!["kraken".to_string()],
+        ..key("s3cr3t")
+    }]);
+    let (tx, url) = start_aggregator_with(&VENUES, BEST_OF, config).await;
+
+    let anonymous = AggregatorClient::new(url.clone());
+    assert!(anonymous.venues().await.is_err());
+
+    let client = AggregatorClient::with_config(ClientConfig {
+        api_key: Some("s3cr3t".to_string()),
+        ..ClientConfig::new(url)
+    });
+    let mut books = client.summaries();
+    let sent = tokio::spawn(async move {
+        loop {
+            for exchange in VENUES {
+                let _ = tx.send(book(exchange, 100.0, 101.0));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+    let book = timeout(WAIT, books.next()).await.unwrap().unwrap().unwrap();
+    sent.abort();
+    assert_eq!(book.bids.len(), 1);
+    assert_eq!(book.best_bid().unwrap().0.name, "Kraken");
+}
+
+#[tokio::test]
+async fn refused_clients_stop_retrying() {
+    let config = auth_config(vec![KeyConfig {
+        max_streams: Some(0),
+        ..key("none")
+    }]);
+    let (_tx, url) = start_aggregator_with(&VENUES, BEST_OF, config).await;
+
+    for api_key in [None, Some("guess"), Some("none")] {
+        let client = AggregatorClient::with_config(ClientConfig {
+            api_key: api_key.map(str::to_string),
+            ..ClientConfig::new(url.clone())
+        });
+        let mut books = client.summaries();
+        let refused = timeout(WAIT, books.next()).await.unwrap().unwrap();
+        assert!(matches!(refused, Err(Error::Refused(_))), "{refused:?}");
+        assert!(timeout(WAIT, books.next()).await.unwrap().is_none());
+    }
+
+    // With only the latest summary kept, the refusal still comes through.
+    let client = AggregatorClient::with_config(ClientConfig {
+        overflow: Overflow::Latest,
+        ..ClientConfig::new(url)
+    });
+    let mut books = client.summaries();
+    let refused = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert!(matches!(refused, Err(Error::Refused(_))));
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_none());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{
+    combined_stream_url, depth_stream, parse_order_book, BinanceClient, PriceLevels, Speed,
+};
+use common::{binance_combined, binance_depth, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn stream_names_and_urls() {
+    let btcusdt = depth_stream("btcusdt", PriceLevels::L20, Speed::S100);
+    let ethbtc = depth_stream("ethbtc", PriceLevels::L5, Speed::S1000);
+    assert_eq!(btcusdt, "btcusdt@depth20@100ms");
+    assert_eq!(ethbtc, "ethbtc@depth5@1000ms");
+    assert_eq!(
+        combined_stream_url("wss://host:9443", &[btcusdt, ethbtc]),
+        "wss://host:9443/stream?streams=btcusdt@depth20@100ms/ethbtc@depth5@1000ms"
+    );
+    assert_eq!(combined_stream_url("wss://host", &[]), "wss://host/stream");
+}
+
+#[test]
+fn recorded_frames_of_both_endpoints_parse() {
+    let raw = binance_depth(7, &[("1.0", "2.0")], &[("3.0", "4.0")]);
+    let combined = binance_combined("btcusdt@depth20@100ms", &raw);
+
+    let from_raw = parse_order_book(&raw, BEST_OF).unwrap();
+    let from_combined = parse_order_book(&combined, BEST_OF).unwrap();
+    assert_eq!(from_raw.last_updated, "7");
+    assert_eq!(from_combined.last_updated, "7");
+    assert_eq!(from_combined.bids, from_raw.bids);
+}
+
+#[tokio::test]
+async fn subscriptions_on_one_connection_get_their_own_books() {
+    let btcusdt = "btcusdt@depth20@100ms";
+    let ethbtc = "ethbtc@depth20@100ms";
+    let frame = |stream: &str, id: u64, price: &str| {
+        binance_combined(stream, &binance_depth(id, &[(price, "1.0")], &[]))
+    };
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![
+            // Gives the client time to subscribe to the second stream.
+            Step::Sleep(Duration::from_millis(100)),
+            Step::Send(frame(ethbtc, 1, "0.05")),
+            Step::Send(frame(btcusdt, 2, "30000.0")),
+            Step::Send(frame(ethbtc, 3, "0.06")),
+            Step::Send(frame(btcusdt, 4, "30001.0")),
+        ]],
+    )
+    .await;
+
+    let mut client = BinanceClient::connect(&binance.url("/stream"))
+        .await
+        .unwrap();
+    let mut btcusdt_books = client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let mut ethbtc_books = client
+        .subscribe_orderbook("ethbtc", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for (id, price) in [("2", 30000.0), ("4", 30001.0)] {
+        let ob = timeout(WAIT, btcusdt_books.next()).await.unwrap().unwrap();
+        assert_eq!((ob.last_updated.as_str(), ob.bids[0].price), (id, price));
+    }
+    for (id, price) in [("1", 0.05), ("3", 0.06)] {
+        let ob = timeout(WAIT, ethbtc_books.next()).await.unwrap().unwrap();
+        assert_eq!((ob.last_updated.as_str(), ob.bids[0].price), (id, price));
+    }
+}
+
+#[tokio::test]
+async fn streams_can_be_subscribed_through_the_url() {
+    let btcusdt = depth_stream("btcusdt", PriceLevels::L5, Speed::S100);
+    let ethbtc = depth_stream("ethbtc", PriceLevels::L5, Speed::S100);
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![
+            Step::Sleep(Duration::from_millis(100)),
+            Step::Send(binance_combined(
+                &ethbtc,
+                &binance_depth(1, &[("0.05", "1.0")], &[]),
+            )),
+            Step::Send(binance_combined(
+                &btcusdt,
+                &binance_depth(2, &[("30000.0", "1.0")], &[]),
+            )),
+        ]],
+    )
+    .await;
+
+    let url = combined_stream_url(&binance.addr, &[btcusdt.clone(), ethbtc]);
+    let client = BinanceClient::connect(&url).await.unwrap();
+    let mut books = client.order_book_events(&btcusdt, BEST_OF).book_events;
+
+    let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(ob.last_updated, "2");
+    assert!(binance.received().is_empty());
+}
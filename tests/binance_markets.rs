@@ -0,0 +1,95 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{
+    combined_stream_url, BinanceClient, Market, PriceLevels, Speed,
+};
+use algo_challenge::types::Exchange;
+use common::{
+    binance_combined, binance_depth, binance_futures_depth, MockExchange, Protocol, Step,
+};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn markets_are_distinct_venues() {
+    for market in [Market::Spot, Market::UsdM, Market::CoinM, Market::Us] {
+        let exchange = market.exchange();
+        assert_eq!(Market::from_exchange(exchange), Some(market));
+        assert_eq!(exchange.to_string().parse::<Exchange>(), Ok(exchange));
+    }
+    assert_eq!("binance-usdm".parse(), Ok(Exchange::BinanceUsdm));
+    assert_eq!(Market::from_exchange(Exchange::Kraken), None);
+}
+
+#[test]
+fn futures_streams_name_their_default_speed_implicitly() {
+    assert_eq!(
+        Market::UsdM.depth_stream("BTCUSDT", PriceLevels::L20, Speed::S250),
+        "btcusdt@depth20"
+    );
+    assert_eq!(
+        Market::CoinM.depth_stream("btcusd_perp", PriceLevels::L10, Speed::S500),
+        "btcusd_perp@depth10@500ms"
+    );
+    assert_eq!(
+        Market::Us.depth_stream("btcusd", PriceLevels::L20, Speed::S100),
+        "btcusd@depth20@100ms"
+    );
+    assert_eq!(Market::UsdM.default_speed() as u16, 250);
+    assert_eq!(Market::Us.default_speed() as u16, 100);
+}
+
+#[test]
+fn each_market_parses_its_own_schema() {
+    let futures = binance_futures_depth("btcusdt", 42, &[("30000.1", "2.5")], &[("30000.2", "1")]);
+    let ob = Market::UsdM.parse_order_book(&futures, BEST_OF).unwrap();
+    assert_eq!(ob.exchange, Exchange::BinanceUsdm);
+    assert_eq!(ob.last_updated, "42");
+    assert_eq!(ob.bids[0].price, 30000.1);
+    assert_eq!(ob.exchange.name(), "Binance-USDM");
+    assert_eq!(ob.asks[0].amount, 1.0);
+
+    let combined = binance_combined("btcusd_perp@depth20", &futures);
+    let ob = Market::CoinM.parse_order_book(&combined, BEST_OF).unwrap();
+    assert_eq!(ob.exchange, Exchange::BinanceCoinm);
+
+    let spot = binance_depth(7, &[("1.0", "2.0")], &[]);
+    let ob = Market::Us.parse_order_book(&spot, BEST_OF).unwrap();
+    assert_eq!(ob.exchange, Exchange::BinanceUs);
+    assert!(Market::UsdM.parse_order_book(&spot, BEST_OF).is_none());
+    assert!(Market::Spot.parse_order_book(&futures, BEST_OF).is_none());
+}
+
+#[tokio::test]
+async fn futures_clients_tag_books_with_their_market() {
+    let stream = "btcusdt@depth20";
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![Step::Send(binance_combined(
+            stream,
+            &binance_futures_depth("btcusdt", 9, &[("30000.1", "2.5")], &[]),
+        ))]],
+    )
+    .await;
+
+    let url = combined_stream_url(&binance.url(""), &[]);
+    let mut client = BinanceClient::connect_market(Market::UsdM, &url, None)
+        .await
+        .unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTCUSDT", PriceLevels::L20, Speed::S250, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(ob.exchange, Exchange::BinanceUsdm);
+    assert_eq!(ob.last_updated, "9");
+    assert_eq!(client.market(), Market::UsdM);
+}
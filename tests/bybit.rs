@@ -0,0 +1,168 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::bybit_client::{BookDepth, Books, BybitClient, PING};
+use algo_challenge::exchange::connection::PING_AFTER_IDLE;
+use algo_challenge::exchange::error::Error;
+use algo_challenge::types::{Exchange, Quote};
+use common::{bybit_book, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const BIDS: &[(&str, &str)] = &[("34245.40", "0.510"), ("34245.30", "0.030")];
+const ASKS: &[(&str, &str)] = &[("34245.50", "1.200")];
+
+fn prices(levels: &[Quote]) -> Vec<(f64, f64)> {
+    levels.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+fn snapshot(update_id: u64) -> String {
+    bybit_book("snapshot", "BTCUSDT", BIDS, ASKS, update_id)
+}
+
+#[test]
+fn deltas_are_applied_in_sequence() {
+    let mut books = Books::default();
+    let ob = books.apply(&snapshot(10), BEST_OF).unwrap().unwrap();
+    assert_eq!(ob.exchange, Exchange::Bybit);
+    assert_eq!(prices(&ob.bids), vec![(34245.4, 0.51), (34245.3, 0.03)]);
+
+    let delta = bybit_book(
+        "delta",
+        "BTCUSDT",
+        &[("34245.40", "0")],
+        &[("34245.60", "0.400")],
+        11,
+    );
+    let ob = books.apply(&delta, BEST_OF).unwrap().unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34245.3, 0.03)]);
+    assert_eq!(prices(&ob.asks), vec![(34245.5, 1.2), (34245.6, 0.4)]);
+    assert_eq!(ob.last_updated, "1697700000000");
+}
+
+#[test]
+fn sequence_gaps_drop_the_book() {
+    let mut books = Books::default();
+    books.apply(&snapshot(10), BEST_OF).unwrap();
+
+    let delta = bybit_book("delta", "BTCUSDT", &[("34245.41", "1")], &[], 12);
+    assert_eq!(
+        books.apply(&delta, BEST_OF).err(),
+        Some(Error::SequenceGap("BTCUSDT".to_string()))
+    );
+    // Later deltas are ignored until the next snapshot, which may restart the
+    // update ids after a service restart.
+    assert!(books.apply(&delta, BEST_OF).unwrap().is_none());
+    assert!(books.apply(&snapshot(1), BEST_OF).unwrap().is_some());
+}
+
+#[test]
+fn levels_that_are_not_numbers_drop_the_book() {
+    let mut books = Books::default();
+    books.apply(&snapshot(10), BEST_OF).unwrap();
+
+    let delta = bybit_book("delta", "BTCUSDT", &[("34245.41", "lots")], &[], 11);
+    assert_eq!(
+        books.apply(&delta, BEST_OF).err(),
+        Some(Error::CorruptBook("BTCUSDT".to_string()))
+    );
+    let delta = bybit_book("delta", "BTCUSDT", &[], &[], 12);
+    assert!(books.apply(&delta, BEST_OF).unwrap().is_none());
+    assert!(books.apply(&snapshot(13), BEST_OF).unwrap().is_some());
+}
+
+#[tokio::test]
+async fn sequence_gaps_resubscribe() {
+    let bybit = MockExchange::start(
+        Protocol::Bybit,
+        vec![vec![
+            Step::Send(snapshot(10)),
+            Step::Send(bybit_book("delta", "BTCUSDT", &[], &[], 20)),
+            // Answers the resubscription.
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Send(snapshot(21)),
+        ]],
+    )
+    .await;
+
+    let mut client = BybitClient::connect(&bybit.url("/v5/public/spot"))
+        .await
+        .unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTCUSDT", BookDepth::D50, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for _ in 0..2 {
+        let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.bids[0].price, 34245.4);
+    }
+
+    let ops = bybit
+        .received()
+        .iter()
+        .filter_map(|text| serde_json::from_str::<Value>(text).ok())
+        .map(|request| {
+            assert_eq!(request["args"][0], "orderbook.50.BTCUSDT");
+            request["op"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ops, vec!["subscribe", "unsubscribe", "subscribe"]);
+}
+
+#[tokio::test]
+async fn invalid_symbols_are_typed_errors() {
+    let bybit = MockExchange::start(
+        Protocol::Bybit,
+        vec![vec![Step::Reply(
+            r#"{"success":false,"ret_msg":"Invalid symbol :[orderbook.50.BTCXYZ]","conn_id":"cejreaspqfh3sjdnldmg","req_id":{id},"op":"subscribe"}"#
+                .to_string(),
+        )]],
+    )
+    .await;
+
+    let mut client = BybitClient::connect(&bybit.url("/v5/public/spot"))
+        .await
+        .unwrap();
+    let result = client
+        .subscribe_orderbook("BTCXYZ", BookDepth::D50, BEST_OF)
+        .await;
+    assert_eq!(
+        result.err(),
+        Some(Error::UnknownSymbol("BTCXYZ".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn text_pings_are_sent() {
+    let bybit = MockExchange::start(Protocol::Bybit, vec![vec![Step::Send(snapshot(1))]]).await;
+
+    let mut client = BybitClient::connect(&bybit.url("/v5/public/spot"))
+        .await
+        .unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTCUSDT", BookDepth::D50, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    timeout(WAIT, books.next()).await.unwrap().unwrap();
+
+    tokio::time::pause();
+    tokio::time::advance(PING_AFTER_IDLE).await;
+    tokio::time::resume();
+
+    timeout(WAIT, async {
+        while !bybit.received().contains(&PING.to_string()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::coinbase_client::{Books, CoinbaseClient};
+use algo_challenge::exchange::error::Error;
+use algo_challenge::types::{Exchange, OrderbookAggregatorClient, Quote, SummaryRequest};
+use common::{
+    book, coinbase_snapshot, coinbase_update, start_aggregator, MockExchange, Protocol, Step,
+};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn prices(levels: &[Quote]) -> Vec<(f64, f64)> {
+    levels.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+#[tokio::test]
+async fn books_are_kept_from_snapshot_and_updates() {
+    let coinbase = MockExchange::start(
+        Protocol::Coinbase,
+        vec![vec![
+            Step::Send(coinbase_snapshot(
+                "BTC-USD",
+                &[("100.00", "1.0"), ("99.00", "2.0")],
+                &[("101.00", "1.0"), ("102.00", "1.0")],
+            )),
+            Step::Send(coinbase_update(
+                "BTC-USD",
+                "2023-10-19T07:20:00.000000Z",
+                &[
+                    ("buy", "100.00", "0.00000000"),
+                    ("buy", "99.50", "1.5"),
+                    ("sell", "101.00", "3.0"),
+                ],
+            )),
+        ]],
+    )
+    .await;
+
+    let mut client = CoinbaseClient::connect(&coinbase.url("/")).await.unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTC-USD", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    let snapshot = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(snapshot.exchange, Exchange::Coinbase);
+    assert_eq!(prices(&snapshot.bids), vec![(100.0, 1.0), (99.0, 2.0)]);
+    assert_eq!(prices(&snapshot.asks), vec![(101.0, 1.0), (102.0, 1.0)]);
+
+    let updated = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(updated.last_updated, "2023-10-19T07:20:00.000000Z");
+    assert_eq!(prices(&updated.bids), vec![(99.5, 1.5), (99.0, 2.0)]);
+    assert_eq!(prices(&updated.asks), vec![(101.0, 3.0), (102.0, 1.0)]);
+
+    let subscribe = &coinbase.received()[0];
+    assert!(subscribe.contains(r#""product_ids":["BTC-USD"]"#));
+    assert!(subscribe.contains("level2_batch"));
+}
+
+#[test]
+fn updates_need_a_snapshot_and_books_are_cut_to_best_of() {
+    let mut books = Books::default();
+    let update = coinbase_update("ETH-USD", "", &[("buy", "10.0", "1.0")]);
+    assert!(books.apply(&update, 2).is_none());
+
+    let snapshot = coinbase_snapshot(
+        "ETH-USD",
+        &[("9.0", "1.0"), ("8.0", "1.0"), ("7.0", "1.0")],
+        &[("11.0", "1.0")],
+    );
+    assert_eq!(books.apply(&snapshot, 2).unwrap().bids.len(), 2);
+    let ob = books.apply(&update, 2).unwrap();
+    assert_eq!(prices(&ob.bids), vec![(10.0, 1.0), (9.0, 1.0)]);
+
+    // Another product's updates leave this book alone.
+    assert!(books
+        .apply(&coinbase_update("BTC-USD", "", &[("buy", "1.0", "1.0")]), 2)
+        .is_none());
+}
+
+#[tokio::test]
+async fn unknown_products_are_typed_errors() {
+    let coinbase = MockExchange::start(
+        Protocol::Coinbase,
+        vec![vec![Step::Reply(
+            r#"{"type":"error","message":"Failed to subscribe","reason":"{channel} is not a valid product"}"#
+                .to_string(),
+        )]],
+    )
+    .await;
+
+    let mut client = CoinbaseClient::connect(&coinbase.url("/")).await.unwrap();
+    let result = client.subscribe_orderbook("BTC-XYZ", BEST_OF).await;
+    assert_eq!(
+        result.err(),
+        Some(Error::UnknownSymbol("BTC-XYZ".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn summaries_merge_every_venue() {
+    let venues = [Exchange::Bitstamp, Exchange::Binance, Exchange::Coinbase];
+    let (tx, url) = start_aggregator(&venues, BEST_OF).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+    let mut summaries = client
+        .book_summary(SummaryRequest {
+            venue_names: true,
+            ..SummaryRequest::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    tx.send(book(Exchange::Binance, 99.0, 102.0)).unwrap();
+    tx.send(book(Exchange::Bitstamp, 98.0, 103.0)).unwrap();
+    tx.send(book(Exchange::Coinbase, 100.0, 101.0)).unwrap();
+
+    // The first summary waits for the last venue.
+    let summary = timeout(WAIT, summaries.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let bids = summary
+        .bids
+        .iter()
+        .map(|l| l.exchange.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(bids, vec!["Coinbase", "Binance", "Bitstamp"]);
+    assert_eq!(summary.spread, 1.0);
+}
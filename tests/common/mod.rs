@@ -0,0 +1,596 @@
+//! Test support: in-process stand-ins for the exchanges' websocket and REST APIs.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use algo_challenge::grpc::{manager, serve_grpc_with, GrpcConfig};
+use algo_challenge::types::{Exchange, OrderBook, Quote, Summary};
+
+/// The subscribe protocol the mock answers.
+#[derive(Debug, Clone, Copy)]
+pub enum Protocol {
+    /// `{"method":"SUBSCRIBE","params":[..],"id":n}` (or `UNSUBSCRIBE`) answered
+    /// with `{"result":null,"id":n}`.
+    Binance,
+    /// `{"event":"bts:subscribe","data":{"channel":..}}` answered with
+    /// `bts:subscription_succeeded`, and `bts:unsubscribe` with
+    /// `bts:unsubscription_succeeded`.
+    Bitstamp,
+    /// `{"type":"subscribe","product_ids":[..],"channels":[..]}` answered with a
+    /// `subscriptions` message listing them, `unsubscribe` with an empty one.
+    Coinbase,
+    /// `{"method":"subscribe","params":{..},"req_id":n}` (or `unsubscribe`)
+    /// answered with `{"method":..,"req_id":n,"success":true}`.
+    Kraken,
+    /// `{"op":"subscribe","args":[{"channel":..,"instId":..}]}` (or
+    /// `unsubscribe`) answered with `{"event":..,"arg":{..}}`.
+    Okx,
+    /// `{"req_id":"n","op":"subscribe","args":[..]}` (or `unsubscribe`)
+    /// answered with `{"success":true,"req_id":"n","op":..}`.
+    Bybit,
+    /// Any JSON message answered with `{"event":"subscribed"}`.
+    Generic,
+}
+
+/// One scripted action taken after the client first subscribed.
+///
+/// Every subscribe and unsubscribe request is acknowledged automatically; a
+/// script starting with [`Step::Reply`] or [`Step::NoReply`] replaces the
+/// acknowledgement of the first one.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Answers the subscription with this text, `{id}` and `{channel}` being
+    /// replaced with the request's.
+    Reply(String),
+    /// Leaves the subscription unanswered.
+    NoReply,
+    Send(String),
+    /// Sends a ping frame; the client's pong is logged as `pong:<payload>`.
+    Ping(String),
+    Sleep(Duration),
+    /// Sends a close frame and ends the connection.
+    Close,
+    /// Drops the TCP connection without a close handshake.
+    Drop,
+}
+
+/// A websocket server on `127.0.0.1` that plays one script per accepted
+/// connection, in order. Connections beyond the last script stay idle.
+pub struct MockExchange {
+    pub addr: String,
+    /// Every text frame (and pong) received from clients, across all connections.
+    pub received: Arc<Mutex<Vec<String>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockExchange {
+    pub async fn start(protocol: Protocol, scripts: Vec<Vec<Step>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = received.clone();
+        let handle = tokio::spawn(async move {
+            let mut scripts = scripts.into_iter();
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = scripts.next().unwrap_or_default();
+                tokio::spawn(serve(protocol, stream, script, log.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            received,
+            handle,
+        }
+    }
+
+    /// The URL of `path` on this server, e.g. `url("/ws")`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.addr)
+    }
+
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// An HTTP server on `127.0.0.1` answering each request with the next of its
+/// `(status, body)` responses, repeating the last one.
+pub struct MockHttp {
+    pub addr: String,
+    /// The path and query of every request, in order.
+    pub requests: Arc<Mutex<Vec<String>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockHttp {
+    pub async fn start(responses: Vec<(u16, String)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let next = Arc::new(AtomicUsize::new(0));
+        let responses = Arc::new(responses);
+
+        let log = requests.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (log, next, responses) = (log.clone(), next.clone(), responses.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split(' ').nth(1).unwrap_or_default();
+                    log.lock().unwrap().push(path.to_string());
+
+                    let i = next.fetch_add(1, Ordering::SeqCst).min(responses.len() - 1);
+                    let (status, body) = &responses[i];
+                    let response = format!(
+                        "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            requests,
+            handle,
+        }
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockHttp {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn serve(
+    protocol: Protocol,
+    stream: TcpStream,
+    script: Vec<Step>,
+    log: Arc<Mutex<Vec<String>>>,
+) {
+    let mut subscribed_in_url = false;
+    #[allow(clippy::result_large_err)] // The signature tungstenite expects.
+    let callback = |request: &Request, response: Response| {
+        subscribed_in_url = request
+            .uri()
+            .query()
+            .is_some_and(|query| query.contains("streams="));
+        Ok(response)
+    };
+    let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, callback).await else {
+        return;
+    };
+
+    // The script plays in its own task, starting with the first subscription,
+    // or right away if the client already subscribed through the URL
+    // (Binance's `/stream?streams=`). Requests are answered meanwhile.
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel();
+    let mut script = Some(script);
+    let mut player = None;
+    if subscribed_in_url {
+        player = script
+            .take()
+            .map(|steps| tokio::spawn(play(steps, out_tx.clone())));
+    }
+
+    loop {
+        tokio::select! {
+            text = next_text(&mut ws, &log) => {
+                let Some(text) = text else {
+                    break;
+                };
+                let Some(ack) = ack(protocol, &text) else {
+                    continue;
+                };
+                let Some(steps) = script.take() else {
+                    if ws.send(Message::Text(ack)).await.is_err() {
+                        break;
+                    }
+                    continue;
+                };
+                let mut steps = steps.into_iter().peekable();
+                match steps.peek() {
+                    Some(Step::Reply(reply)) => {
+                        let request = serde_json::from_str::<Value>(&text).unwrap();
+                        let channel = request["data"]["channel"]
+                            .as_str()
+                            .or(request["product_ids"][0].as_str())
+                            .or(request["params"]["symbol"][0].as_str())
+                            .or(request["args"][0]["instId"].as_str())
+                            .or(request["args"][0].as_str())
+                            .unwrap_or("");
+                        let reply = reply
+                            .replace("{id}", &request_id(&request).to_string())
+                            .replace("{channel}", channel);
+                        ws.send(Message::Text(reply)).await.unwrap();
+                        steps.next();
+                    }
+                    Some(Step::NoReply) => {
+                        steps.next();
+                    }
+                    _ => ws.send(Message::Text(ack)).await.unwrap(),
+                }
+                player = Some(tokio::spawn(play(steps.collect(), out_tx.clone())));
+            }
+            Some(step) = out_rx.recv() => match step {
+                Step::Send(text) => {
+                    if ws.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Step::Ping(payload) => {
+                    if ws.send(Message::Ping(payload.into_bytes())).await.is_err() {
+                        break;
+                    }
+                }
+                Step::Close => {
+                    let _ = ws.close(None).await;
+                    break;
+                }
+                _ => break,
+            },
+        }
+    }
+
+    if let Some(player) = player {
+        player.abort();
+    }
+}
+
+/// Plays `script`, handing the steps that touch the connection to `serve`.
+async fn play(script: Vec<Step>, out: mpsc::UnboundedSender<Step>) {
+    for step in script {
+        match step {
+            Step::Reply(_) | Step::NoReply => {}
+            Step::Sleep(duration) => tokio::time::sleep(duration).await,
+            step => {
+                let last = matches!(step, Step::Close | Step::Drop);
+                if out.send(step).is_err() || last {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn next_text(
+    ws: &mut WebSocketStream<TcpStream>,
+    log: &Arc<Mutex<Vec<String>>>,
+) -> Option<String> {
+    while let Some(Ok(msg)) = ws.next().await {
+        match msg {
+            Message::Text(text) => {
+                log.lock().unwrap().push(text.clone());
+                return Some(text);
+            }
+            Message::Pong(payload) => {
+                let payload = String::from_utf8_lossy(&payload);
+                log.lock().unwrap().push(format!("pong:{payload}"));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The id of a request, `id` on Binance and `req_id` on Kraken and Bybit.
+fn request_id(request: &Value) -> &Value {
+    if request["id"].is_null() {
+        &request["req_id"]
+    } else {
+        &request["id"]
+    }
+}
+
+fn ack(protocol: Protocol, text: &str) -> Option<String> {
+    let request = serde_json::from_str::<Value>(text).ok()?;
+    let bitstamp_ack = |event: &str| {
+        json!({
+            "event": event,
+            "channel": request["data"]["channel"],
+            "data": {},
+        })
+        .to_string()
+    };
+    match protocol {
+        Protocol::Binance
+            if request["method"] == "SUBSCRIBE" || request["method"] == "UNSUBSCRIBE" =>
+        {
+            Some(json!({"result": null, "id": request["id"]}).to_string())
+        }
+        Protocol::Bitstamp if request["event"] == "bts:subscribe" => {
+            Some(bitstamp_ack("bts:subscription_succeeded"))
+        }
+        Protocol::Bitstamp if request["event"] == "bts:unsubscribe" => {
+            Some(bitstamp_ack("bts:unsubscription_succeeded"))
+        }
+        Protocol::Coinbase if request["type"] == "subscribe" => {
+            let channels = request["channels"]
+                .as_array()?
+                .iter()
+                .map(|name| json!({"name": name, "product_ids": request["product_ids"]}))
+                .collect::<Vec<_>>();
+            Some(json!({"type": "subscriptions", "channels": channels}).to_string())
+        }
+        Protocol::Coinbase if request["type"] == "unsubscribe" => {
+            Some(json!({"type": "subscriptions", "channels": []}).to_string())
+        }
+        Protocol::Kraken
+            if request["method"] == "subscribe" || request["method"] == "unsubscribe" =>
+        {
+            Some(
+                json!({
+                    "method": request["method"],
+                    "req_id": request["req_id"],
+                    "result": {"channel": request["params"]["channel"], "symbol": request["params"]["symbol"][0]},
+                    "success": true,
+                })
+                .to_string(),
+            )
+        }
+        Protocol::Okx if request["op"] == "subscribe" || request["op"] == "unsubscribe" => {
+            Some(json!({"event": request["op"], "arg": request["args"][0], "connId": "a4d3ae55"}).to_string())
+        }
+        Protocol::Bybit if request["op"] == "subscribe" || request["op"] == "unsubscribe" => {
+            Some(
+                json!({
+                    "success": true,
+                    "ret_msg": "",
+                    "conn_id": "cejreaspqfh3sjdnldmg",
+                    "req_id": request["req_id"],
+                    "op": request["op"],
+                })
+                .to_string(),
+            )
+        }
+        Protocol::Generic => Some(json!({"event": "subscribed"}).to_string()),
+        _ => None,
+    }
+}
+
+/// A Binance partial depth frame; levels are `(price, amount)`.
+pub fn binance_depth(last_update_id: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+    json!({"lastUpdateId": last_update_id, "bids": bids, "asks": asks}).to_string()
+}
+
+/// A Binance futures partial depth frame (`depthUpdate`) of `symbol`; levels
+/// are `(price, quantity)`.
+pub fn binance_futures_depth(
+    symbol: &str,
+    final_update_id: u64,
+    bids: &[(&str, &str)],
+    asks: &[(&str, &str)],
+) -> String {
+    json!({
+        "e": "depthUpdate",
+        "E": 1697700000123u64,
+        "T": 1697700000120u64,
+        "s": symbol.to_uppercase(),
+        "U": final_update_id - 5,
+        "u": final_update_id,
+        "pu": final_update_id - 6,
+        "b": bids,
+        "a": asks,
+    })
+    .to_string()
+}
+
+/// `data` wrapped as a frame of Binance's combined stream endpoint.
+pub fn binance_combined(stream: &str, data: &str) -> String {
+    let data = serde_json::from_str::<Value>(data).unwrap();
+    json!({"stream": stream, "data": data}).to_string()
+}
+
+/// A Bitstamp `data` order book frame for `channel`; levels are `(price, amount)`.
+pub fn bitstamp_book(channel: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+    json!({
+        "data": {
+            "timestamp": "1697700000",
+            "microtimestamp": "1697700000000000",
+            "bids": bids,
+            "asks": asks,
+        },
+        "channel": channel,
+        "event": "data",
+    })
+    .to_string()
+}
+
+/// A Bitstamp REST order book (`/api/v2/order_book/`); levels are `(price, amount)`.
+pub fn bitstamp_order_book(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+    json!({
+        "timestamp": "1697700000",
+        "microtimestamp": "1697700000000000",
+        "bids": bids,
+        "asks": asks,
+    })
+    .to_string()
+}
+
+/// A Coinbase level2 `snapshot` of `product_id`; levels are `(price, size)`.
+pub fn coinbase_snapshot(product_id: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> String {
+    json!({"type": "snapshot", "product_id": product_id, "bids": bids, "asks": asks}).to_string()
+}
+
+/// A Coinbase `l2update` of `product_id`; changes are `(side, price, size)`.
+pub fn coinbase_update(product_id: &str, time: &str, changes: &[(&str, &str, &str)]) -> String {
+    json!({"type": "l2update", "product_id": product_id, "time": time, "changes": changes})
+        .to_string()
+}
+
+/// A Kraken `book` message of `kind` (`snapshot` or `update`); levels are
+/// `(price, qty)` and kept as written, which the checksum is computed from.
+pub fn kraken_book(
+    kind: &str,
+    symbol: &str,
+    bids: &[(&str, &str)],
+    asks: &[(&str, &str)],
+    checksum: u32,
+) -> String {
+    let levels = |side: &[(&str, &str)]| {
+        side.iter()
+            .map(|(price, qty)| format!(r#"{{"price":{price},"qty":{qty}}}"#))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        r#"{{"channel":"book","type":"{kind}","data":[{{"symbol":"{symbol}","bids":[{}],"asks":[{}],"checksum":{checksum},"timestamp":"2023-10-19T07:20:00.000000Z"}}]}}"#,
+        levels(bids),
+        levels(asks),
+    )
+}
+
+/// Kraken's checksum of a book given best first, as documented: the top 10
+/// asks and then bids, each price and qty without `.` and leading zeros.
+pub fn kraken_checksum(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> u32 {
+    let mut text = String::new();
+    for (price, qty) in asks.iter().take(10).chain(bids.iter().take(10)) {
+        for number in [price, qty] {
+            text.push_str(number.replace('.', "").trim_start_matches('0'));
+        }
+    }
+    crc32fast::hash(text.as_bytes())
+}
+
+/// An OKX `books` push of `action` (`snapshot` or `update`); levels are
+/// `(price, size)`.
+pub fn okx_book(
+    action: &str,
+    inst_id: &str,
+    bids: &[(&str, &str)],
+    asks: &[(&str, &str)],
+    checksum: i32,
+    prev_seq_id: i64,
+    seq_id: i64,
+) -> String {
+    let levels = |side: &[(&str, &str)]| {
+        side.iter()
+            .map(|(price, size)| json!([price, size, "0", "1"]))
+            .collect::<Vec<_>>()
+    };
+    json!({
+        "arg": {"channel": "books", "instId": inst_id},
+        "action": action,
+        "data": [{
+            "asks": levels(asks),
+            "bids": levels(bids),
+            "ts": "1697700000000",
+            "checksum": checksum,
+            "prevSeqId": prev_seq_id,
+            "seqId": seq_id,
+        }],
+    })
+    .to_string()
+}
+
+/// OKX's checksum of a book given best first, as documented: the top 25 bids
+/// and asks interleaved as `bid:size:ask:size:...`, as a signed CRC32.
+pub fn okx_checksum(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> i32 {
+    let mut fields = Vec::new();
+    for i in 0..25 {
+        for side in [bids, asks] {
+            if let Some((price, size)) = side.get(i) {
+                fields.push(*price);
+                fields.push(*size);
+            }
+        }
+    }
+    crc32fast::hash(fields.join(":").as_bytes()) as i32
+}
+
+/// A Bybit `orderbook.50` push of `kind` (`snapshot` or `delta`); levels are
+/// `(price, size)`.
+pub fn bybit_book(
+    kind: &str,
+    symbol: &str,
+    bids: &[(&str, &str)],
+    asks: &[(&str, &str)],
+    update_id: u64,
+) -> String {
+    json!({
+        "topic": format!("orderbook.50.{symbol}"),
+        "type": kind,
+        "ts": 1697700000000u64,
+        "data": {"s": symbol, "b": bids, "a": asks, "u": update_id, "seq": update_id + 1000},
+        "cts": 1697699999999u64,
+    })
+    .to_string()
+}
+
+/// A book of `exchange` with one level of amount 1 on each side.
+pub fn book(exchange: Exchange, bid: f64, ask: f64) -> OrderBook {
+    let level = |price| Quote { price, amount: 1.0 };
+    OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids: vec![level(bid)],
+        asks: vec![level(ask)],
+        degraded: false,
+    }
+}
+
+/// Runs `manager` for `venues` and a gRPC server on an OS-assigned port,
+/// returning the order book sender to feed and the server's URL.
+pub async fn start_aggregator(
+    venues: &[Exchange],
+    best_of: usize,
+) -> (broadcast::Sender<OrderBook>, String) {
+    start_aggregator_with(venues, best_of, GrpcConfig::default()).await
+}
+
+/// [`start_aggregator`] with the gRPC server configured by `config`.
+pub async fn start_aggregator_with(
+    venues: &[Exchange],
+    best_of: usize,
+    config: GrpcConfig,
+) -> (broadcast::Sender<OrderBook>, String) {
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, _) = broadcast::channel::<Summary>(32);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let url = format!("{scheme}://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve_grpc_with(listener, s_tx.clone(), config));
+    tokio::spawn(manager(venues.to_vec(), rx, s_tx, best_of));
+
+    (tx, url)
+}
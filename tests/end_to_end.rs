@@ -0,0 +1,188 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use algo_challenge::exchange::bitstamp_client::BitstampClient;
+use algo_challenge::types::{Exchange, OrderbookAggregatorClient, Summary, SummaryRequest};
+use common::{binance_depth, bitstamp_book, start_aggregator, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn summary_merges_both_venues_over_grpc() {
+    let (tx, url) = start_aggregator(&[Exchange::Bitstamp, Exchange::Binance], BEST_OF).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+    let mut summaries = client
+        .book_summary(SummaryRequest {
+            venue_names: true,
+            ..SummaryRequest::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![Step::Send(binance_depth(
+            1,
+            &[("100.0", "1.0"), ("99.0", "2.0")],
+            &[("101.5", "1.0"), ("102.0", "3.0")],
+        ))]],
+    )
+    .await;
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![Step::Send(bitstamp_book(
+            "order_book_btcusd",
+            &[("100.5", "0.5"), ("98.0", "4.0")],
+            &[("101.0", "0.25"), ("103.0", "1.0")],
+        ))]],
+    )
+    .await;
+
+    let mut binance_client = BinanceClient::connect(&binance.url("/ws")).await.unwrap();
+    let mut binance_books = binance_client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    // Wait for Binance's book first so that the summary below is the first one
+    // containing both venues.
+    let binance_book = timeout(WAIT, binance_books.next()).await.unwrap().unwrap();
+    tx.send(binance_book).unwrap();
+
+    let mut bitstamp_client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    let mut bitstamp_books = bitstamp_client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let bitstamp_book = timeout(WAIT, bitstamp_books.next()).await.unwrap().unwrap();
+    tx.send(bitstamp_book).unwrap();
+
+    let summary: Summary = timeout(WAIT, summaries.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let bids = summary
+        .bids
+        .iter()
+        .map(|l| (l.exchange.as_str(), l.price, l.amount))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bids,
+        vec![
+            ("Bitstamp", 100.5, 0.5),
+            ("Binance", 100.0, 1.0),
+            ("Binance", 99.0, 2.0),
+            ("Bitstamp", 98.0, 4.0),
+        ]
+    );
+    let asks = summary
+        .asks
+        .iter()
+        .map(|l| (l.exchange.as_str(), l.price, l.amount))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        asks,
+        vec![
+            ("Bitstamp", 101.0, 0.25),
+            ("Binance", 101.5, 1.0),
+            ("Binance", 102.0, 3.0),
+            ("Bitstamp", 103.0, 1.0),
+        ]
+    );
+    assert_eq!(summary.spread, 0.5);
+
+    assert!(binance.received()[0].contains("btcusdt@depth20@100ms"));
+    assert!(bitstamp.received()[0].contains("order_book_btcusd"));
+}
+
+#[tokio::test]
+async fn malformed_frames_are_skipped() {
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![
+            Step::Send("not json".to_string()),
+            Step::Send(r#"{"lastUpdateId":1,"bids":[["1.0"]],"asks":[]}"#.to_string()),
+            Step::Send(binance_depth(2, &[("abc", "1.0")], &[("101.0", "1.0")])),
+            Step::Send(binance_depth(3, &[("100.0", "1.0")], &[("101.0", "1.0")])),
+        ]],
+    )
+    .await;
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![
+            Step::Send(r#"{"event":"data","data":{}}"#.to_string()),
+            Step::Send(bitstamp_book("order_book_btcusd", &[], &[("1e", "1")])),
+            Step::Send(bitstamp_book(
+                "order_book_btcusd",
+                &[("100.0", "1.0")],
+                &[("101.0", "1.0")],
+            )),
+        ]],
+    )
+    .await;
+
+    let mut binance_client = BinanceClient::connect(&binance.url("/ws")).await.unwrap();
+    let mut books = binance_client
+        .subscribe_orderbook("btcusdt", PriceLevels::L5, Speed::S1000, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let book = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(book.last_updated, "3");
+
+    let mut bitstamp_client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    let mut books = bitstamp_client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let book = timeout(WAIT, books.next()).await.unwrap().unwrap();
+    assert_eq!(book.bids[0].price, 100.0);
+}
+
+#[tokio::test]
+async fn book_stream_ends_when_exchange_disconnects() {
+    let book = binance_depth(1, &[("100.0", "1.0")], &[("101.0", "1.0")]);
+    let binance =
+        MockExchange::start(Protocol::Binance, vec![vec![Step::Send(book), Step::Drop]]).await;
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![
+            Step::Send(bitstamp_book(
+                "order_book_btcusd",
+                &[("100.0", "1.0")],
+                &[("101.0", "1.0")],
+            )),
+            Step::Close,
+        ]],
+    )
+    .await;
+
+    let mut binance_client = BinanceClient::connect(&binance.url("/ws")).await.unwrap();
+    let mut books = binance_client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_some());
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_none());
+
+    let mut bitstamp_client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    let mut books = bitstamp_client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_some());
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_none());
+}
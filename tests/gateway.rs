@@ -0,0 +1,277 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+
+use algo_challenge::auth::{Auth, AuthConfig, KeyConfig};
+use algo_challenge::gateway::{serve_gateway_with, JsonSummary};
+use algo_challenge::grpc::manager;
+use algo_challenge::types::{
+    Exchange, OrderBook, OrderbookAggregatorClient, Quote, Selection, Summary, SummaryRequest,
+};
+use common::start_aggregator;
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+const VENUES: [Exchange; 3] = [Exchange::Bitstamp, Exchange::Binance, Exchange::Kraken];
+
+fn book(exchange: Exchange, bids: &[f64], asks: &[f64]) -> OrderBook {
+    let quotes = |prices: &[f64]| {
+        prices
+            .iter()
+            .map(|&price| Quote { price, amount: 1.0 })
+            .collect()
+    };
+    OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids: quotes(bids),
+        asks: quotes(asks),
+        degraded: false,
+    }
+}
+
+/// The books of `VENUES`, whose merged summary has Binance best on both sides.
+fn books() -> Vec<OrderBook> {
+    vec![
+        book(Exchange::Bitstamp, &[100.0, 99.0], &[103.0, 104.0]),
+        book(Exchange::Binance, &[101.0, 98.0], &[102.0, 105.0]),
+        book(Exchange::Kraken, &[97.0], &[106.0]),
+    ]
+}
+
+/// Runs `manager` for `VENUES` and the gateway on an OS-assigned port,
+/// returning the order book sender to feed and the gateway's address.
+async fn start_gateway() -> (broadcast::Sender<OrderBook>, String) {
+    start_gateway_with(None).await
+}
+
+/// [`start_gateway`] requiring the keys of `auth`, if any.
+async fn start_gateway_with(auth: Option<Auth>) -> (broadcast::Sender<OrderBook>, String) {
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, _) = broadcast::channel::<Summary>(32);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(serve_gateway_with(listener, s_tx.clone(), auth));
+    tokio::spawn(manager(VENUES.to_vec(), rx, s_tx, BEST_OF));
+
+    (tx, addr)
+}
+
+fn send_books(tx: &broadcast::Sender<OrderBook>) {
+    for ob in books() {
+        tx.send(ob).unwrap();
+    }
+}
+
+fn prices(levels: &[algo_challenge::gateway::JsonLevel]) -> Vec<(&str, f64)> {
+    levels
+        .iter()
+        .map(|level| (level.exchange.as_str(), level.price))
+        .collect()
+}
+
+#[test]
+fn selections_cut_summaries_down() {
+    let summary = Summary::merge_all(books(), BEST_OF);
+
+    let all = Selection::default().apply(summary.clone());
+    assert_eq!(all, summary);
+
+    let request = SummaryRequest {
+        venue_names: true,
+        depth: 1,
+        venues: vec!["bitstamp".to_string(), "KRAKEN".to_string()],
+    };
+    let selection = Selection::from_request(&request).unwrap();
+    assert_eq!(selection.venues, vec![Exchange::Bitstamp, Exchange::Kraken]);
+    let picked = selection.apply(summary);
+    assert_eq!(picked.bids.len(), 1);
+    assert_eq!(
+        (picked.bids[0].exchange.as_str(), picked.bids[0].price),
+        ("Bitstamp", 100.0)
+    );
+    assert_eq!(picked.asks[0].price, 103.0);
+    assert_eq!(picked.spread, 3.0);
+
+    let unknown = SummaryRequest {
+        venues: vec!["nowhere".to_string()],
+        ..SummaryRequest::default()
+    };
+    assert!(Selection::from_request(&unknown).is_err());
+}
+
+#[tokio::test]
+async fn websocket_clients_get_json_summaries() {
+    let (tx, addr) = start_gateway().await;
+    let url = format!("ws://{addr}/ws?depth=1&venues=bitstamp,kraken");
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // The subscription is made on upgrade, before the books are sent.
+    send_books(&tx);
+    let message = timeout(WAIT, ws.next()).await.unwrap().unwrap().unwrap();
+    let Message::Text(text) = message else {
+        panic!("not text: {message:?}");
+    };
+    let summary = serde_json::from_str::<JsonSummary>(&text).unwrap();
+    assert_eq!(prices(&summary.bids), vec![("Bitstamp", 100.0)]);
+    assert_eq!(prices(&summary.asks), vec![("Bitstamp", 103.0)]);
+    assert_eq!(summary.spread, 3.0);
+    assert_eq!(summary.bids[0].venue, 1);
+}
+
+#[tokio::test]
+async fn sse_clients_get_json_events() {
+    let (tx, addr) = start_gateway().await;
+    let mut response = reqwest::get(format!("http://{addr}/sse?depth=2"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+
+    send_books(&tx);
+    let mut text = String::new();
+    let event = timeout(WAIT, async {
+        loop {
+            let chunk = response.chunk().await.unwrap().unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            if let Some(end) = text.find("\n\n") {
+                break text[..end].to_string();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    let data = event.strip_prefix("data:").expect(&event);
+    let summary = serde_json::from_str::<JsonSummary>(data).unwrap();
+    assert_eq!(
+        prices(&summary.bids),
+        vec![("Binance", 101.0), ("Bitstamp", 100.0)]
+    );
+    assert_eq!(
+        prices(&summary.asks),
+        vec![("Binance", 102.0), ("Bitstamp", 103.0)]
+    );
+    assert_eq!(summary.spread, 1.0);
+}
+
+#[tokio::test]
+async fn unknown_venues_are_rejected() {
+    let (_tx, addr) = start_gateway().await;
+    let response = reqwest::get(format!("http://{addr}/sse?venues=nowhere"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.text().await.unwrap(), "unknown venue: nowhere");
+
+    let venues = reqwest::get(format!("http://{addr}/venues"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let venues = serde_json::from_str::<serde_json::Value>(&venues).unwrap();
+    assert_eq!(venues[3], serde_json::json!({"id": 3, "name": "Kraken"}));
+}
+
+#[tokio::test]
+async fn grpc_clients_select_depth_and_venues() {
+    let (tx, url) = start_aggregator(&VENUES, BEST_OF).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+
+    let status = client
+        .book_summary(SummaryRequest {
+            venues: vec!["nowhere".to_string()],
+            ..SummaryRequest::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    let mut summaries = client
+        .book_summary(SummaryRequest {
+            venue_names: false,
+            depth: 1,
+            venues: vec!["binance".to_string()],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    send_books(&tx);
+    let summary = timeout(WAIT, summaries.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let levels = summary
+        .bids
+        .iter()
+        .chain(&summary.asks)
+        .map(|level| (level.venue, level.price))
+        .collect::<Vec<_>>();
+    assert_eq!(levels, vec![(0, 101.0), (0, 102.0)]);
+}
+
+#[tokio::test]
+async fn keys_limit_what_the_gateway_serves() {
+    let config = AuthConfig {
+        keys: vec![KeyConfig {
+            key: "desk".to_string(),
+            venues: vec!["bitstamp".to_string()],
+            instruments: vec![],
+            max_streams: Some(1),
+        }],
+    };
+    let auth = Auth::new(&config, &[]).unwrap();
+    let (tx, addr) = start_gateway_with(Some(auth)).await;
+    let status = |path: &str| {
+        let url = format!("http://{addr}{path}");
+        async move { reqwest::get(url).await.unwrap().status() }
+    };
+
+    assert_eq!(status("/sse").await, 401);
+    assert_eq!(status("/venues?key=guess").await, 401);
+    assert_eq!(status("/sse?key=desk&venues=binance").await, 403);
+    let venues = reqwest::Client::new()
+        .get(format!("http://{addr}/venues"))
+        .header("authorization", "Bearer desk")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(venues, r#"[{"id":1,"name":"Bitstamp"}]"#);
+
+    let url = format!("ws://{addr}/ws?key=desk");
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    // The key's one stream is taken.
+    assert_eq!(status("/sse?key=desk").await, 429);
+
+    send_books(&tx);
+    let message = timeout(WAIT, ws.next()).await.unwrap().unwrap().unwrap();
+    let summary = serde_json::from_str::<JsonSummary>(message.to_text().unwrap()).unwrap();
+    assert_eq!(
+        prices(&summary.bids),
+        vec![("Bitstamp", 100.0), ("Bitstamp", 99.0)]
+    );
+
+    // Closing the socket frees the stream.
+    ws.close(None).await.unwrap();
+    timeout(WAIT, async {
+        while status("/sse?key=desk").await != 200 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
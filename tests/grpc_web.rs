@@ -0,0 +1,165 @@
+mod common;
+
+use std::time::Duration;
+
+use prost::Message;
+use tokio::time::timeout;
+
+use algo_challenge::grpc::GrpcConfig;
+use algo_challenge::types::{
+    Empty, Exchange, OrderbookAggregatorClient, Summary, SummaryRequest, VenueList,
+};
+use common::{book, start_aggregator_with};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+const ORIGIN: &str = "https://dashboard.example.com";
+const GRPC_WEB: &str = "application/grpc-web+proto";
+
+fn config() -> GrpcConfig {
+    GrpcConfig {
+        grpc_web: true,
+        cors_origins: vec![ORIGIN.to_string()],
+        ..GrpcConfig::default()
+    }
+}
+
+/// `message` as a gRPC-Web data frame.
+fn frame(message: &impl Message) -> Vec<u8> {
+    let body = message.encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend((body.len() as u32).to_be_bytes());
+    frame.extend(body);
+    frame
+}
+
+/// The complete frames at the start of `body`, as their flags and payloads.
+fn frames(body: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut rest = body;
+    while rest.len() >= 5 {
+        let len = u32::from_be_bytes(rest[1..5].try_into().unwrap()) as usize;
+        if rest.len() < 5 + len {
+            break;
+        }
+        frames.push((rest[0], rest[5..5 + len].to_vec()));
+        rest = &rest[5 + len..];
+    }
+    frames
+}
+
+fn call(url: &str, method: &str, body: Vec<u8>) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .post(format!("{url}/orderbook.OrderbookAggregator/{method}"))
+        .header("content-type", GRPC_WEB)
+        .header("x-grpc-web", "1")
+        .header("origin", ORIGIN)
+        .body(body)
+}
+
+#[tokio::test]
+async fn unary_calls_work_over_http1() {
+    let (_tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config()).await;
+    let response = call(&url, "Venues", frame(&Empty {})).send().await.unwrap();
+    assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], GRPC_WEB);
+    assert_eq!(response.headers()["access-control-allow-origin"], ORIGIN);
+    assert!(response.headers()["access-control-expose-headers"]
+        .to_str()
+        .unwrap()
+        .contains("grpc-status"));
+
+    let body = response.bytes().await.unwrap();
+    let frames = frames(&body);
+    assert_eq!(frames.len(), 2);
+    let venues = VenueList::decode(frames[0].1.as_slice()).unwrap();
+    assert_eq!(venues.venues[1].name, "Bitstamp");
+    // The status comes last, as a trailer frame.
+    assert_eq!(frames[1].0, 0x80);
+    assert!(String::from_utf8_lossy(&frames[1].1).contains("grpc-status:0"));
+}
+
+#[tokio::test]
+async fn book_summaries_stream_over_grpc_web() {
+    let (tx, url) = start_aggregator_with(&[Exchange::Bitstamp], BEST_OF, config()).await;
+    let request = SummaryRequest {
+        venue_names: true,
+        ..SummaryRequest::default()
+    };
+    let mut response = call(&url, "BookSummary", frame(&request))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    tx.send(book(Exchange::Bitstamp, 100.0, 101.0)).unwrap();
+    let mut body = Vec::new();
+    let summary = timeout(WAIT, async {
+        loop {
+            body.extend(response.chunk().await.unwrap().unwrap());
+            if let Some((0, message)) = frames(&body).into_iter().next() {
+                break Summary::decode(message.as_slice()).unwrap();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(summary.spread, 1.0);
+    assert_eq!(summary.bids[0].exchange, "Bitstamp");
+}
+
+#[tokio::test]
+async fn preflights_only_allow_configured_origins() {
+    let (_tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config()).await;
+    let preflight = |origin: &'static str| {
+        reqwest::Client::new()
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("{url}/orderbook.OrderbookAggregator/BookSummary"),
+            )
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type,x-grpc-web")
+            .send()
+    };
+
+    let allowed = preflight(ORIGIN).await.unwrap();
+    assert!(allowed.status().is_success());
+    let headers = allowed.headers();
+    assert_eq!(headers["access-control-allow-origin"], ORIGIN);
+    assert_eq!(headers["access-control-allow-methods"], "POST");
+    assert_eq!(
+        headers["access-control-allow-headers"],
+        "content-type,x-grpc-web"
+    );
+
+    let denied = preflight("https://elsewhere.example.com").await.unwrap();
+    assert!(!denied.headers().contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn native_grpc_clients_still_connect() {
+    let (tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config()).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+    let mut summaries = client
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    tx.send(book(Exchange::Binance, 100.0, 100.5)).unwrap();
+    let summary = timeout(WAIT, summaries.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.spread, 0.5);
+}
+
+#[tokio::test]
+async fn grpc_web_is_off_by_default() {
+    let (_tx, url) =
+        start_aggregator_with(&[Exchange::Binance], BEST_OF, GrpcConfig::default()).await;
+    // Without gRPC-Web the server only speaks HTTP/2.
+    assert!(call(&url, "Venues", frame(&Empty {})).send().await.is_err());
+}
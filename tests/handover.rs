@@ -0,0 +1,113 @@
+mod common;
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use algo_challenge::exchange::bitstamp_client::BitstampClient;
+use algo_challenge::streaming::{supervise, Session, Watchdog};
+use algo_challenge::types::OrderBook;
+use common::{binance_depth, bitstamp_book, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const WATCHDOG: Watchdog = Watchdog {
+    no_data_timeout: Duration::from_secs(2),
+    initial_backoff: Duration::from_millis(10),
+    max_backoff: Duration::from_millis(100),
+    rest_poll_interval: None,
+};
+
+fn bitstamp_bid(price: &str) -> Step {
+    Step::Send(bitstamp_book("order_book_btcusd", &[(price, "1.0")], &[]))
+}
+
+async fn next_bid(rx: &mut broadcast::Receiver<OrderBook>) -> f64 {
+    timeout(WAIT, rx.recv()).await.unwrap().unwrap().bids[0].price
+}
+
+#[tokio::test]
+async fn bitstamp_reconnect_requests_hand_over_without_a_gap() {
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![
+            vec![
+                bitstamp_bid("1.0"),
+                Step::Send(
+                    r#"{"event":"bts:request_reconnect","channel":"","data":""}"#.to_string(),
+                ),
+                // Still served while the next connection subscribes.
+                Step::Sleep(Duration::from_millis(20)),
+                bitstamp_bid("2.0"),
+                // Sent after the switch, when this connection is closed.
+                Step::Sleep(Duration::from_millis(500)),
+                bitstamp_bid("3.0"),
+            ],
+            vec![
+                Step::Sleep(Duration::from_millis(200)),
+                bitstamp_bid("10.0"),
+            ],
+        ],
+    )
+    .await;
+
+    let (tx, mut rx) = broadcast::channel(32);
+    let url = bitstamp.url("/");
+    tokio::spawn(async move {
+        supervise("Bitstamp btcusd", WATCHDOG, tx, || async {
+            let mut client = BitstampClient::connect(&url).await?;
+            let subscription = client.subscribe_orderbook("btcusd", BEST_OF).await?;
+            Ok(Session {
+                handover: Box::pin(client.handover_requested()),
+                client,
+                book_events: subscription.book_events,
+            })
+        })
+        .await
+    });
+
+    assert_eq!(next_bid(&mut rx).await, 1.0);
+    assert_eq!(next_bid(&mut rx).await, 2.0);
+    assert_eq!(next_bid(&mut rx).await, 10.0);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn binance_connections_are_rotated_before_they_expire() {
+    let book = |id: u64| Step::Send(binance_depth(id, &[("1.0", "1.0")], &[]));
+    let binance = MockExchange::start(Protocol::Binance, vec![vec![book(1)], vec![book(2)]]).await;
+
+    let (tx, mut rx) = broadcast::channel(32);
+    let url = binance.url("/ws");
+    tokio::spawn(async move {
+        supervise("Binance btcusdt", WATCHDOG, tx, || async {
+            let mut client = BinanceClient::connect(&url).await?;
+            client.max_connection_age = Duration::from_millis(200);
+            let subscription = client
+                .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+                .await?;
+            Ok(Session {
+                handover: Box::pin(client.handover_requested()),
+                client,
+                book_events: subscription.book_events,
+            })
+        })
+        .await
+    });
+
+    for id in ["1", "2"] {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(ob.last_updated, id);
+    }
+    // Rotated on schedule rather than by the watchdog.
+    let subscribes = binance
+        .received()
+        .iter()
+        .filter(|text| text.contains("\"SUBSCRIBE\""))
+        .count();
+    assert_eq!(subscribes, 2);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::json;
+use tokio::time::timeout;
+
+use algo_challenge::config::Config;
+use algo_challenge::exchange::json_adapter::{AdapterConfig, JsonAdapterClient};
+use algo_challenge::registry::VenueRegistry;
+use algo_challenge::types::{Exchange, Quote};
+use common::{MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 2;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn prices(levels: &[Quote]) -> Vec<(f64, f64)> {
+    levels.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+/// A venue shaped like Bitstamp: `data.bids`/`data.asks` string pairs.
+fn bitstamp_like_json(url: &str) -> serde_json::Value {
+    json!({
+        "url": url,
+        "subscribe": r#"{"event":"subscribe","channel":"book.{symbol}"}"#,
+        "bids": "/data/bids",
+        "asks": "/data/asks",
+        "timestamp": "/data/timestamp",
+    })
+}
+
+fn bitstamp_like(url: &str) -> AdapterConfig {
+    serde_json::from_value(bitstamp_like_json(url)).unwrap()
+}
+
+fn book_frame(bid: &str) -> String {
+    json!({
+        "channel": "book.btcusd",
+        "data": {
+            "timestamp": "1697700000",
+            "bids": [[bid, "1.5"], ["99.0", "2"], ["98.0", "3"]],
+            "asks": [["101.0", "0.5"]],
+        },
+    })
+    .to_string()
+}
+
+#[test]
+fn books_are_read_at_the_configured_pointers() {
+    let config = bitstamp_like("wss://example.com");
+    let exchange = Exchange::intern("Adapter-Parse");
+
+    let ob = config
+        .parse_order_book(exchange, &book_frame("100.0"), BEST_OF)
+        .unwrap();
+    assert_eq!(ob.exchange, exchange);
+    assert_eq!(ob.last_updated, "1697700000");
+    assert_eq!(prices(&ob.bids), vec![(100.0, 1.5), (99.0, 2.0)]);
+    assert_eq!(prices(&ob.asks), vec![(101.0, 0.5)]);
+    assert_eq!(ob.exchange.name(), "Adapter-Parse");
+
+    // Acknowledgements and levels that are not numbers yield no book.
+    let ack = r#"{"event":"subscribed","channel":"book.btcusd"}"#;
+    assert!(config.parse_order_book(exchange, ack, BEST_OF).is_none());
+    let bad = book_frame("n/a");
+    assert!(config.parse_order_book(exchange, &bad, BEST_OF).is_none());
+}
+
+#[test]
+fn symbols_are_escaped_in_subscribe_messages() {
+    let config = bitstamp_like("wss://example.com");
+    let message = config.subscribe_message(r#"btc"usd\"#).unwrap();
+    let message = serde_json::from_str::<serde_json::Value>(&message).unwrap();
+    assert_eq!(
+        message,
+        json!({"event": "subscribe", "channel": r#"book.btc"usd\"#})
+    );
+}
+
+#[test]
+fn levels_can_be_objects_of_numbers() {
+    let config: AdapterConfig = serde_json::from_value(json!({
+        "url": "wss://example.com/{symbol}",
+        "bids": "/b",
+        "asks": "/a",
+        "timestamp": "/ts",
+        "price": "/px",
+        "amount": "/qty",
+    }))
+    .unwrap();
+    assert_eq!(config.url("ETH-USD"), "wss://example.com/ETH-USD");
+    assert_eq!(config.subscribe_message("ETH-USD"), None);
+
+    let frame = r#"{"ts":1697700000123,"b":[{"px":10.5,"qty":3}],"a":[]}"#;
+    let ob = config
+        .parse_order_book(Exchange::intern("Adapter-Objects"), frame, BEST_OF)
+        .unwrap();
+    assert_eq!(ob.last_updated, "1697700000123");
+    assert_eq!(prices(&ob.bids), vec![(10.5, 3.0)]);
+    assert!(ob.asks.is_empty());
+}
+
+#[test]
+fn adapters_are_registered_from_the_configuration() {
+    let config: Config = serde_json::from_value(json!({
+        "adapters": {"Adapter-Config": bitstamp_like_json("wss://example.com")},
+        "venues": [{"venue": "adapter-config", "symbol": "btcusd"}],
+    }))
+    .unwrap();
+    assert_eq!(config.adapters.len(), 1);
+
+    let mut registry = VenueRegistry::builtin();
+    assert!(registry.lookup("adapter-config").is_err());
+    config.register_adapters(&mut registry);
+    let exchange = registry.lookup(&config.venues[0].venue).unwrap();
+    assert_eq!(exchange.to_string(), "Adapter-Config");
+}
+
+#[tokio::test]
+async fn clients_subscribe_with_the_template_and_stream_books() {
+    let venue = MockExchange::start(
+        Protocol::Generic,
+        vec![vec![
+            Step::Send(book_frame("100.0")),
+            Step::Send(book_frame("100.5")),
+        ]],
+    )
+    .await;
+
+    let exchange = Exchange::intern("Adapter-Client");
+    let config = bitstamp_like(&venue.url("/ws"));
+    let mut client = JsonAdapterClient::connect(exchange, config, "btcusd", None)
+        .await
+        .unwrap();
+    let mut books = client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for price in [100.0, 100.5] {
+        let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.exchange, exchange);
+        assert_eq!(ob.bids[0].price, price);
+    }
+    assert_eq!(
+        venue.received(),
+        vec![r#"{"event":"subscribe","channel":"book.btcusd"}"#.to_string()]
+    );
+}
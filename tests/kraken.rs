@@ -0,0 +1,171 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::error::Error;
+use algo_challenge::exchange::kraken_client::{BookDepth, Books, KrakenClient};
+use algo_challenge::types::{Exchange, Quote};
+use common::{kraken_book, kraken_checksum, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const BIDS: &[(&str, &str)] = &[("45283.5", "0.10000000"), ("45283.4", "1.54582015")];
+const ASKS: &[(&str, &str)] = &[("45285.2", "0.00100000"), ("45286.4", "1.54571953")];
+
+fn prices(levels: &[Quote]) -> Vec<(f64, f64)> {
+    levels.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+fn snapshot() -> String {
+    kraken_book(
+        "snapshot",
+        "BTC/USD",
+        BIDS,
+        ASKS,
+        kraken_checksum(BIDS, ASKS),
+    )
+}
+
+#[test]
+fn checksums_are_computed_from_the_levels_as_sent() {
+    assert_eq!(
+        kraken_checksum(&[("0.5", "10.0")], &[("1.05", "0.001")]),
+        crc32fast::hash(b"10515100")
+    );
+}
+
+#[test]
+fn updates_are_applied_and_checked() {
+    let mut books = Books::default();
+    let ob = books.apply(&snapshot(), BEST_OF).unwrap().unwrap();
+    assert_eq!(ob.exchange, Exchange::Kraken);
+    assert_eq!(
+        prices(&ob.bids),
+        vec![(45283.5, 0.1), (45283.4, 1.54582015)]
+    );
+
+    // Removes the best bid and adds a new best ask, which pushes the worst ask
+    // beyond the snapshot's depth of two levels.
+    let bids = &[("45283.4", "1.54582015")];
+    let asks = &[("45284.0", "2.00000000"), ("45285.2", "0.00100000")];
+    let update = kraken_book(
+        "update",
+        "BTC/USD",
+        &[("45283.5", "0.00000000")],
+        &[("45284.0", "2.00000000")],
+        kraken_checksum(bids, asks),
+    );
+    let ob = books.apply(&update, BEST_OF).unwrap().unwrap();
+    assert_eq!(prices(&ob.bids), vec![(45283.4, 1.54582015)]);
+    assert_eq!(prices(&ob.asks), vec![(45284.0, 2.0), (45285.2, 0.001)]);
+    assert_eq!(ob.last_updated, "2023-10-19T07:20:00.000000Z");
+}
+
+#[test]
+fn thin_books_grow_up_to_the_subscribed_depth() {
+    let mut books = Books::with_depth(BookDepth::D10);
+    books.apply(&snapshot(), BEST_OF).unwrap().unwrap();
+
+    // A third ask fits the subscribed depth of ten levels, and Kraken's
+    // checksum covers it.
+    let asks = &[
+        ("45285.2", "0.00100000"),
+        ("45286.4", "1.54571953"),
+        ("45287.0", "3.00000000"),
+    ];
+    let update = kraken_book(
+        "update",
+        "BTC/USD",
+        &[],
+        &[("45287.0", "3.00000000")],
+        kraken_checksum(BIDS, asks),
+    );
+    let ob = books.apply(&update, BEST_OF).unwrap().unwrap();
+    assert_eq!(ob.asks.len(), 3);
+}
+
+#[test]
+fn mismatching_checksums_drop_the_book() {
+    let mut books = Books::default();
+    books.apply(&snapshot(), BEST_OF).unwrap();
+
+    let update = kraken_book("update", "BTC/USD", &[("45283.6", "1.0")], &[], 42);
+    assert_eq!(
+        books.apply(&update, BEST_OF).err(),
+        Some(Error::ChecksumMismatch("BTC/USD".to_string()))
+    );
+    // Later updates are ignored until the next snapshot.
+    assert!(books.apply(&update, BEST_OF).unwrap().is_none());
+    assert!(books.apply(&snapshot(), BEST_OF).unwrap().is_some());
+}
+
+#[tokio::test]
+async fn mismatching_checksums_resubscribe() {
+    let kraken = MockExchange::start(
+        Protocol::Kraken,
+        vec![vec![
+            Step::Send(snapshot()),
+            Step::Send(kraken_book(
+                "update",
+                "BTC/USD",
+                &[("45283.6", "1.0")],
+                &[],
+                42,
+            )),
+            // Answers the resubscription.
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Send(snapshot()),
+        ]],
+    )
+    .await;
+
+    let mut client = KrakenClient::connect(&kraken.url("/v2")).await.unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTC/USD", BookDepth::D10, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for _ in 0..2 {
+        let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.bids[0].price, 45283.5);
+    }
+
+    let methods = kraken
+        .received()
+        .iter()
+        .map(|text| serde_json::from_str::<Value>(text).unwrap())
+        .map(|request| {
+            assert_eq!(request["params"]["symbol"][0], "BTC/USD");
+            assert_eq!(request["params"]["depth"], 10);
+            request["method"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(methods, vec!["subscribe", "unsubscribe", "subscribe"]);
+}
+
+#[tokio::test]
+async fn unsupported_pairs_are_typed_errors() {
+    let kraken = MockExchange::start(
+        Protocol::Kraken,
+        vec![vec![Step::Reply(
+            r#"{"error":"Currency pair not supported BTC/XYZ","method":"subscribe","req_id":{id},"success":false}"#
+                .to_string(),
+        )]],
+    )
+    .await;
+
+    let mut client = KrakenClient::connect(&kraken.url("/v2")).await.unwrap();
+    let result = client
+        .subscribe_orderbook("BTC/XYZ", BookDepth::D10, BEST_OF)
+        .await;
+    assert_eq!(
+        result.err(),
+        Some(Error::UnknownSymbol("BTC/XYZ".to_string()))
+    );
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+
+use algo_challenge::grpc::manager_with;
+use algo_challenge::types::{Exchange, OrderBook, Summary};
+use common::book;
+
+const BEST_OF: usize = 10;
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// The venue ids of the summary's bids.
+fn bid_venues(summary: &Summary) -> Vec<u32> {
+    summary.bids.iter().map(|level| level.venue).collect()
+}
+
+fn id(exchange: Exchange) -> u32 {
+    u32::from(exchange.id())
+}
+
+#[tokio::test]
+async fn venues_without_fresh_books_are_left_out() {
+    tokio::time::pause();
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, mut s_rx) = broadcast::channel::<Summary>(32);
+    let venues = vec![Exchange::Binance, Exchange::Kraken];
+    tokio::spawn(manager_with(venues, rx, s_tx, BEST_OF, STALE_AFTER));
+
+    // Kraken never sends a book, e.g. because its subscription was rejected:
+    // the first summary waits for it until its book would be stale.
+    tx.send(book(Exchange::Binance, 100.0, 101.0)).unwrap();
+    assert!(timeout(STALE_AFTER / 2, s_rx.recv()).await.is_err());
+    tx.send(book(Exchange::Binance, 100.0, 101.0)).unwrap();
+    let summary = s_rx.recv().await.unwrap();
+    assert_eq!(bid_venues(&summary), [id(Exchange::Binance)]);
+    assert_eq!(summary.degraded, ["Kraken"]);
+
+    tx.send(book(Exchange::Kraken, 99.0, 102.0)).unwrap();
+    let summary = s_rx.recv().await.unwrap();
+    assert_eq!(
+        bid_venues(&summary),
+        [id(Exchange::Binance), id(Exchange::Kraken)]
+    );
+    assert!(summary.degraded.is_empty());
+
+    // Only Binance keeps updating, so Kraken's book goes stale.
+    sleep(STALE_AFTER / 2).await;
+    tx.send(book(Exchange::Binance, 100.5, 101.0)).unwrap();
+    assert_eq!(s_rx.recv().await.unwrap().bids.len(), 2);
+    let summary = s_rx.recv().await.unwrap();
+    assert_eq!(bid_venues(&summary), [id(Exchange::Binance)]);
+    assert_eq!(summary.degraded, ["Kraken"]);
+    assert_eq!(summary.spread, 0.5);
+}
+
+#[tokio::test]
+async fn the_first_summary_waits_for_every_venue() {
+    tokio::time::pause();
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, mut s_rx) = broadcast::channel::<Summary>(32);
+    let venues = vec![Exchange::Binance, Exchange::Kraken];
+    tokio::spawn(manager_with(venues, rx, s_tx, BEST_OF, STALE_AFTER));
+
+    tx.send(book(Exchange::Binance, 100.0, 101.0)).unwrap();
+    sleep(Duration::from_secs(1)).await;
+    tx.send(book(Exchange::Kraken, 99.0, 102.0)).unwrap();
+    let summary = timeout(Duration::from_secs(1), s_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.bids.len(), 2);
+    assert!(summary.degraded.is_empty());
+}
+
+#[tokio::test]
+async fn books_can_go_stale_at_once() {
+    tokio::time::pause();
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, mut s_rx) = broadcast::channel::<Summary>(32);
+    let manager = tokio::spawn(manager_with(
+        vec![Exchange::Binance],
+        rx,
+        s_tx,
+        BEST_OF,
+        Duration::ZERO,
+    ));
+
+    tx.send(book(Exchange::Binance, 100.0, 101.0)).unwrap();
+    assert!(timeout(Duration::from_secs(1), s_rx.recv()).await.is_ok());
+    assert!(!manager.is_finished());
+}
@@ -0,0 +1,201 @@
+use proptest::prelude::*;
+
+use algo_challenge::types::{Exchange, Level, OrderBook, Quote, Summary};
+
+fn price() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        // A coarse grid so that both books regularly share prices.
+        8 => (1u32..40).prop_map(|p| f64::from(p) * 0.5),
+        2 => -1e9..1e9f64,
+        1 => Just(f64::NAN),
+        1 => Just(f64::INFINITY),
+        1 => Just(f64::NEG_INFINITY),
+    ]
+}
+
+fn amount() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        8 => (1u32..1000).prop_map(|a| f64::from(a) / 100.0),
+        1 => Just(0.0),
+        1 => Just(-1.0),
+        1 => Just(f64::NAN),
+    ]
+}
+
+fn levels() -> impl Strategy<Value = Vec<Quote>> {
+    prop::collection::vec((price(), amount()), 0..30).prop_map(|levels| {
+        levels
+            .into_iter()
+            .map(|(price, amount)| Quote { price, amount })
+            .collect()
+    })
+}
+
+fn book(exchange: Exchange) -> impl Strategy<Value = OrderBook> {
+    (levels(), levels()).prop_map(move |(bids, asks)| OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids,
+        asks,
+        degraded: false,
+    })
+}
+
+/// Whether `level` passes the check merging applies to quotes.
+fn is_valid(level: &Level) -> bool {
+    Quote {
+        price: level.price,
+        amount: level.amount,
+    }
+    .is_valid()
+}
+
+fn same(a: &Level, b: &Level) -> bool {
+    a.venue == b.venue
+        && a.price.to_bits() == b.price.to_bits()
+        && a.amount.to_bits() == b.amount.to_bits()
+}
+
+/// Checks one side of the merged book against the levels it was built from.
+/// `better(a, b)` is whether price `a` ranks strictly before price `b`.
+fn check_side(
+    output: &[Level],
+    input: &[Level],
+    best_of: usize,
+    better: fn(f64, f64) -> bool,
+) -> Result<(), TestCaseError> {
+    prop_assert!(output.len() <= best_of);
+    prop_assert!(output.iter().all(is_valid));
+    prop_assert!(output.windows(2).all(|w| !better(w[1].price, w[0].price)));
+
+    // Every output level comes from the input, as often as it appears there.
+    for level in output {
+        let in_output = output.iter().filter(|l| same(l, level)).count();
+        let in_input = input.iter().filter(|l| same(l, level)).count();
+        prop_assert!(in_output <= in_input, "{level:?} not in input");
+    }
+
+    let valid = input.iter().filter(|l| is_valid(l)).collect::<Vec<_>>();
+    match output.last() {
+        Some(worst) if output.len() == best_of => {
+            // Nothing priced better than the worst output level was dropped.
+            for level in valid.iter().filter(|l| better(l.price, worst.price)) {
+                prop_assert!(output.iter().any(|l| same(l, level)), "{level:?} dropped");
+            }
+        }
+        _ => prop_assert_eq!(output.len(), valid.len().min(best_of)),
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn merge_keeps_the_best_levels_in_order(
+        ob1 in book(Exchange::Bitstamp),
+        ob2 in book(Exchange::Binance),
+        best_of in 0usize..25,
+    ) {
+        let tagged = |side: fn(&OrderBook) -> &[Quote]| {
+            [&ob1, &ob2]
+                .into_iter()
+                .flat_map(|ob| side(ob).iter().map(|q| q.to_level(ob.exchange)))
+                .collect::<Vec<_>>()
+        };
+        let bids = tagged(|ob| &ob.bids);
+        let asks = tagged(|ob| &ob.asks);
+
+        let summary = Summary::merge(ob1, ob2, best_of);
+
+        check_side(&summary.bids, &bids, best_of, |a, b| a > b)?;
+        check_side(&summary.asks, &asks, best_of, |a, b| a < b)?;
+        match (summary.bids.first(), summary.asks.first()) {
+            (Some(bid), Some(ask)) => prop_assert_eq!(summary.spread, ask.price - bid.price),
+            _ => prop_assert_eq!(summary.spread, 0.0),
+        }
+    }
+}
+
+fn book_of(exchange: Exchange, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> OrderBook {
+    let levels = |side: &[(f64, f64)]| {
+        side.iter()
+            .map(|&(price, amount)| Quote { price, amount })
+            .collect()
+    };
+    OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids: levels(bids),
+        asks: levels(asks),
+        degraded: false,
+    }
+}
+
+#[test]
+fn merge_of_empty_books_is_empty() {
+    let summary = Summary::merge(
+        book_of(Exchange::Bitstamp, &[], &[]),
+        book_of(Exchange::Binance, &[], &[]),
+        10,
+    );
+    assert!(summary.bids.is_empty());
+    assert!(summary.asks.is_empty());
+    assert_eq!(summary.spread, 0.0);
+}
+
+#[test]
+fn merge_of_one_sided_books_has_no_spread() {
+    let summary = Summary::merge(
+        book_of(Exchange::Bitstamp, &[(100.0, 1.0)], &[]),
+        book_of(Exchange::Binance, &[(99.0, 1.0)], &[]),
+        10,
+    );
+    assert_eq!(summary.bids.len(), 2);
+    assert!(summary.asks.is_empty());
+    assert_eq!(summary.spread, 0.0);
+}
+
+#[test]
+fn merge_keeps_both_venues_at_the_same_price() {
+    let summary = Summary::merge(
+        book_of(Exchange::Bitstamp, &[(100.0, 1.0)], &[(101.0, 0.0)]),
+        book_of(
+            Exchange::Binance,
+            &[(100.0, 2.0)],
+            &[(101.0, f64::NAN), (102.0, 1.0)],
+        ),
+        10,
+    )
+    .with_venue_names();
+    let bids = summary
+        .bids
+        .iter()
+        .map(|l| (l.exchange.as_str(), l.price, l.amount))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bids,
+        vec![("Bitstamp", 100.0, 1.0), ("Binance", 100.0, 2.0)]
+    );
+    assert_eq!(summary.asks.len(), 1);
+    assert_eq!(summary.spread, 2.0);
+}
+
+#[test]
+fn merge_all_takes_any_number_of_venues() {
+    let summary = Summary::merge_all(
+        [
+            book_of(Exchange::Bitstamp, &[(100.0, 1.0)], &[(103.0, 1.0)]),
+            book_of(Exchange::Binance, &[(101.0, 1.0)], &[(104.0, 1.0)]),
+            book_of(Exchange::Coinbase, &[(100.0, 2.0)], &[(102.0, 1.0)]),
+        ],
+        2,
+    )
+    .with_venue_names();
+    let bids = summary
+        .bids
+        .iter()
+        .map(|l| (l.exchange.as_str(), l.price))
+        .collect::<Vec<_>>();
+    assert_eq!(bids, vec![("Binance", 101.0), ("Bitstamp", 100.0)]);
+    assert_eq!(summary.asks[0].exchange, "Coinbase");
+    assert_eq!(summary.spread, 1.0);
+}
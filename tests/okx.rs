@@ -0,0 +1,265 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::connection::PING_AFTER_IDLE;
+use algo_challenge::exchange::error::Error;
+use algo_challenge::exchange::okx_client::{BookChannel, Books, OkxClient};
+use algo_challenge::types::{Exchange, Quote};
+use common::{okx_book, okx_checksum, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const BIDS: &[(&str, &str)] = &[("34245.4", "0.51"), ("34245.3", "0.03")];
+const ASKS: &[(&str, &str)] = &[("34245.5", "1.2"), ("34246.0", "0.7")];
+
+fn prices(levels: &[Quote]) -> Vec<(f64, f64)> {
+    levels.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+fn snapshot() -> String {
+    okx_book(
+        "snapshot",
+        "BTC-USDT",
+        BIDS,
+        ASKS,
+        okx_checksum(BIDS, ASKS),
+        -1,
+        100,
+    )
+}
+
+#[test]
+fn checksums_interleave_bids_and_asks() {
+    assert_eq!(
+        okx_checksum(&[("3366.1", "7"), ("3366", "6")], &[("3366.8", "9")]),
+        crc32fast::hash(b"3366.1:7:3366.8:9:3366:6") as i32
+    );
+}
+
+#[test]
+fn updates_are_applied_and_checked() {
+    let mut books = Books::default();
+    let ob = books.apply(&snapshot(), BEST_OF).unwrap().unwrap();
+    assert_eq!(ob.exchange, Exchange::Okx);
+    assert_eq!(prices(&ob.bids), vec![(34245.4, 0.51), (34245.3, 0.03)]);
+
+    let bids = &[("34245.3", "0.03")];
+    let asks = &[("34245.5", "2.5"), ("34246.0", "0.7")];
+    let update = okx_book(
+        "update",
+        "BTC-USDT",
+        &[("34245.4", "0")],
+        &[("34245.5", "2.5")],
+        okx_checksum(bids, asks),
+        100,
+        101,
+    );
+    let ob = books.apply(&update, BEST_OF).unwrap().unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34245.3, 0.03)]);
+    assert_eq!(prices(&ob.asks), vec![(34245.5, 2.5), (34246.0, 0.7)]);
+    assert_eq!(ob.last_updated, "1697700000000");
+}
+
+#[test]
+fn sequence_gaps_and_mismatching_checksums_drop_the_book() {
+    let mut books = Books::default();
+    books.apply(&snapshot(), BEST_OF).unwrap();
+
+    let gap = okx_book(
+        "update",
+        "BTC-USDT",
+        &[],
+        &[],
+        okx_checksum(BIDS, ASKS),
+        99,
+        101,
+    );
+    assert_eq!(
+        books.apply(&gap, BEST_OF).err(),
+        Some(Error::SequenceGap("BTC-USDT".to_string()))
+    );
+    assert!(books.apply(&gap, BEST_OF).unwrap().is_none());
+
+    books.apply(&snapshot(), BEST_OF).unwrap();
+    let mismatch = okx_book("update", "BTC-USDT", &[("34245.6", "1")], &[], 42, 100, 101);
+    assert_eq!(
+        books.apply(&mismatch, BEST_OF).err(),
+        Some(Error::ChecksumMismatch("BTC-USDT".to_string()))
+    );
+}
+
+#[test]
+fn books5_pushes_are_snapshots() {
+    let mut books = Books::default();
+    let push = r#"{"arg":{"channel":"books5","instId":"BTC-USDT"},"data":[{"asks":[["34245.5","1.2","0","3"]],"bids":[["34245.4","0.51","0","2"]],"instId":"BTC-USDT","ts":"1697700000000","seqId":7}]}"#;
+    let ob = books.apply(push, BEST_OF).unwrap().unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34245.4, 0.51)]);
+    assert_eq!(prices(&ob.asks), vec![(34245.5, 1.2)]);
+}
+
+#[tokio::test]
+async fn sequence_gaps_resubscribe() {
+    let okx = MockExchange::start(
+        Protocol::Okx,
+        vec![vec![
+            Step::Send(snapshot()),
+            Step::Send(okx_book("update", "BTC-USDT", &[], &[], 0, 42, 43)),
+            // Answers the resubscription.
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Send(snapshot()),
+        ]],
+    )
+    .await;
+
+    let mut client = OkxClient::connect(&okx.url("/ws/v5/public")).await.unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTC-USDT", BookChannel::Books, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for _ in 0..2 {
+        let ob = timeout(WAIT, books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.bids[0].price, 34245.4);
+    }
+
+    let ops = okx
+        .received()
+        .iter()
+        .filter_map(|text| serde_json::from_str::<Value>(text).ok())
+        .map(|request| {
+            assert_eq!(request["args"][0]["channel"], "books");
+            assert_eq!(request["args"][0]["instId"], "BTC-USDT");
+            request["op"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ops, vec!["subscribe", "unsubscribe", "subscribe"]);
+}
+
+#[tokio::test]
+async fn both_book_channels_of_an_instrument_share_a_connection() {
+    let books5 = r#"{"arg":{"channel":"books5","instId":"BTC-USDT"},"data":[{"asks":[["34250.0","3","0","1"]],"bids":[["34240.0","2","0","1"]],"instId":"BTC-USDT","ts":"1697700000000","seqId":7}]}"#;
+    let bids = &[("34245.3", "0.03")];
+    let asks = &[("34245.5", "2.5"), ("34246.0", "0.7")];
+    let update = okx_book(
+        "update",
+        "BTC-USDT",
+        &[("34245.4", "0")],
+        &[("34245.5", "2.5")],
+        okx_checksum(bids, asks),
+        100,
+        101,
+    );
+    let okx = MockExchange::start(
+        Protocol::Okx,
+        vec![vec![
+            // Lets the second subscription register.
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Send(snapshot()),
+            Step::Send(books5.to_string()),
+            Step::Send(update),
+        ]],
+    )
+    .await;
+
+    let mut client = OkxClient::connect(&okx.url("/ws/v5/public")).await.unwrap();
+    let mut full = client
+        .subscribe_orderbook("BTC-USDT", BookChannel::Books, BEST_OF)
+        .await
+        .unwrap();
+    let mut top = client
+        .subscribe_orderbook("BTC-USDT", BookChannel::Books5, BEST_OF)
+        .await
+        .unwrap();
+
+    let ob = timeout(WAIT, full.book_events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34245.4, 0.51), (34245.3, 0.03)]);
+    let ob = timeout(WAIT, full.book_events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34245.3, 0.03)]);
+    assert_eq!(prices(&ob.asks), vec![(34245.5, 2.5), (34246.0, 0.7)]);
+    let ob = timeout(WAIT, top.book_events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(prices(&ob.bids), vec![(34240.0, 2.0)]);
+
+    client.unsubscribe(&top.handle).await.unwrap();
+    let requests = okx
+        .received()
+        .iter()
+        .filter_map(|text| serde_json::from_str::<Value>(text).ok())
+        .map(|request| {
+            assert_eq!(request["args"][0]["instId"], "BTC-USDT");
+            (
+                request["op"].as_str().unwrap().to_string(),
+                request["args"][0]["channel"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        requests,
+        [
+            ("subscribe".to_string(), "books".to_string()),
+            ("subscribe".to_string(), "books5".to_string()),
+            ("unsubscribe".to_string(), "books5".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unknown_instruments_are_typed_errors() {
+    let okx = MockExchange::start(
+        Protocol::Okx,
+        vec![vec![Step::Reply(
+            r#"{"event":"error","code":"60018","msg":"Wrong URL or channel:books,instId:BTC-XYZ doesn't exist. Please use the correct URL, channel and parameters referring to API document.","connId":"a4d3ae55"}"#
+                .to_string(),
+        )]],
+    )
+    .await;
+
+    let mut client = OkxClient::connect(&okx.url("/ws/v5/public")).await.unwrap();
+    let result = client
+        .subscribe_orderbook("BTC-XYZ", BookChannel::Books, BEST_OF)
+        .await;
+    assert_eq!(
+        result.err(),
+        Some(Error::UnknownSymbol("BTC-XYZ".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn text_pings_are_sent_while_books_arrive() {
+    let okx = MockExchange::start(Protocol::Okx, vec![vec![Step::Send(snapshot())]]).await;
+
+    let mut client = OkxClient::connect(&okx.url("/ws/v5/public")).await.unwrap();
+    let mut books = client
+        .subscribe_orderbook("BTC-USDT", BookChannel::Books, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    timeout(WAIT, books.next()).await.unwrap().unwrap();
+
+    tokio::time::pause();
+    tokio::time::advance(PING_AFTER_IDLE).await;
+    tokio::time::resume();
+
+    timeout(WAIT, async {
+        while !okx.received().contains(&"ping".to_string()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,154 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+
+use algo_challenge::aggregator_client::MergedBook;
+use algo_challenge::output::{rows, Format, LevelRow, Side, SummaryWriter};
+use algo_challenge::types::{Exchange, Quote, Venue};
+
+fn merged_book() -> MergedBook {
+    let quote = |price, amount| Quote { price, amount };
+    MergedBook {
+        spread: 1.5,
+        bids: vec![
+            (Venue::from(Exchange::Binance), quote(100.0, 1.0)),
+            (Venue::from(Exchange::Bitstamp), quote(99.5, 2.0)),
+        ],
+        asks: vec![(Venue::from(Exchange::Kraken), quote(101.5, 0.25))],
+        degraded: vec![],
+    }
+}
+
+/// 2023-10-19T07:20:00.123456Z.
+fn received() -> std::time::SystemTime {
+    UNIX_EPOCH + Duration::from_micros(1_697_700_000_123_456)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("algo_challenge-{}-{name}", std::process::id()))
+}
+
+/// Writes two summaries in `format` to a temporary file and returns its path.
+fn write_file(format: Format, name: &str) -> PathBuf {
+    let path = temp_path(name);
+    let mut writer = SummaryWriter::new(format, Box::new(File::create(&path).unwrap())).unwrap();
+    writer.write(received(), &merged_book()).unwrap();
+    writer
+        .write(received() + Duration::from_secs(1), &merged_book())
+        .unwrap();
+    writer.finish().unwrap();
+    path
+}
+
+#[test]
+fn summaries_flatten_into_one_row_per_level() {
+    let book = merged_book();
+    let rows = rows(received(), &book).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows[1],
+        LevelRow {
+            timestamp: 1_697_700_000_123_456,
+            side: Side::Bid,
+            rank: 1,
+            exchange: "Bitstamp".to_string(),
+            price: 99.5,
+            amount: 2.0,
+            spread: 1.5,
+        }
+    );
+    assert_eq!((rows[2].side, rows[2].rank), (Side::Ask, 0));
+
+    assert_eq!("jsonl".parse(), Ok(Format::JsonLines));
+    assert_eq!("csv".parse(), Ok(Format::Csv));
+    assert_eq!("parquet".parse(), Ok(Format::Parquet));
+    assert!("xlsx".parse::<Format>().is_err());
+}
+
+#[test]
+fn json_lines_hold_a_level_each() {
+    let path = write_file(Format::JsonLines, "levels.jsonl");
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 6);
+    let first = serde_json::from_str::<serde_json::Value>(lines[0]).unwrap();
+    assert_eq!(
+        first,
+        serde_json::json!({
+            "timestamp": 1_697_700_000_123_456i64,
+            "side": "bid",
+            "rank": 0,
+            "exchange": "Binance",
+            "price": 100.0,
+            "amount": 1.0,
+            "spread": 1.5,
+        })
+    );
+}
+
+#[test]
+fn csv_has_a_header_and_a_row_per_level() {
+    let path = write_file(Format::Csv, "levels.csv");
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[..3],
+        [
+            "timestamp,side,rank,exchange,price,amount,spread",
+            "1697700000123456,bid,0,Binance,100.0,1.0,1.5",
+            "1697700000123456,bid,1,Bitstamp,99.5,2.0,1.5",
+        ]
+    );
+    assert_eq!(lines.len(), 7);
+    assert!(lines[6].starts_with("1697700001123456,ask,0,Kraken,"));
+}
+
+#[test]
+fn parquet_files_read_back_by_column() {
+    let path = write_file(Format::Parquet, "levels.parquet");
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+
+    let metadata = reader.metadata();
+    assert_eq!(metadata.file_metadata().num_rows(), 6);
+    let columns = metadata
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        [
+            "timestamp",
+            "side",
+            "rank",
+            "exchange",
+            "price",
+            "amount",
+            "spread"
+        ]
+    );
+
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    fs::remove_file(path).unwrap();
+    let last = &rows[5];
+    assert_eq!(last.get_timestamp_micros(0).unwrap(), 1_697_700_001_123_456);
+    assert_eq!(last.get_string(1).unwrap(), "ask");
+    assert_eq!(last.get_int(2).unwrap(), 0);
+    assert_eq!(last.get_string(3).unwrap(), "Kraken");
+    assert_eq!(last.get_double(4).unwrap(), 101.5);
+    assert_eq!(last.get_double(5).unwrap(), 0.25);
+    assert_eq!(last.get_double(6).unwrap(), 1.5);
+}
@@ -0,0 +1,112 @@
+use proptest::prelude::*;
+use serde_json::value::RawValue;
+
+use algo_challenge::decimal;
+use algo_challenge::exchange::{binance_client, bitstamp_client, levels};
+use algo_challenge::types::Quote;
+
+const FRAME: &str = include_str!("../benches/data/bitstamp_order_book_btcusd.json");
+
+fn same_as_std(text: &str) -> Result<(), TestCaseError> {
+    let expected = text.parse::<f64>().ok().map(f64::to_bits);
+    prop_assert_eq!(
+        decimal::parse(text).map(f64::to_bits),
+        expected,
+        "{:?}",
+        text
+    );
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn decimals_parse_like_the_standard_parser(text in r"-?[0-9]{0,20}(\.[0-9]{0,24})?") {
+        same_as_std(&text)?;
+    }
+
+    #[test]
+    fn printed_floats_parse_back_exactly(value in any::<f64>()) {
+        same_as_std(&value.to_string())?;
+        same_as_std(&format!("{value:.8}"))?;
+    }
+}
+
+#[test]
+fn other_forms_fall_back_to_the_standard_parser() {
+    for text in [
+        "1e-5", "+1.5", "inf", "NaN", "", ".", "-", "1.2.3", " 1", "0x10",
+    ] {
+        same_as_std(text).unwrap();
+    }
+    assert_eq!(decimal::parse("0.00000001"), Some(1e-8));
+    assert_eq!(decimal::parse("28470"), Some(28470.0));
+}
+
+fn raw(json: &str) -> Box<RawValue> {
+    RawValue::from_string(json.to_string()).unwrap()
+}
+
+#[test]
+fn only_the_best_levels_are_parsed() {
+    let side = raw(r#"[["3.5","1"],["3","2.25"],["not","a number"],{"x":1}]"#);
+    assert_eq!(
+        levels::top_quotes(&side, 2),
+        Some(vec![
+            Quote {
+                price: 3.5,
+                amount: 1.0
+            },
+            Quote {
+                price: 3.0,
+                amount: 2.25
+            },
+        ])
+    );
+    assert_eq!(levels::top_quotes(&side, 0), Some(vec![]));
+    assert_eq!(levels::top_quotes(&side, 3), None);
+    assert_eq!(levels::top_quotes(&raw("[]"), 10), Some(vec![]));
+    assert_eq!(levels::top_quotes(&raw(r#"{"bids":[]}"#), 10), None);
+    assert_eq!(levels::top_quotes(&raw(r#"[[3.5, 1]]"#), 10), None);
+}
+
+#[test]
+fn bitstamp_frames_keep_the_best_levels_in_order() {
+    let value = serde_json::from_str::<serde_json::Value>(FRAME).unwrap();
+    let expected = |side: &str| {
+        value["data"][side].as_array().unwrap()[..10]
+            .iter()
+            .map(|level| Quote {
+                price: level[0].as_str().unwrap().parse().unwrap(),
+                amount: level[1].as_str().unwrap().parse().unwrap(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let ob = bitstamp_client::parse_order_book(FRAME, 10).unwrap();
+    assert_eq!(ob.last_updated, "1697700000123456");
+    assert_eq!(ob.bids, expected("bids"));
+    assert_eq!(ob.asks, expected("asks"));
+    assert_eq!(
+        bitstamp_client::parse_order_book(FRAME, 1000)
+            .unwrap()
+            .bids
+            .len(),
+        100
+    );
+}
+
+#[test]
+fn binance_frames_are_parsed_without_owned_levels() {
+    let frame =
+        r#"{"lastUpdateId":160,"bids":[["0.0024","10"],["0.0023","5"]],"asks":[["0.0026","100"]]}"#;
+    let ob = binance_client::parse_order_book(frame, 1).unwrap();
+    assert_eq!(ob.last_updated, "160");
+    assert_eq!(
+        ob.bids,
+        vec![Quote {
+            price: 0.0024,
+            amount: 10.0
+        }]
+    );
+    assert_eq!(ob.asks.len(), 1);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use algo_challenge::streaming::{supervise, Session, Watchdog};
+use algo_challenge::types::OrderBook;
+use common::{binance_depth, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const WATCHDOG: Watchdog = Watchdog {
+    no_data_timeout: Duration::from_millis(300),
+    initial_backoff: Duration::from_millis(10),
+    max_backoff: Duration::from_millis(100),
+    rest_poll_interval: None,
+};
+
+fn book(id: u64) -> Step {
+    Step::Send(binance_depth(id, &[("1.0", "1.0")], &[]))
+}
+
+/// Supervises Binance `btcusdt` feeds from `binance`, returning their books.
+fn supervise_binance(binance: &MockExchange) -> broadcast::Receiver<OrderBook> {
+    let (tx, rx) = broadcast::channel(32);
+    let url = binance.url("/ws");
+    tokio::spawn(async move {
+        supervise("Binance btcusdt", WATCHDOG, tx, || async {
+            let mut client = BinanceClient::connect(&url).await?;
+            let subscription = client
+                .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+                .await?;
+            Ok(Session {
+                handover: Box::pin(client.handover_requested()),
+                client,
+                book_events: subscription.book_events,
+            })
+        })
+        .await
+    });
+    rx
+}
+
+fn subscribes(binance: &MockExchange) -> usize {
+    binance
+        .received()
+        .iter()
+        .filter(|text| text.contains("\"SUBSCRIBE\""))
+        .count()
+}
+
+#[tokio::test]
+async fn silent_connections_are_replaced() {
+    // The first connection stays open but goes quiet after one book.
+    let binance = MockExchange::start(Protocol::Binance, vec![vec![book(1)], vec![book(2)]]).await;
+    let mut rx = supervise_binance(&binance);
+
+    for id in ["1", "2"] {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(ob.last_updated, id);
+    }
+    assert_eq!(subscribes(&binance), 2);
+}
+
+#[tokio::test]
+async fn closed_connections_are_replaced() {
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![book(1), Step::Close], vec![book(2)]],
+    )
+    .await;
+    let mut rx = supervise_binance(&binance);
+
+    for id in ["1", "2"] {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(ob.last_updated, id);
+    }
+}
+
+#[tokio::test]
+async fn unknown_symbols_are_not_retried() {
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![Step::Reply(
+            r#"{"code":-1121,"msg":"Invalid symbol.","id":{id}}"#.to_string(),
+        )]],
+    )
+    .await;
+    let (tx, mut rx) = broadcast::channel(32);
+    let url = binance.url("/ws");
+    let feed = supervise("Binance btcusdt", WATCHDOG, tx, || async {
+        let mut client = BinanceClient::connect(&url).await?;
+        let subscription = client
+            .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+            .await?;
+        Ok(Session {
+            handover: Box::pin(client.handover_requested()),
+            client,
+            book_events: subscription.book_events,
+        })
+    });
+
+    timeout(WAIT, feed).await.unwrap();
+    assert_eq!(subscribes(&binance), 1);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn pings_are_answered() {
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![Step::Ping("keepalive".to_string()), book(1)]],
+    )
+    .await;
+    let mut rx = supervise_binance(&binance);
+
+    timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+    // The pong is sent before the book frame is read, give the mock time to log it.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(binance.received().contains(&"pong:keepalive".to_string()));
+}
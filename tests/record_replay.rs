@@ -0,0 +1,168 @@
+mod common;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::StreamExt;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Instant};
+
+use algo_challenge::exchange::bitstamp_client::BitstampClient;
+use algo_challenge::recorder::{
+    now_micros, recording_files, Record, Recorder, RecorderConfig, FILE_PREFIX, FILE_SUFFIX,
+};
+use algo_challenge::replay::{replay, Pacing, RecordParser, RecordReader, ReplayConfig};
+use algo_challenge::types::{Exchange, OrderBook};
+use common::{bitstamp_book, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn levels(ob: &OrderBook) -> Vec<(f64, f64)> {
+    ob.bids
+        .iter()
+        .chain(&ob.asks)
+        .map(|l| (l.price, l.amount))
+        .collect()
+}
+
+/// Writes a recording of Bitstamp books received `offsets` after the first
+/// into a new directory.
+fn write_recording(name: &str, offsets: &[Duration]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("algo_challenge-{name}-{}", now_micros()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = File::create(dir.join(format!("{FILE_PREFIX}0{FILE_SUFFIX}"))).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    let start = now_micros();
+    for (i, offset) in offsets.iter().enumerate() {
+        let bid = format!("{}.0", 100 + i);
+        let record = Record {
+            received_at: start + offset.as_micros() as u64,
+            exchange: Exchange::Bitstamp,
+            frame: bitstamp_book("order_book_btcusd", &[(&bid, "1.0")], &[("200.0", "1.0")]),
+        };
+        writeln!(encoder, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+    }
+    encoder.finish().unwrap();
+    dir
+}
+
+/// When each book of a replay of `dir` arrived, after the first one.
+async fn arrivals(dir: &Path, pacing: Pacing) -> Vec<Duration> {
+    let (tx, mut rx) = broadcast::channel(32);
+    let replayer = tokio::spawn(replay(ReplayConfig::new(dir, pacing), tx, BEST_OF));
+    let mut arrivals = Vec::new();
+    while rx.recv().await.is_ok() {
+        arrivals.push(Instant::now());
+    }
+    replayer.await.unwrap().unwrap();
+    arrivals.iter().map(|at| *at - arrivals[0]).collect()
+}
+
+#[tokio::test]
+async fn replays_keep_the_recorded_gaps() {
+    tokio::time::pause();
+    let offsets = [0, 1000, 3000].map(Duration::from_millis);
+    let dir = write_recording("paced", &offsets);
+
+    assert_eq!(arrivals(&dir, Pacing::RealTime).await, offsets);
+    assert_eq!(
+        arrivals(&dir, Pacing::Accelerated(4.0)).await,
+        [0, 250, 750].map(Duration::from_millis)
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn only_builtin_venues_are_replayed() {
+    assert!(RecordParser::supports(Exchange::Kraken));
+    assert!(RecordParser::supports(Exchange::BinanceUs));
+    assert!(!RecordParser::supports(Exchange::intern(
+        "Replay-Adapter-Venue"
+    )));
+}
+
+#[tokio::test]
+async fn replayed_books_match_the_live_ones() {
+    let frames = (1..=3)
+        .map(|i| {
+            let bid = format!("{}.0", 100 + i);
+            let ask = format!("{}.0", 200 + i);
+            bitstamp_book("order_book_btcusd", &[(&bid, "1.0")], &[(&ask, "2.0")])
+        })
+        .collect::<Vec<_>>();
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![frames.iter().cloned().map(Step::Send).collect()],
+    )
+    .await;
+
+    let dir = std::env::temp_dir().join(format!("algo_challenge-replay-{}", now_micros()));
+    let (recorder, writer) = Recorder::start(RecorderConfig::new(&dir)).unwrap();
+
+    let mut client = BitstampClient::connect_with_recorder(&bitstamp.url("/"), Some(recorder))
+        .await
+        .unwrap();
+    let mut books = client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let mut live = Vec::new();
+    for _ in 0..frames.len() {
+        live.push(timeout(WAIT, books.next()).await.unwrap().unwrap());
+    }
+    drop(books);
+    drop(client);
+    writer.await.unwrap().unwrap();
+
+    let files = recording_files(&dir).unwrap();
+    assert_eq!(files.len(), 1);
+    let records = RecordReader::open(&files[0])
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    // The subscription acknowledgement is captured along with the book frames.
+    assert_eq!(records.len(), frames.len() + 1);
+    assert!(records.iter().all(|r| r.exchange == Exchange::Bitstamp));
+    assert_eq!(
+        records[1..].iter().map(|r| &r.frame).collect::<Vec<_>>(),
+        frames.iter().collect::<Vec<_>>()
+    );
+    assert!(records
+        .windows(2)
+        .all(|w| w[0].received_at <= w[1].received_at));
+
+    let (tx, mut rx) = broadcast::channel(32);
+    let replayer = tokio::spawn(replay(
+        ReplayConfig::new(&dir, Pacing::AsFastAsPossible),
+        tx,
+        BEST_OF,
+    ));
+    let mut replayed = Vec::new();
+    while let Ok(ob) = rx.recv().await {
+        replayed.push(ob);
+    }
+    replayer.await.unwrap().unwrap();
+
+    assert_eq!(
+        replayed.iter().map(levels).collect::<Vec<_>>(),
+        live.iter().map(levels).collect::<Vec<_>>()
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pacing_parses_from_configuration_strings() {
+    assert_eq!("realtime".parse::<Pacing>(), Ok(Pacing::RealTime));
+    assert_eq!("max".parse::<Pacing>(), Ok(Pacing::AsFastAsPossible));
+    assert_eq!("10x".parse::<Pacing>(), Ok(Pacing::Accelerated(10.0)));
+    assert_eq!("2.5".parse::<Pacing>(), Ok(Pacing::Accelerated(2.5)));
+    assert!("0x".parse::<Pacing>().is_err());
+    assert!("fast".parse::<Pacing>().is_err());
+}
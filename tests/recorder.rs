@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+
+use algo_challenge::recorder::{now_micros, recording_files, Recorder, RecorderConfig};
+use algo_challenge::replay::RecordReader;
+use algo_challenge::types::Exchange;
+
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("algo_challenge-{name}-{}", now_micros()))
+}
+
+/// The frames of each recording file in `dir`, oldest file first.
+fn frames_per_file(dir: &Path) -> Vec<Vec<String>> {
+    recording_files(dir)
+        .unwrap()
+        .iter()
+        .map(|path| {
+            RecordReader::open(path)
+                .unwrap()
+                .map(|record| record.unwrap().frame)
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether `path` is a complete gzip file, i.e. was finished rather than cut.
+fn is_finished(path: &Path) -> bool {
+    let mut text = String::new();
+    GzDecoder::new(File::open(path).unwrap())
+        .read_to_string(&mut text)
+        .is_ok()
+}
+
+#[tokio::test]
+async fn files_rotate_once_they_hold_max_file_bytes() {
+    let dir = temp_dir("rotate-bytes");
+    let config = RecorderConfig {
+        // Every record fills a file.
+        max_file_bytes: 1,
+        ..RecorderConfig::new(&dir)
+    };
+    let (recorder, writer) = Recorder::start(config).unwrap();
+    for frame in ["a", "b", "c"] {
+        recorder.record(Exchange::Bitstamp, frame);
+    }
+    drop(recorder);
+    writer.await.unwrap().unwrap();
+
+    // Files opened within the same millisecond still list in order.
+    assert_eq!(frames_per_file(&dir), [["a"], ["b"], ["c"]]);
+    assert!(recording_files(&dir)
+        .unwrap()
+        .iter()
+        .all(|f| is_finished(f)));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn files_rotate_once_they_are_max_file_age_old() {
+    let dir = temp_dir("rotate-age");
+    let config = RecorderConfig {
+        max_file_age: Duration::from_millis(50),
+        ..RecorderConfig::new(&dir)
+    };
+    let (recorder, writer) = Recorder::start(config).unwrap();
+    recorder.record(Exchange::Kraken, "a");
+    recorder.record(Exchange::Kraken, "b");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    recorder.record(Exchange::Kraken, "c");
+    drop(recorder);
+    writer.await.unwrap().unwrap();
+
+    assert_eq!(frames_per_file(&dir), [vec!["a", "b"], vec!["c"]]);
+    assert!(recording_files(&dir)
+        .unwrap()
+        .iter()
+        .all(|f| is_finished(f)));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn files_are_finished_when_the_recorder_stops() {
+    let dir = temp_dir("finish");
+    let (recorder, writer) = Recorder::start(RecorderConfig::new(&dir)).unwrap();
+    recorder.record(Exchange::Okx, "a");
+    let files = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let files = recording_files(&dir).unwrap();
+            if !files.is_empty() {
+                return files;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    // Flushed, but not finished while a clone of the recorder is alive.
+    let copy = recorder.clone();
+    drop(recorder);
+    assert!(!is_finished(&files[0]));
+
+    drop(copy);
+    writer.await.unwrap().unwrap();
+    assert!(is_finished(&files[0]));
+    assert_eq!(frames_per_file(&dir), [["a"]]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+use algo_challenge::config::{Config, VenueConfig};
+use algo_challenge::grpc::manager;
+use algo_challenge::registry::{FeedConfig, VenueRegistry};
+use algo_challenge::streaming::Watchdog;
+use algo_challenge::types::{Exchange, OrderBook, Quote, Summary};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn feed_config(exchange: Exchange, symbol: &str, tx: broadcast::Sender<OrderBook>) -> FeedConfig {
+    FeedConfig {
+        exchange,
+        symbol: symbol.to_string(),
+        tx,
+        best_of: BEST_OF,
+        recorder: None,
+        watchdog: Watchdog::default(),
+    }
+}
+
+/// A venue that sends one book with a bid at `price`.
+fn register_fixed_venue(registry: &mut VenueRegistry, name: &str, price: f64) -> Exchange {
+    registry.register(name, move |c| {
+        Box::pin(async move {
+            let level = Quote { price, amount: 1.0 };
+            let book = OrderBook {
+                exchange: c.exchange,
+                last_updated: c.symbol,
+                bids: vec![level],
+                asks: vec![],
+                degraded: false,
+            };
+            c.tx.send(book).unwrap();
+        })
+    })
+}
+
+#[test]
+fn builtin_venues_keep_their_ids_and_names() {
+    assert_eq!(Exchange::Binance.id(), 0);
+    assert_eq!(Exchange::Bitstamp.id(), 1);
+    assert_eq!(Exchange::BinanceUs.id(), 8);
+    assert_eq!(Exchange::Okx.to_string(), "OKX");
+    assert_eq!("binance_usdm".parse(), Ok(Exchange::BinanceUsdm));
+    assert!("nowhere".parse::<Exchange>().is_err());
+}
+
+#[test]
+fn new_names_are_interned_once() {
+    let exchange = Exchange::intern("Test-Venue-A");
+    assert!(exchange.id() > Exchange::BinanceUs.id());
+    assert_eq!(Exchange::intern("test venue a"), exchange);
+    assert_eq!(exchange.to_string(), "Test-Venue-A");
+
+    let json = serde_json::to_string(&exchange).unwrap();
+    assert_eq!(json, r#""Test-Venue-A""#);
+    assert_eq!(serde_json::from_str::<Exchange>(&json).unwrap(), exchange);
+    assert_eq!(
+        serde_json::from_str::<Exchange>(r#""Kraken""#).unwrap(),
+        Exchange::Kraken
+    );
+}
+
+#[test]
+fn the_builtin_registry_knows_every_client() {
+    let registry = VenueRegistry::builtin();
+    let names = registry.names().collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "Binance",
+            "Bitstamp",
+            "Coinbase",
+            "Kraken",
+            "OKX",
+            "Bybit",
+            "Binance-USDM",
+            "Binance-COINM",
+            "Binance-US"
+        ]
+    );
+    assert_eq!(registry.lookup("okx"), Ok(Exchange::Okx));
+
+    let error = registry.lookup("nowhere").unwrap_err();
+    assert!(error.starts_with("unknown venue: nowhere (known: Binance, Bitstamp"));
+}
+
+#[test]
+fn venues_come_from_arguments_or_a_file() {
+    let args = ["btcusd", "kraken=BTC/USD", "ethusdt"].map(String::from);
+    assert_eq!(
+        Config::from_args(&args).unwrap().venues,
+        vec![
+            VenueConfig::new("bitstamp", "btcusd"),
+            VenueConfig::new("kraken", "BTC/USD"),
+            VenueConfig::new("binance", "ethusdt"),
+        ]
+    );
+    assert!(Config::from_args(&["a", "b", "c"].map(String::from)).is_err());
+    assert_eq!(
+        Config::from_args(&["binance=btcusdt", "Binance=ethusdt"].map(String::from)),
+        Err("venue listed twice: Binance (btcusdt and ethusdt)".to_string())
+    );
+
+    let path = std::env::temp_dir().join(format!("venues-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"venues": [{"venue": "okx", "symbol": "BTC-USDT"}, {"venue": "bybit", "symbol": "BTCUSDT"}]}"#,
+    )
+    .unwrap();
+    let config = Config::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        config.venues,
+        vec![
+            VenueConfig::new("okx", "BTC-USDT"),
+            VenueConfig::new("bybit", "BTCUSDT")
+        ]
+    );
+
+    std::fs::write(
+        &path,
+        r#"{"venues": [{"venue": "okx", "symbol": "BTC-USDT"}, {"venue": "okx", "symbol": "ETH-USDT"}]}"#,
+    )
+    .unwrap();
+    let error = Config::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.ends_with("venue listed twice: okx (BTC-USDT and ETH-USDT)"));
+}
+
+#[tokio::test]
+async fn registered_venues_are_merged_like_builtin_ones() {
+    let mut registry = VenueRegistry::builtin();
+    let venue_b = register_fixed_venue(&mut registry, "Test-Venue-B", 101.0);
+    let venue_c = register_fixed_venue(&mut registry, "Test-Venue-C", 102.0);
+    assert_eq!(registry.lookup("test-venue-b"), Ok(venue_b));
+
+    let (tx, rx) = broadcast::channel::<OrderBook>(32);
+    let (s_tx, mut s_rx) = broadcast::channel::<Summary>(32);
+    tokio::spawn(manager(vec![venue_b, venue_c], rx, s_tx, BEST_OF));
+
+    for venue in [venue_b, venue_c] {
+        let feed = registry
+            .feed(feed_config(venue, "XYZ", tx.clone()))
+            .unwrap();
+        tokio::spawn(feed);
+    }
+
+    let summary = timeout(WAIT, s_rx.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .with_venue_names();
+    let bids = summary
+        .bids
+        .iter()
+        .map(|level| (level.exchange.as_str(), level.price))
+        .collect::<Vec<_>>();
+    assert_eq!(bids, vec![("Test-Venue-C", 102.0), ("Test-Venue-B", 101.0)]);
+}
+
+#[test]
+fn unregistered_venues_have_no_feed() {
+    let registry = VenueRegistry::default();
+    let (tx, _rx) = broadcast::channel::<OrderBook>(1);
+    assert!(registry
+        .feed(feed_config(Exchange::Binance, "btcusdt", tx))
+        .is_none());
+}
@@ -0,0 +1,254 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Instant};
+
+use algo_challenge::exchange::binance_client::{BinanceClient, Market, PriceLevels, Speed};
+use algo_challenge::exchange::error::Error;
+use algo_challenge::exchange::rest_client::{RestClient, RestVenue};
+use algo_challenge::streaming::{supervise_with_fallback, Fallback, Session, Watchdog};
+use algo_challenge::types::{Exchange, OrderBook, Summary};
+use common::{binance_depth, bitstamp_order_book, MockExchange, MockHttp, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+const WATCHDOG: Watchdog = Watchdog {
+    no_data_timeout: Duration::from_secs(5),
+    initial_backoff: Duration::from_millis(200),
+    max_backoff: Duration::from_millis(200),
+    rest_poll_interval: Some(Duration::from_millis(20)),
+};
+
+/// A session of the Binance mock at `url`.
+async fn binance_session(url: &str) -> Result<Session<BinanceClient>, Error> {
+    let mut client = BinanceClient::connect(url).await?;
+    let subscription = client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await?;
+    Ok(Session {
+        handover: Box::pin(client.handover_requested()),
+        client,
+        book_events: subscription.book_events,
+    })
+}
+
+#[tokio::test]
+async fn bitstamp_books_are_polled_and_degraded() {
+    let body = bitstamp_order_book(&[("100.0", "1.5"), ("99.0", "2")], &[("101.0", "0.5")]);
+    let http = MockHttp::start(vec![(200, body)]).await;
+    let client = RestClient::new(RestVenue::Bitstamp, &http.addr);
+
+    let ob = client.order_book("BTCUSD", 1).await.unwrap();
+    assert_eq!(ob.exchange, Exchange::Bitstamp);
+    assert!(ob.degraded);
+    assert_eq!(ob.last_updated, "1697700000000000");
+    assert_eq!((ob.bids.len(), ob.bids[0].price), (1, 100.0));
+    assert_eq!(ob.asks[0].amount, 0.5);
+    assert_eq!(http.requests(), vec!["/api/v2/order_book/btcusd/"]);
+}
+
+#[tokio::test]
+async fn binance_depth_is_requested_at_a_valid_limit() {
+    let body = binance_depth(7, &[("100.0", "1.0")], &[("101.0", "2.0")]);
+    let http = MockHttp::start(vec![(200, body)]).await;
+
+    let spot = RestClient::new(RestVenue::Binance(Market::Spot), &http.addr);
+    let ob = spot.order_book("btcusdt", BEST_OF).await.unwrap();
+    assert_eq!((ob.exchange, ob.degraded), (Exchange::Binance, true));
+    assert_eq!(ob.last_updated, "7");
+
+    let usdm = RestClient::new(RestVenue::Binance(Market::UsdM), &http.addr);
+    let ob = usdm.order_book("btcusdt", 15).await.unwrap();
+    assert_eq!(ob.exchange, Exchange::BinanceUsdm);
+
+    assert_eq!(
+        http.requests(),
+        vec![
+            "/api/v3/depth?symbol=BTCUSDT&limit=10",
+            "/fapi/v1/depth?symbol=BTCUSDT&limit=20",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn failed_requests_map_to_typed_errors() {
+    let http = MockHttp::start(vec![
+        (400, r#"{"code":-1121,"msg":"Invalid symbol."}"#.to_string()),
+        (
+            429,
+            r#"{"code":-1003,"msg":"Too many requests."}"#.to_string(),
+        ),
+        (500, "oops".to_string()),
+        (200, "[]".to_string()),
+    ])
+    .await;
+    let client = RestClient::new(RestVenue::Binance(Market::Spot), &http.addr);
+
+    let errors = [
+        client.order_book("nope", BEST_OF).await,
+        client.order_book("btcusdt", BEST_OF).await,
+        client.order_book("btcusdt", BEST_OF).await,
+        client.order_book("btcusdt", BEST_OF).await,
+    ];
+    assert!(matches!(&errors[0], Err(Error::UnknownSymbol(s)) if s == "nope"));
+    assert!(matches!(&errors[1], Err(Error::RateLimited(_))));
+    assert!(matches!(&errors[2], Err(Error::Http(e)) if e.contains("500")));
+    assert!(matches!(&errors[3], Err(Error::MalformedJSON(_))));
+}
+
+#[tokio::test]
+async fn feeds_poll_rest_until_the_websocket_recovers() {
+    let http = MockHttp::start(vec![(
+        200,
+        binance_depth(1, &[("99.0", "1.0")], &[("100.0", "1.0")]),
+    )])
+    .await;
+    let live = |id| Step::Send(binance_depth(id, &[("99.5", "1.0")], &[("100.0", "1.0")]));
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![
+            live(2),
+            Step::Sleep(Duration::from_millis(50)),
+            live(3),
+            Step::Sleep(Duration::from_millis(50)),
+            live(4),
+        ]],
+    )
+    .await;
+
+    let (tx, mut rx) = broadcast::channel::<OrderBook>(256);
+    let url = binance.url("/ws");
+    let fallback = Fallback {
+        client: RestClient::new(RestVenue::Binance(Market::Spot), &http.addr),
+        symbol: "btcusdt".to_string(),
+        best_of: BEST_OF,
+        interval: WATCHDOG.rest_poll_interval.unwrap(),
+    };
+    // The websocket is unavailable for the first attempt.
+    let attempts = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(async move {
+        supervise_with_fallback("Binance btcusdt", WATCHDOG, Some(fallback), tx, || {
+            let url = url.clone();
+            let attempts = attempts.clone();
+            async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(Error::ConnectionClosed);
+                }
+                binance_session(&url).await
+            }
+        })
+        .await
+    });
+
+    let mut polled = 0;
+    let first_live = loop {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        if !ob.degraded {
+            break ob;
+        }
+        assert_eq!(ob.bids[0].price, 99.0);
+        polled += 1;
+    };
+    assert!(polled >= 2, "polled {polled} books");
+    assert_eq!(first_live.last_updated, "2");
+    assert!(http.requests().len() >= polled);
+
+    // Polling stops once the websocket delivers again.
+    for id in ["3", "4"] {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        assert!(!ob.degraded);
+        assert_eq!(ob.last_updated, id);
+    }
+}
+
+#[tokio::test]
+async fn rest_is_polled_while_a_connect_hangs() {
+    let http = MockHttp::start(vec![(
+        200,
+        binance_depth(1, &[("99.0", "1.0")], &[("100.0", "1.0")]),
+    )])
+    .await;
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![Step::Send(binance_depth(
+            2,
+            &[("99.5", "1.0")],
+            &[("100.0", "1.0")],
+        ))]],
+    )
+    .await;
+    let watchdog = Watchdog {
+        no_data_timeout: Duration::from_millis(500),
+        ..WATCHDOG
+    };
+
+    let (tx, mut rx) = broadcast::channel::<OrderBook>(256);
+    let url = binance.url("/ws");
+    let fallback = Fallback {
+        client: RestClient::new(RestVenue::Binance(Market::Spot), &http.addr),
+        symbol: "btcusdt".to_string(),
+        best_of: BEST_OF,
+        interval: WATCHDOG.rest_poll_interval.unwrap(),
+    };
+    // The first attempt fails and the second one never completes.
+    let attempts = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(async move {
+        supervise_with_fallback("Binance btcusdt", watchdog, Some(fallback), tx, || {
+            let url = url.clone();
+            let attempts = attempts.clone();
+            async move {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Error::ConnectionClosed),
+                    1 => std::future::pending().await,
+                    _ => binance_session(&url).await,
+                }
+            }
+        })
+        .await
+    });
+
+    let mut last = Instant::now();
+    let mut longest_gap = Duration::ZERO;
+    loop {
+        let ob = timeout(WAIT, rx.recv()).await.unwrap().unwrap();
+        if !ob.degraded {
+            assert_eq!(ob.last_updated, "2");
+            break;
+        }
+        longest_gap = longest_gap.max(last.elapsed());
+        last = Instant::now();
+    }
+    assert!(longest_gap < Duration::from_millis(250), "{longest_gap:?}");
+}
+
+#[test]
+fn summaries_list_degraded_venues() {
+    let book = |exchange: Exchange, degraded| OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids: vec![],
+        asks: vec![],
+        degraded,
+    };
+    let summary = Summary::merge_all(
+        [
+            book(Exchange::Bitstamp, true),
+            book(Exchange::Binance, false),
+            book(Exchange::Kraken, true),
+        ],
+        BEST_OF,
+    );
+    assert_eq!(summary.degraded, vec!["Bitstamp", "Kraken"]);
+    assert!(Summary::merge(
+        book(Exchange::Binance, false),
+        book(Exchange::Bitstamp, false),
+        BEST_OF
+    )
+    .degraded
+    .is_empty());
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::timeout;
+
+use algo_challenge::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use algo_challenge::exchange::bitstamp_client::BitstampClient;
+use common::{binance_combined, binance_depth, bitstamp_book, MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+/// The requests of `mock` with the given `method` (Binance) or `event` (Bitstamp).
+fn requests(mock: &MockExchange, kind: &str) -> Vec<Value> {
+    mock.received()
+        .iter()
+        .filter_map(|text| serde_json::from_str::<Value>(text).ok())
+        .filter(|request| request["method"] == kind || request["event"] == kind)
+        .collect()
+}
+
+#[tokio::test]
+async fn bitstamp_channels_do_not_see_each_others_books() {
+    let bitstamp = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![
+            // Gives the client time to subscribe to the second channel.
+            Step::Sleep(Duration::from_millis(100)),
+            Step::Send(bitstamp_book("order_book_ethbtc", &[("0.05", "1.0")], &[])),
+            Step::Send(bitstamp_book(
+                "order_book_btcusd",
+                &[("30000.0", "1.0")],
+                &[],
+            )),
+            Step::Send(bitstamp_book("order_book_ethbtc", &[("0.06", "1.0")], &[])),
+            Step::Send(bitstamp_book(
+                "order_book_btcusd",
+                &[("30001.0", "1.0")],
+                &[],
+            )),
+        ]],
+    )
+    .await;
+
+    let mut client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    let mut btcusd_books = client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let mut ethbtc_books = client
+        .subscribe_orderbook("ethbtc", BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+
+    for price in [30000.0, 30001.0] {
+        let ob = timeout(WAIT, btcusd_books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.bids[0].price, price);
+    }
+    for price in [0.05, 0.06] {
+        let ob = timeout(WAIT, ethbtc_books.next()).await.unwrap().unwrap();
+        assert_eq!(ob.bids[0].price, price);
+    }
+}
+
+#[tokio::test]
+async fn bitstamp_unsubscribe_ends_the_stream() {
+    let bitstamp = MockExchange::start(Protocol::Bitstamp, vec![vec![]]).await;
+
+    let mut client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    let subscription = client.subscribe_orderbook("btcusd", BEST_OF).await.unwrap();
+    let mut books = subscription.book_events;
+
+    client.unsubscribe(&subscription.handle).await.unwrap();
+
+    assert!(timeout(WAIT, books.next()).await.unwrap().is_none());
+    let unsubscribes = requests(&bitstamp, "bts:unsubscribe");
+    assert_eq!(unsubscribes.len(), 1);
+    assert_eq!(unsubscribes[0]["data"]["channel"], "order_book_btcusd");
+}
+
+#[tokio::test]
+async fn binance_unsubscribe_leaves_other_streams_running() {
+    let btcusdt = "btcusdt@depth20@100ms";
+    let ethbtc = "ethbtc@depth20@100ms";
+    let frame = |stream: &str, id: u64| {
+        binance_combined(stream, &binance_depth(id, &[("1.0", "1.0")], &[]))
+    };
+    let binance = MockExchange::start(
+        Protocol::Binance,
+        vec![vec![
+            // Gives the client time to subscribe and unsubscribe.
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Send(frame(ethbtc, 1)),
+            Step::Send(frame(btcusdt, 2)),
+        ]],
+    )
+    .await;
+
+    let mut client = BinanceClient::connect(&binance.url("/stream"))
+        .await
+        .unwrap();
+    let btcusdt_subscription = client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap();
+    let mut ethbtc_books = client
+        .subscribe_orderbook("ethbtc", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap()
+        .book_events;
+    let mut btcusdt_books = btcusdt_subscription.book_events;
+
+    client
+        .unsubscribe(&btcusdt_subscription.handle)
+        .await
+        .unwrap();
+
+    assert!(timeout(WAIT, btcusdt_books.next()).await.unwrap().is_none());
+    let ob = timeout(WAIT, ethbtc_books.next()).await.unwrap().unwrap();
+    assert_eq!(ob.last_updated, "1");
+
+    let unsubscribes = requests(&binance, "UNSUBSCRIBE");
+    assert_eq!(unsubscribes.len(), 1);
+    assert_eq!(unsubscribes[0]["params"][0], btcusdt);
+}
+
+#[tokio::test]
+async fn shared_channels_are_unsubscribed_with_their_last_subscription() {
+    let binance = MockExchange::start(Protocol::Binance, vec![vec![]]).await;
+
+    let mut client = BinanceClient::connect(&binance.url("/stream"))
+        .await
+        .unwrap();
+    let first = client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap();
+    let second = client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .unwrap();
+    assert_ne!(first.handle, second.handle);
+
+    client.unsubscribe(&first.handle).await.unwrap();
+    assert!(requests(&binance, "UNSUBSCRIBE").is_empty());
+
+    client.unsubscribe(&second.handle).await.unwrap();
+    assert_eq!(requests(&binance, "UNSUBSCRIBE").len(), 1);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::Duration;
+
+use algo_challenge::exchange::binance_client::{BinanceClient, PriceLevels, Speed};
+use algo_challenge::exchange::bitstamp_client::BitstampClient;
+use algo_challenge::exchange::error::Error;
+use common::{MockExchange, Protocol, Step};
+
+const BEST_OF: usize = 10;
+
+async fn subscribe_binance(script: Vec<Step>) -> Result<(), Error> {
+    let binance = MockExchange::start(Protocol::Binance, vec![script]).await;
+    let mut client = BinanceClient::connect(&binance.url("/ws")).await.unwrap();
+    client.request_timeout = Duration::from_millis(200);
+    client
+        .subscribe_orderbook("btcusdt", PriceLevels::L20, Speed::S100, BEST_OF)
+        .await
+        .map(drop)
+}
+
+async fn subscribe_bitstamp(script: Vec<Step>) -> Result<(), Error> {
+    let bitstamp = MockExchange::start(Protocol::Bitstamp, vec![script]).await;
+    let mut client = BitstampClient::connect(&bitstamp.url("/")).await.unwrap();
+    client.request_timeout = Duration::from_millis(200);
+    client
+        .subscribe_orderbook("btcusd", BEST_OF)
+        .await
+        .map(drop)
+}
+
+#[tokio::test]
+async fn acknowledged_subscriptions_succeed() {
+    assert_eq!(subscribe_binance(vec![]).await, Ok(()));
+    assert_eq!(subscribe_bitstamp(vec![]).await, Ok(()));
+}
+
+#[tokio::test]
+async fn binance_responses_are_matched_by_id() {
+    let result = subscribe_binance(vec![
+        Step::Reply(r#"{"result":null,"id":999}"#.to_string()),
+        Step::Send(r#"{"result":null,"id":1}"#.to_string()),
+    ])
+    .await;
+    assert_eq!(result, Ok(()));
+}
+
+#[tokio::test]
+async fn binance_errors_are_typed() {
+    let result = subscribe_binance(vec![Step::Reply(
+        r#"{"code":-1121,"msg":"Invalid symbol.","id":{id}}"#.to_string(),
+    )])
+    .await;
+    assert_eq!(
+        result,
+        Err(Error::UnknownSymbol("Invalid symbol.".to_string()))
+    );
+
+    let result = subscribe_binance(vec![Step::Reply(
+        r#"{"error":{"code":-1003,"msg":"Too many requests"},"id":{id}}"#.to_string(),
+    )])
+    .await;
+    assert_eq!(
+        result,
+        Err(Error::RateLimited("Too many requests".to_string()))
+    );
+
+    let result = subscribe_binance(vec![Step::Reply(
+        r#"{"error":{"code":2,"msg":"Invalid request: too many parameters"},"id":{id}}"#
+            .to_string(),
+    )])
+    .await;
+    assert_eq!(
+        result,
+        Err(Error::SubscriptionRejected(
+            "2: Invalid request: too many parameters".to_string()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn bitstamp_errors_are_typed() {
+    let error = |message: &str| {
+        Step::Reply(format!(
+            r#"{{"event":"bts:error","channel":"","data":{{"code":null,"message":"{message}"}}}}"#
+        ))
+    };
+
+    let result = subscribe_bitstamp(vec![error("Bad subscription string.")]).await;
+    assert_eq!(
+        result,
+        Err(Error::UnknownSymbol("order_book_btcusd".to_string()))
+    );
+
+    let result = subscribe_bitstamp(vec![error("Rate limit exceeded.")]).await;
+    assert_eq!(
+        result,
+        Err(Error::RateLimited("Rate limit exceeded.".to_string()))
+    );
+
+    let result = subscribe_bitstamp(vec![error("Incorrect JSON format.")]).await;
+    assert_eq!(
+        result,
+        Err(Error::SubscriptionRejected(
+            "Incorrect JSON format.".to_string()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn unanswered_subscriptions_time_out() {
+    assert_eq!(
+        subscribe_binance(vec![Step::NoReply]).await,
+        Err(Error::Timeout(200))
+    );
+    assert_eq!(
+        subscribe_bitstamp(vec![Step::NoReply]).await,
+        Err(Error::Timeout(200))
+    );
+}
+
+#[tokio::test]
+async fn closed_connections_fail_pending_subscriptions() {
+    assert_eq!(
+        subscribe_binance(vec![Step::NoReply, Step::Close]).await,
+        Err(Error::ConnectionClosed)
+    );
+    assert_eq!(
+        subscribe_bitstamp(vec![Step::NoReply, Step::Drop]).await,
+        Err(Error::ConnectionClosed)
+    );
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+use tokio::time::timeout;
+
+use algo_challenge::aggregator_client::{AggregatorClient, ClientConfig, ClientTls};
+use algo_challenge::grpc::{GrpcConfig, ServerTls};
+use algo_challenge::types::Exchange;
+use common::{book, start_aggregator_with};
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+/// The name the server's certificate is for; tests connect to `127.0.0.1`.
+const DOMAIN: &str = "aggregator.test";
+
+fn ca(name: &str) -> Certificate {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+    Certificate::from_params(params).unwrap()
+}
+
+/// A certificate for `name` signed by `ca`, as its PEM and its key's PEM.
+fn signed(ca: &Certificate, name: &str) -> (Vec<u8>, Vec<u8>) {
+    let cert = Certificate::from_params(CertificateParams::new(vec![name.to_string()])).unwrap();
+    let pem = cert.serialize_pem_with_signer(ca).unwrap();
+    (
+        pem.into_bytes(),
+        cert.serialize_private_key_pem().into_bytes(),
+    )
+}
+
+fn pem(ca: &Certificate) -> Vec<u8> {
+    ca.serialize_pem().unwrap().into_bytes()
+}
+
+/// A server certificate for `DOMAIN` signed by `server_ca`, verifying clients
+/// with `client_ca` if given.
+fn server_config(server_ca: &Certificate, client_ca: Option<&Certificate>) -> GrpcConfig {
+    let (cert, key) = signed(server_ca, DOMAIN);
+    GrpcConfig {
+        tls: Some(ServerTls {
+            cert,
+            key,
+            client_ca: client_ca.map(pem),
+        }),
+        ..GrpcConfig::default()
+    }
+}
+
+fn client(url: &str, tls: Option<ClientTls>) -> AggregatorClient {
+    AggregatorClient::with_config(ClientConfig {
+        connect_timeout: WAIT,
+        tls,
+        ..ClientConfig::new(url)
+    })
+}
+
+fn trusting(ca: &Certificate) -> ClientTls {
+    ClientTls {
+        ca: Some(pem(ca)),
+        domain: Some(DOMAIN.to_string()),
+        ..ClientTls::default()
+    }
+}
+
+#[tokio::test]
+async fn summaries_stream_over_tls() {
+    let server_ca = ca("Server CA");
+    let config = server_config(&server_ca, None);
+    let (tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config).await;
+    assert!(url.starts_with("https://"));
+
+    let client = client(&url, Some(trusting(&server_ca)));
+    let mut books = client.summaries();
+    let sent = tokio::spawn(async move {
+        loop {
+            let _ = tx.send(book(Exchange::Binance, 100.0, 100.5));
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+    let book = timeout(WAIT, books.next()).await.unwrap().unwrap().unwrap();
+    sent.abort();
+    assert_eq!(book.spread, 0.5);
+    assert_eq!(book.best_bid().unwrap().0.name, "Binance");
+}
+
+#[tokio::test]
+async fn clients_refuse_untrusted_servers() {
+    let server_ca = ca("Server CA");
+    let config = server_config(&server_ca, None);
+    let (_tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config).await;
+
+    // Signed by another CA than the one the client trusts.
+    let untrusted = client(&url, Some(trusting(&ca("Other CA"))));
+    assert!(untrusted.venues().await.is_err());
+
+    // Trusted, but for another name than the client checks.
+    let wrong_name = client(
+        &url,
+        Some(ClientTls {
+            domain: Some("elsewhere.test".to_string()),
+            ..trusting(&server_ca)
+        }),
+    );
+    assert!(wrong_name.venues().await.is_err());
+
+    // Plaintext to a TLS server.
+    let plaintext = client(&url.replacen("https", "http", 1), None);
+    assert!(plaintext.venues().await.is_err());
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_a_client_certificate() {
+    let server_ca = ca("Server CA");
+    let client_ca = ca("Client CA");
+    let config = server_config(&server_ca, Some(&client_ca));
+    let (_tx, url) = start_aggregator_with(&[Exchange::Binance], BEST_OF, config).await;
+
+    let anonymous = client(&url, Some(trusting(&server_ca)));
+    assert!(anonymous.venues().await.is_err());
+
+    let impostor = client(
+        &url,
+        Some(ClientTls {
+            identity: Some(signed(&ca("Other CA"), "impostor")),
+            ..trusting(&server_ca)
+        }),
+    );
+    assert!(impostor.venues().await.is_err());
+
+    let known = client(
+        &url,
+        Some(ClientTls {
+            identity: Some(signed(&client_ca, "client")),
+            ..trusting(&server_ca)
+        }),
+    );
+    let venues = known.venues().await.unwrap();
+    assert_eq!(venues.get(0), Some("Binance"));
+}
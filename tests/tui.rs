@@ -0,0 +1,173 @@
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::Modifier;
+use ratatui::Terminal;
+
+use algo_challenge::aggregator_client::MergedBook;
+use algo_challenge::tui::{venue_color, Control, Ladder, MAX_DEPTH};
+use algo_challenge::types::{Exchange, Quote, Venue};
+
+fn quote(price: f64, amount: f64) -> Quote {
+    Quote { price, amount }
+}
+
+fn book(bids: &[(Exchange, f64, f64)], asks: &[(Exchange, f64, f64)]) -> MergedBook {
+    let side = |levels: &[(Exchange, f64, f64)]| {
+        levels
+            .iter()
+            .map(|&(exchange, price, amount)| (Venue::from(exchange), quote(price, amount)))
+            .collect::<Vec<_>>()
+    };
+    MergedBook {
+        spread: asks[0].1 - bids[0].1,
+        bids: side(bids),
+        asks: side(asks),
+        degraded: vec![],
+    }
+}
+
+fn draw(ladder: &Ladder) -> Buffer {
+    let mut terminal = Terminal::new(TestBackend::new(70, 20)).unwrap();
+    terminal.draw(|frame| ladder.render(frame)).unwrap();
+    terminal.backend().buffer().clone()
+}
+
+fn lines(buffer: &Buffer) -> Vec<String> {
+    let area = buffer.area;
+    (0..area.height)
+        .map(|y| {
+            (0..area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// The column and row `text` starts at below the header line.
+fn find(buffer: &Buffer, text: &str) -> Option<(u16, u16)> {
+    lines(buffer)
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find_map(|(y, line)| {
+            let start = line.find(text)?;
+            Some((line[..start].chars().count() as u16, y as u16))
+        })
+}
+
+#[test]
+fn ladders_show_asks_above_bids_with_spread_and_mid() {
+    let mut ladder = Ladder::new(10);
+    assert!(find(&draw(&ladder), "Waiting for summaries").is_some());
+
+    ladder.update(book(
+        &[
+            (Exchange::Binance, 100.0, 1.0),
+            (Exchange::Bitstamp, 99.0, 2.0),
+        ],
+        &[
+            (Exchange::Kraken, 101.0, 3.0),
+            (Exchange::Binance, 102.0, 4.0),
+        ],
+    ));
+    let buffer = draw(&ladder);
+    let header = &lines(&buffer)[0];
+    assert!(header.contains("spread 1.00000000"), "{header}");
+    assert!(header.contains("mid 100.50000000"), "{header}");
+
+    let rows = [
+        "102.00000000",
+        "101.00000000",
+        "spread",
+        "100.00000000",
+        "99.00000000",
+    ]
+    .map(|text| find(&buffer, text).unwrap().1);
+    assert!(rows.windows(2).all(|pair| pair[0] < pair[1]), "{rows:?}");
+
+    let (x, y) = find(&buffer, "Kraken").unwrap();
+    assert_eq!(buffer[(x, y)].fg, venue_color(&Exchange::Kraken.into()));
+    assert_ne!(
+        venue_color(&Exchange::Kraken.into()),
+        venue_color(&Exchange::Bitstamp.into())
+    );
+}
+
+#[test]
+fn changed_levels_are_highlighted() {
+    let mut ladder = Ladder::new(10);
+    ladder.update(book(
+        &[
+            (Exchange::Binance, 100.0, 1.0),
+            (Exchange::Bitstamp, 99.0, 2.0),
+        ],
+        &[(Exchange::Kraken, 101.0, 3.0)],
+    ));
+    let highlighted = |buffer: &Buffer, text: &str| {
+        let (x, y) = find(buffer, text).unwrap();
+        buffer[(x, y)].modifier.contains(Modifier::REVERSED)
+    };
+    // Nothing changed on the first summary.
+    assert!(!highlighted(&draw(&ladder), "100.00000000"));
+
+    ladder.update(book(
+        &[
+            (Exchange::Binance, 100.0, 1.5),
+            (Exchange::Bitstamp, 99.0, 2.0),
+        ],
+        &[(Exchange::Kraken, 101.0, 3.0)],
+    ));
+    let buffer = draw(&ladder);
+    assert!(highlighted(&buffer, "100.00000000"));
+    assert!(!highlighted(&buffer, "99.00000000"));
+    assert!(!highlighted(&buffer, "101.00000000"));
+}
+
+#[test]
+fn keys_change_the_depth_and_pause() {
+    let mut ladder = Ladder::new(1);
+    ladder.update(book(
+        &[
+            (Exchange::Binance, 100.0, 1.0),
+            (Exchange::Bitstamp, 99.0, 2.0),
+        ],
+        &[(Exchange::Kraken, 101.0, 3.0)],
+    ));
+    assert!(find(&draw(&ladder), "99.00000000").is_none());
+
+    assert_eq!(ladder.handle_key(KeyCode::Up.into()), Control::Continue);
+    assert_eq!(ladder.depth(), 2);
+    assert!(find(&draw(&ladder), "99.00000000").is_some());
+    for _ in 0..5 {
+        ladder.handle_key(KeyCode::Char('-').into());
+    }
+    assert_eq!(ladder.depth(), 1);
+    assert_eq!(Ladder::new(1000).depth(), MAX_DEPTH);
+
+    ladder.handle_key(KeyCode::Char(' ').into());
+    assert!(ladder.paused());
+    ladder.update(book(
+        &[(Exchange::Binance, 200.0, 1.0)],
+        &[(Exchange::Kraken, 201.0, 3.0)],
+    ));
+    let buffer = draw(&ladder);
+    assert!(lines(&buffer)[0].contains("PAUSED"));
+    assert!(find(&buffer, "100.00000000").is_some());
+
+    ladder.handle_key(KeyCode::Char('p').into());
+    ladder.update(book(
+        &[(Exchange::Binance, 200.0, 1.0)],
+        &[(Exchange::Kraken, 201.0, 3.0)],
+    ));
+    assert!(find(&draw(&ladder), "200.00000000").is_some());
+
+    assert_eq!(ladder.handle_key(KeyCode::Char('q').into()), Control::Quit);
+    assert_eq!(ladder.handle_key(KeyCode::Esc.into()), Control::Quit);
+    let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+    assert_eq!(ladder.handle_key(ctrl_c), Control::Quit);
+    assert_eq!(
+        ladder.handle_key(KeyCode::Char('c').into()),
+        Control::Continue
+    );
+}
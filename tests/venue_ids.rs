@@ -0,0 +1,138 @@
+mod common;
+
+use std::time::Duration;
+
+use prost::Message;
+use tokio::time::timeout;
+
+use algo_challenge::types::{
+    Empty, Exchange, Level, OrderBook, OrderbookAggregatorClient, Quote, Summary, SummaryRequest,
+};
+use common::start_aggregator;
+
+const BEST_OF: usize = 10;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn book(exchange: Exchange, bid: f64) -> OrderBook {
+    OrderBook {
+        exchange,
+        last_updated: String::new(),
+        bids: vec![Quote {
+            price: bid,
+            amount: 1.0,
+        }],
+        asks: vec![],
+        degraded: false,
+    }
+}
+
+#[test]
+fn builtin_ids_resolve_to_their_names() {
+    assert_eq!(Exchange::from_id(0), Some(Exchange::Binance));
+    assert_eq!(Exchange::from_id(8), Some(Exchange::BinanceUs));
+    assert_eq!(Exchange::from_id(u32::from(u16::MAX) + 1), None);
+    assert!(Exchange::all().starts_with(&[Exchange::Binance, Exchange::Bitstamp]));
+
+    let mut level = Quote {
+        price: 1.0,
+        amount: 1.0,
+    }
+    .to_level(Exchange::Kraken);
+    assert_eq!((level.venue, level.exchange.as_str()), (3, ""));
+    level.name_venue();
+    assert_eq!(level.exchange, "Kraken");
+
+    let mut unknown = Level {
+        venue: u32::from(u16::MAX),
+        ..Level::default()
+    };
+    unknown.name_venue();
+    assert_eq!(unknown.exchange, "");
+}
+
+#[test]
+fn summaries_carry_ids_until_names_are_asked_for() {
+    let summary = Summary::merge(
+        book(Exchange::Bitstamp, 100.0),
+        book(Exchange::Binance, 101.0),
+        BEST_OF,
+    );
+    let ids = summary.bids.iter().map(|l| l.venue).collect::<Vec<_>>();
+    assert_eq!(ids, vec![0, 1]);
+    assert!(summary.bids.iter().all(|l| l.exchange.is_empty()));
+
+    let named = summary.with_venue_names();
+    assert_eq!(named.bids[0].exchange, "Binance");
+    assert_eq!(named.bids[1].exchange, "Bitstamp");
+}
+
+#[test]
+fn empty_requests_decode_as_ids_only() {
+    let request = SummaryRequest::decode(Empty {}.encode_to_vec().as_slice()).unwrap();
+    assert!(!request.venue_names);
+}
+
+#[tokio::test]
+async fn grpc_clients_choose_between_ids_and_names() {
+    let venues = [Exchange::Bitstamp, Exchange::Binance];
+    let (tx, url) = start_aggregator(&venues, BEST_OF).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+    let mut ids = client
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let mut names = client
+        .book_summary(SummaryRequest {
+            venue_names: true,
+            ..SummaryRequest::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    tx.send(book(Exchange::Bitstamp, 100.0)).unwrap();
+    tx.send(book(Exchange::Binance, 101.0)).unwrap();
+
+    let summary = timeout(WAIT, ids.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let levels = summary
+        .bids
+        .iter()
+        .map(|l| (l.venue, l.exchange.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(levels, vec![(0, ""), (1, "")]);
+
+    let summary = timeout(WAIT, names.message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let levels = summary
+        .bids
+        .iter()
+        .map(|l| (l.venue, l.exchange.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(levels, vec![(0, "Binance"), (1, "Bitstamp")]);
+}
+
+#[tokio::test]
+async fn venues_are_listed_by_id() {
+    let adapter = Exchange::intern("Venue-Ids-Adapter");
+    let (_tx, url) = start_aggregator(&[Exchange::Binance], BEST_OF).await;
+    let mut client = OrderbookAggregatorClient::connect(url).await.unwrap();
+
+    let venues = client.venues(Empty {}).await.unwrap().into_inner().venues;
+    let name = |id: u32| {
+        venues
+            .iter()
+            .find(|venue| venue.id == id)
+            .map(|venue| venue.name.as_str())
+    };
+    assert_eq!(name(0), Some("Binance"));
+    assert_eq!(name(5), Some("Bybit"));
+    assert_eq!(name(adapter.id().into()), Some("Venue-Ids-Adapter"));
+}